@@ -0,0 +1,10 @@
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    let rev = Command::new("git").args(["rev-parse", "--short", "HEAD"]).output();
+    if let Ok(out) = rev && out.status.success() {
+        println!("cargo:rustc-env=RUSTYADV_GIT_REV={}", String::from_utf8_lossy(&out.stdout).trim());
+    }
+}
@@ -0,0 +1,1163 @@
+//! Dashboard state fed by sensor updates, independent of how it is drawn.
+use std::io::{self, Write};
+use std::fs::File;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use chrono::{DateTime, SecondsFormat, Utc};
+use clap::ValueEnum;
+use serde::Serialize;
+use crate::{dotfile, lock, NO_RESPONSE, SensorUpdate};
+use crate::config::ConfigSource;
+use crate::dtc::{CanId, DtcCode, FaultState, FreezeFrame, PidValue, Severity, SeverityMap};
+use crate::sensors::{is_enabled, Position, SensorSwitches, SentinelComponent, ADAS_TREND_FLOOR};
+use crate::storage::{AckKey, LatencyHistogram, Session};
+
+pub const DEFAULT_LOG_CAPACITY: usize = 500;
+// Healthy ADAS modules report 95-99%, so a tracking line only reads as a warning once it falls under
+// the floor the degradation trend watches.
+pub const LOW_CONFIDENCE: u32 = ADAS_TREND_FLOOR;
+pub const FUSION_DISAGREEMENT: u32 = 40;
+pub const DTC_RATE_WINDOW: Duration = Duration::from_secs(60);
+pub const BUS_LOAD_WINDOW: usize = 60;
+pub const DEFAULT_LIVENESS_POLLS: u32 = 3;
+pub const DEFAULT_DEDUP_WINDOW_SECS: u64 = 5;
+pub const DEFAULT_FPS: u32 = 10;
+// Time on the wire for one frame at 500 kbit/s, charged once per frame that wins arbitration first.
+pub const CAN_FRAME_TIME: Duration = Duration::from_micros(250);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum PanelLayout {
+    #[default]
+    Horizontal,
+    Vertical,
+    Grid,
+}
+
+impl PanelLayout {
+    pub fn next(self) -> Self {
+        match self {
+            PanelLayout::Horizontal => PanelLayout::Vertical,
+            PanelLayout::Vertical => PanelLayout::Grid,
+            PanelLayout::Grid => PanelLayout::Horizontal,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LogRecord {
+    pub can_id: String,
+    pub message: String,
+    pub is_dtc: bool,
+    pub timestamp: String,
+    #[serde(flatten)]
+    pub position: Option<Position>,
+}
+
+impl LogRecord {
+    pub fn new(id: CanId, message: &str, is_dtc: bool, at: DateTime<Utc>) -> Self {
+        Self {
+            can_id: format!("{}", id),
+            message: message.to_string(),
+            is_dtc,
+            timestamp: at.to_rfc3339_opts(SecondsFormat::Millis, true),
+            position: None,
+        }
+    }
+
+    pub fn with_position(mut self, position: Option<Position>) -> Self {
+        self.position = position;
+        self
+    }
+}
+
+pub struct EventLog {
+    pub format: LogFormat,
+    pub out: Box<dyn Write>,
+    pub position: Option<Position>,
+}
+
+impl EventLog {
+    pub fn open(format: LogFormat, path: Option<&Path>) -> io::Result<Self> {
+        let out: Box<dyn Write> = match path {
+            Some(path) => Box::new(io::BufWriter::new(File::options().create(true).append(true).open(path)?)),
+            None => Box::new(io::stdout()),
+        };
+        Ok(Self { format, out, position: None })
+    }
+
+    pub fn write_session(&mut self, session: &Session) -> io::Result<()> {
+        if self.format != LogFormat::Json { return Ok(()); }
+        serde_json::to_writer(&mut self.out, &serde_json::json!({ "session": session }))?;
+        writeln!(self.out)
+    }
+
+    pub fn record(&mut self, update: &SensorUpdate) -> io::Result<()> {
+        let SensorUpdate::Status { id, status, is_dtc, at, position, .. } = update else { return Ok(()) };
+        self.position = position.or(self.position);
+        match self.format {
+            LogFormat::Json => {
+                let record = LogRecord::new(*id, status, *is_dtc, *at).with_position(self.position);
+                serde_json::to_writer(&mut self.out, &record)?;
+                writeln!(self.out)
+            }
+            LogFormat::Text if *is_dtc => {
+                let local = at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S%.3f");
+                writeln!(self.out, "{} [CAN ID {}] {}", local, id, status)
+            }
+            LogFormat::Text => Ok(()),
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+pub struct LogEntry {
+    pub can_id: Option<CanId>,
+    pub text: String,
+    pub fault: FaultState,
+    pub freeze: Option<FreezeFrame>,
+    // One per blackbox row the line stands for, so acknowledging a collapsed line acknowledges them all.
+    pub ack_keys: Vec<AckKey>,
+    pub acknowledged: bool,
+    pub count: u32,
+}
+
+impl LogEntry {
+    pub fn display_text(&self) -> String {
+        match self.count {
+            0 | 1 => self.text.clone(),
+            n => format!("{} \u{d7}{}", self.text, n),
+        }
+    }
+
+    pub fn repeats(&self, other: &LogEntry) -> bool {
+        match (DtcCode::parse(&self.text), DtcCode::parse(&other.text)) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.text == other.text,
+        }
+    }
+
+    pub fn unacknowledged_dtc(&self) -> bool {
+        !self.ack_keys.is_empty() && !self.acknowledged
+    }
+
+    pub fn severity(&self, severities: &SeverityMap) -> (Option<Severity>, bool) {
+        let severity = severities.classify(&self.text);
+        (severity, severity.is_some() && self.fault == FaultState::Confirmed)
+    }
+}
+
+// Per-second frame counts for the last BUS_LOAD_WINDOW seconds; the last bucket is the second in progress.
+#[derive(Default)]
+pub struct BusLoad {
+    pub buckets: VecDeque<u64>,
+    pub second: u64,
+}
+
+impl BusLoad {
+    pub fn advance(&mut self, second: u64) {
+        if self.buckets.is_empty() {
+            self.second = second;
+            self.buckets.push_back(0);
+        }
+        while self.second < second {
+            self.second += 1;
+            self.buckets.push_back(0);
+            if self.buckets.len() > BUS_LOAD_WINDOW + 1 {
+                self.buckets.pop_front();
+            }
+        }
+    }
+
+    pub fn record(&mut self, second: u64) {
+        self.advance(second);
+        if let Some(current) = self.buckets.back_mut() {
+            *current += 1;
+        }
+    }
+
+    pub fn frames_per_second(&self) -> u64 {
+        self.buckets.iter().rev().nth(1).copied().unwrap_or(0)
+    }
+
+    pub fn completed(&self) -> Vec<u64> {
+        self.buckets.iter().take(self.buckets.len().saturating_sub(1)).copied().collect()
+    }
+}
+
+// Session-wide DTC totals for the exit report; unlike SensorStats these survive 'c'.
+#[derive(Default)]
+pub struct SessionSummary {
+    pub per_sensor: HashMap<CanId, usize>,
+    pub per_code: HashMap<DtcCode, usize>,
+    pub first: Option<DateTime<Utc>>,
+    pub last: Option<DateTime<Utc>>,
+}
+
+impl SessionSummary {
+    pub fn record(&mut self, id: CanId, code: Option<DtcCode>, at: DateTime<Utc>) {
+        *self.per_sensor.entry(id).or_default() += 1;
+        if let Some(code) = code {
+            *self.per_code.entry(code).or_default() += 1;
+        }
+        self.first = Some(self.first.map_or(at, |first| first.min(at)));
+        self.last = Some(self.last.map_or(at, |last| last.max(at)));
+    }
+
+    pub fn most_frequent(&self) -> Option<(DtcCode, usize)> {
+        self.per_code.iter().map(|(&code, &n)| (code, n)).max_by_key(|&(code, n)| (n, std::cmp::Reverse(code.as_str())))
+    }
+}
+
+// Fusion layer over the ADAS modules' status lines: each confident module counts in proportion to
+// its own confidence, so a blind one drops out of the fused figure instead of dragging it to zero.
+#[derive(Default)]
+pub struct PerceptionFusion {
+    pub inputs: BTreeMap<CanId, u32>,
+    pub modules: usize,
+    pub disagreeing: bool,
+}
+
+pub enum FusionStatus {
+    Waiting { reporting: usize, modules: usize },
+    Healthy { confidence: u32, modules: usize },
+    Disagreement { blind: CanId, blind_confidence: u32, confident: CanId, confidence: u32, fused: u32 },
+}
+
+impl PerceptionFusion {
+    // Only readings with a confidence feed the fusion; initializing or silent modules are left out.
+    pub fn observe(&mut self, id: CanId, status: &str) {
+        let confidence = match DtcCode::parse(status) {
+            Some(DtcCode::SensorBlind) => Some(0),
+            _ => confidence_of(status),
+        };
+        match confidence {
+            Some(confidence) => self.inputs.insert(id, confidence),
+            None => self.inputs.remove(&id),
+        };
+    }
+
+    pub fn forget(&mut self, id: CanId) {
+        self.inputs.remove(&id);
+    }
+
+    pub fn fused(&self) -> u32 {
+        let weight: u32 = self.inputs.values().sum();
+        match weight {
+            0 => 0,
+            _ => self.inputs.values().map(|c| c * c).sum::<u32>() / weight,
+        }
+    }
+
+    pub fn status(&self) -> FusionStatus {
+        let lowest = self.inputs.iter().min_by_key(|&(_, c)| *c);
+        let highest = self.inputs.iter().max_by_key(|&(_, c)| *c);
+        let (Some((&blind, &blind_confidence)), Some((&confident, &confidence))) = (lowest, highest) else {
+            return FusionStatus::Waiting { reporting: 0, modules: self.modules };
+        };
+        if self.inputs.len() < 2 {
+            return FusionStatus::Waiting { reporting: self.inputs.len(), modules: self.modules };
+        }
+        if confidence - blind_confidence >= FUSION_DISAGREEMENT {
+            FusionStatus::Disagreement { blind, blind_confidence, confident, confidence, fused: self.fused() }
+        } else {
+            FusionStatus::Healthy { confidence: self.fused(), modules: self.inputs.len() }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct SensorStats {
+    pub dtc_count: usize,
+    pub last_dtc: Option<DtcCode>,
+}
+
+pub struct AppState {
+    pub logs: VecDeque<LogEntry>,
+    pub log_capacity: usize,
+    pub dedup_window: Duration,
+    pub frame_interval: Duration,
+    // Set whenever something on screen may have changed since the last frame.
+    pub dirty: bool,
+    pub log_scroll: usize,
+    pub log_page: usize,
+    pub status_area: ListArea,
+    pub status_offset: usize,
+    pub layout: PanelLayout,
+    pub sort_by_severity: bool,
+    pub kinds: HashMap<CanId, &'static str>,
+    pub names: HashMap<CanId, String>,
+    pub config_source: Option<ConfigSource>,
+    pub dotfile: Option<dotfile::Dotfile>,
+    pub icons: bool,
+    pub theme: ThemeName,
+    pub severities: SeverityMap,
+    pub command_input: Option<String>,
+    pub marker_input: Option<(DateTime<Utc>, String)>,
+    pub console: Option<(String, Vec<String>)>,
+    pub show_details: bool,
+    pub show_histogram: bool,
+    pub show_chart: bool,
+    pub show_help: bool,
+    pub log_filter: Option<CanId>,
+    pub buses: HashMap<CanId, u8>,
+    pub bus_filter: Option<u8>,
+    pub filter_input: Option<String>,
+    pub searching: bool,
+    pub search_query: String,
+    pub search_match: Option<usize>,
+    pub history: Option<Vec<LogRecord>>,
+    pub pid_response: Option<(CanId, Vec<PidValue>)>,
+    pub spark_sensor: usize,
+    pub selected: usize,
+    pub sensor_status: Vec<(CanId, String)>,
+    pub sensor_stats: HashMap<CanId, SensorStats>,
+    pub fault_states: HashMap<CanId, FaultState>,
+    pub paused: bool,
+    pub replay_complete: bool,
+    pub held_status: Vec<(CanId, String)>,
+    pub held_logs: Vec<LogEntry>,
+    pub start: Instant,
+    pub recent_dtcs: VecDeque<Instant>,
+    pub summary: SessionSummary,
+    pub fusion: PerceptionFusion,
+    pub arbitration_losses: HashMap<CanId, u64>,
+    // How long each sensor's latest frame waited on the wire behind lower IDs in its tick.
+    pub arbitration_delay: HashMap<CanId, Duration>,
+    pub latency: Arc<Mutex<LatencyHistogram>>,
+    pub db_dropped: Arc<AtomicU64>,
+    pub enabled: SensorSwitches,
+    pub position: Option<Position>,
+    pub bus_load: BusLoad,
+    pub bus_loads: BTreeMap<u8, BusLoad>,
+    pub liveness: HashMap<CanId, Duration>,
+    pub last_seen: HashMap<CanId, Instant>,
+    pub silent: HashSet<CanId>,
+}
+
+impl AppState {
+    pub fn new(log_capacity: usize, ids: &[CanId]) -> Self {
+        Self {
+            logs: VecDeque::with_capacity(log_capacity),
+            log_capacity,
+            dedup_window: Duration::from_secs(DEFAULT_DEDUP_WINDOW_SECS),
+            frame_interval: Duration::from_secs(1) / DEFAULT_FPS,
+            dirty: true,
+            log_scroll: 0,
+            log_page: 1,
+            status_area: ListArea::default(),
+            status_offset: 0,
+            layout: PanelLayout::default(),
+            sort_by_severity: false,
+            kinds: HashMap::new(),
+            names: HashMap::new(),
+            config_source: None,
+            dotfile: None,
+            icons: false,
+            theme: ThemeName::default(),
+            severities: SeverityMap::default(),
+            command_input: None,
+            marker_input: None,
+            console: None,
+            show_details: false,
+            show_histogram: false,
+            show_chart: false,
+            show_help: false,
+            log_filter: None,
+            buses: HashMap::new(),
+            bus_filter: None,
+            filter_input: None,
+            searching: false,
+            search_query: String::new(),
+            search_match: None,
+            history: None,
+            pid_response: None,
+            spark_sensor: 0,
+            selected: 0,
+            sensor_status: ids.iter().map(|&id| (id, "Initializing...".to_string())).collect(),
+            sensor_stats: HashMap::new(),
+            fault_states: HashMap::new(),
+            paused: false,
+            replay_complete: false,
+            held_status: Vec::new(),
+            held_logs: Vec::new(),
+            start: Instant::now(),
+            recent_dtcs: VecDeque::new(),
+            summary: SessionSummary::default(),
+            fusion: PerceptionFusion::default(),
+            arbitration_losses: HashMap::new(),
+            arbitration_delay: HashMap::new(),
+            latency: Arc::default(),
+            db_dropped: Arc::default(),
+            enabled: Arc::default(),
+            position: None,
+            liveness: HashMap::new(),
+            last_seen: HashMap::new(),
+            silent: HashSet::new(),
+            bus_load: BusLoad::default(),
+            bus_loads: BTreeMap::new(),
+        }
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        if !self.paused {
+            for (id, status) in std::mem::take(&mut self.held_status) {
+                self.update_sensor(id, status);
+            }
+            for entry in std::mem::take(&mut self.held_logs) {
+                self.add_log(entry);
+            }
+        }
+    }
+
+    // Entries that arrive while paused are held back, not dropped, and land in order on resume.
+    pub fn add_log(&mut self, entry: LogEntry) {
+        if self.paused {
+            self.held_logs.push(entry);
+            return;
+        }
+        if self.collapse_repeat(&entry) { return; }
+        self.push_log(entry);
+    }
+
+    // Folds a DTC into the sensor's newest log line when it repeats the same code within the window.
+    // Only the display collapses; the blackbox still gets a row per occurrence.
+    pub fn collapse_repeat(&mut self, entry: &LogEntry) -> bool {
+        let (Some(id), Some(key)) = (entry.can_id, entry.ack_keys.last()) else { return false };
+        if self.dedup_window.is_zero() { return false; }
+        let window = self.dedup_window;
+        let Some(prev) = self.logs.iter_mut().rev().find(|e| e.can_id == Some(id)) else { return false };
+        let Some(prev_key) = prev.ack_keys.last() else { return false };
+        if !prev.repeats(entry) || !(key.at - prev_key.at).to_std().is_ok_and(|gap| gap <= window) {
+            return false;
+        }
+        prev.count += 1;
+        prev.text = entry.text.clone();
+        prev.fault = entry.fault;
+        prev.ack_keys.extend(entry.ack_keys.iter().cloned());
+        prev.acknowledged = entry.acknowledged;
+        if entry.freeze.is_some() {
+            prev.freeze = entry.freeze.clone();
+        }
+        true
+    }
+
+    pub fn notify(&mut self, msg: String) {
+        self.push_log(LogEntry { can_id: None, text: msg, fault: FaultState::Ok, freeze: None, ack_keys: Vec::new(), acknowledged: false, count: 1 });
+    }
+
+    pub fn push_log(&mut self, entry: LogEntry) {
+        let visible = self.shows(&entry);
+        self.logs.push_back(entry);
+        if self.logs.len() > self.log_capacity {
+            self.logs.pop_front();
+        }
+        if visible && self.log_scroll > 0 {
+            self.log_scroll += 1;
+        }
+        self.log_scroll = self.log_scroll.min(self.max_log_scroll());
+    }
+
+    pub fn clear_dtcs(&mut self) {
+        self.logs.clear();
+        self.held_logs.clear();
+        self.log_scroll = 0;
+        self.sensor_stats.clear();
+        self.fault_states.clear();
+        self.notify("[CLEAR] Stored DTCs cleared".to_string());
+    }
+
+    pub fn record_dtc_event(&mut self, at: Instant) {
+        while self.recent_dtcs.front().is_some_and(|t| at.duration_since(*t) > DTC_RATE_WINDOW) {
+            self.recent_dtcs.pop_front();
+        }
+        self.recent_dtcs.push_back(at);
+    }
+
+    pub fn dtcs_last_minute(&self) -> usize {
+        self.recent_dtcs.iter().filter(|t| t.elapsed() <= DTC_RATE_WINDOW).count()
+    }
+
+    pub fn uptime(&self) -> String {
+        let secs = self.start.elapsed().as_secs();
+        format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.sensor_status.is_empty() {
+            self.selected = (self.selected + 1) % self.sensor_status.len();
+        }
+    }
+
+    pub fn select_prev(&mut self) {
+        if !self.sensor_status.is_empty() {
+            self.selected = (self.selected + self.sensor_status.len() - 1) % self.sensor_status.len();
+        }
+    }
+
+    pub fn click(&mut self, column: u16, row: u16) {
+        let area = self.status_area;
+        let inside = column > area.x && column < area.x.saturating_add(area.width).saturating_sub(1)
+            && row > area.y && row < area.y.saturating_add(area.height).saturating_sub(1);
+        if !inside { return; }
+        let row = self.status_offset + (row - area.y - 1) as usize;
+        if row < self.sensor_status.len() {
+            self.selected = row;
+        }
+    }
+
+    // Cached areas belong to the old size until the next draw, so drop them rather than hit-test against them.
+    pub fn handle_resize(&mut self, height: u16) {
+        self.status_area = ListArea::default();
+        self.status_offset = 0;
+        self.selected = self.selected.min(self.sensor_status.len().saturating_sub(1));
+        self.log_page = self.log_page.min(height.saturating_sub(2).max(1) as usize);
+        self.log_scroll = self.log_scroll.min(self.max_log_scroll());
+    }
+
+    // Replays have no live sensors to ask, so their rows go untagged.
+    pub fn kind_prefix(&self, id: CanId) -> String {
+        match self.kinds.get(&id) {
+            Some(kind) if self.icons => format!("{} ", kind_icon(kind)),
+            Some(kind) => format!("[{}] ", kind),
+            None => String::new(),
+        }
+    }
+
+    pub fn fusion_line(&self) -> Option<(String, Tone)> {
+        if self.fusion.modules < 2 { return None; }
+        Some(match self.fusion.status() {
+            FusionStatus::Waiting { reporting, modules } => (
+                format!("[FUSION] Perception health: waiting for ADAS modules ({} of {} reporting)", reporting, modules),
+                Tone::Warning,
+            ),
+            FusionStatus::Healthy { confidence, modules } => (
+                format!("[FUSION] Perception health: {}% across {} ADAS modules", confidence, modules),
+                if confidence < LOW_CONFIDENCE { Tone::Warning } else { Tone::Ok },
+            ),
+            FusionStatus::Disagreement { blind, blind_confidence, confident, confidence, fused } => (
+                format!(
+                    "[FUSION] Sensor Disagreement: {} {}% vs {} {}%, fused {}%",
+                    self.short_label(blind), blind_confidence, self.short_label(confident), confidence, fused,
+                ),
+                Tone::Dtc,
+            ),
+        })
+    }
+
+    pub fn observe_fusion(&mut self, id: CanId, status: &str) {
+        if self.kinds.get(&id) != Some(&"ADAS") { return; }
+        self.fusion.observe(id, status);
+        let disagreeing = matches!(self.fusion.status(), FusionStatus::Disagreement { .. });
+        if disagreeing && !self.fusion.disagreeing && let Some((line, _)) = self.fusion_line() {
+            self.notify(line);
+        }
+        self.fusion.disagreeing = disagreeing;
+    }
+
+    pub fn short_label(&self, id: CanId) -> String {
+        self.names.get(&id).cloned().unwrap_or_else(|| id.to_string())
+    }
+
+    pub fn label(&self, id: CanId) -> String {
+        match self.names.get(&id) {
+            Some(name) => format!("{} ({})", id, name),
+            None => id.to_string(),
+        }
+    }
+
+    pub fn selected_id(&self) -> Option<CanId> {
+        self.sensor_status.get(self.selected).map(|(id, _)| *id)
+    }
+
+    pub fn is_enabled(&self, id: CanId) -> bool {
+        is_enabled(&self.enabled, id)
+    }
+
+    pub fn toggle_selected_sensor(&mut self) {
+        let Some(id) = self.selected_id() else { return };
+        let Some(flag) = self.enabled.get(&id) else {
+            self.notify(format!("[SENSOR] CAN ID {} has no worker to pause", self.label(id)));
+            return;
+        };
+        let enabled = !flag.fetch_xor(true, Ordering::Relaxed);
+        // The liveness timer starts over so a re-enabled sensor gets its full timeout before U0100.
+        self.last_seen.insert(id, Instant::now());
+        self.notify(format!("[SENSOR] CAN ID {} {}", self.label(id), if enabled { "enabled" } else { "disabled" }));
+    }
+
+    pub fn bus(&self, id: CanId) -> u8 {
+        self.buses.get(&id).copied().unwrap_or_default()
+    }
+
+    pub fn bus_numbers(&self) -> BTreeSet<u8> {
+        self.sensor_status.iter().map(|(id, _)| self.bus(*id)).collect()
+    }
+
+    pub fn multi_bus(&self) -> bool {
+        self.bus_numbers().len() > 1
+    }
+
+    pub fn bus_prefix(&self, id: CanId) -> String {
+        if self.multi_bus() { format!("B{} ", self.bus(id)) } else { String::new() }
+    }
+
+    pub fn on_filtered_bus(&self, id: CanId) -> bool {
+        self.bus_filter.is_none_or(|bus| self.bus(id) == bus)
+    }
+
+    // All buses, then each bus in turn, then all again.
+    pub fn cycle_bus_filter(&mut self) {
+        let buses = self.bus_numbers();
+        if buses.len() < 2 {
+            self.bus_filter = None;
+            self.notify("[BUS] all sensors are on one bus".to_string());
+            return;
+        }
+        self.bus_filter = match self.bus_filter {
+            None => buses.first().copied(),
+            Some(current) => buses.range(current + 1..).next().copied(),
+        };
+        self.log_scroll = 0;
+        self.search_match = None;
+    }
+
+    pub fn shows(&self, entry: &LogEntry) -> bool {
+        self.log_filter.is_none_or(|id| entry.can_id == Some(id))
+            && entry.can_id.is_none_or(|id| self.on_filtered_bus(id))
+    }
+
+    // Indices into `logs` in display order, oldest at the top. Sorting by severity is stable, so arrival order
+    // still decides within a severity, and the most severe entries end up at the bottom where the view follows.
+    pub fn visible_indices(&self) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..self.logs.len()).filter(|&i| self.shows(&self.logs[i])).collect();
+        if self.sort_by_severity {
+            indices.sort_by_key(|&i| self.logs[i].severity(&self.severities));
+        }
+        indices
+    }
+
+    pub fn visible_logs(&self) -> impl Iterator<Item = &LogEntry> {
+        self.visible_indices().into_iter().map(|i| &self.logs[i])
+    }
+
+    pub fn toggle_sort(&mut self) {
+        self.sort_by_severity = !self.sort_by_severity;
+        self.log_scroll = 0;
+        self.search_match = None;
+    }
+
+    pub fn unacknowledged_dtcs(&self) -> usize {
+        self.visible_logs().filter(|entry| entry.unacknowledged_dtc()).count()
+    }
+
+    // Acknowledges the search match if there is one, otherwise the newest unacknowledged DTC at or above the
+    // bottom of the log view, so repeated presses walk back through the log.
+    pub fn acknowledge_selected(&mut self) -> Option<Vec<AckKey>> {
+        let visible = self.visible_indices();
+        let bottom = visible.len().checked_sub(self.log_scroll + 1)?;
+        let searched = self.search_match.and_then(|i| visible.get(i)).copied()
+            .filter(|&i| self.logs[i].unacknowledged_dtc());
+        let index = searched.or_else(|| visible[..=bottom].iter().rev().copied().find(|&i| self.logs[i].unacknowledged_dtc()))?;
+        let entry = &mut self.logs[index];
+        entry.acknowledged = true;
+        Some(entry.ack_keys.clone())
+    }
+
+    pub fn max_log_scroll(&self) -> usize {
+        self.visible_logs().count().saturating_sub(self.log_page)
+    }
+
+    pub fn apply_filter_input(&mut self) {
+        let Some(input) = self.filter_input.take() else { return };
+        match input.parse::<CanId>() {
+            Ok(id) => {
+                self.log_filter = Some(id);
+                self.log_scroll = 0;
+            }
+            Err(e) => self.notify(format!("[FILTER] {}", e)),
+        }
+    }
+
+    pub fn search_matches(&self) -> Vec<usize> {
+        if self.search_query.is_empty() { return Vec::new(); }
+        let query = self.search_query.to_ascii_lowercase();
+        self.visible_logs().enumerate()
+            .filter(|(_, entry)| entry.text.to_ascii_lowercase().contains(&query))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    pub fn next_search_match(&mut self) {
+        let matches = self.search_matches();
+        let next = match self.search_match {
+            Some(current) => matches.iter().rev().find(|&&i| i < current).or(matches.last()),
+            None => matches.last(),
+        };
+        self.search_match = next.copied();
+        if let Some(i) = self.search_match {
+            let visible = self.visible_logs().count();
+            self.log_scroll = (visible - 1 - i).min(self.max_log_scroll());
+        }
+    }
+
+    pub fn close_search(&mut self) {
+        self.searching = false;
+        self.search_query.clear();
+        self.search_match = None;
+    }
+
+    pub fn clear_filter(&mut self) {
+        self.log_filter = None;
+        self.log_scroll = 0;
+    }
+
+    pub fn scroll_logs_up(&mut self, n: usize) {
+        self.log_scroll = (self.log_scroll + n).min(self.max_log_scroll());
+    }
+
+    pub fn scroll_logs_down(&mut self, n: usize) {
+        self.log_scroll = self.log_scroll.saturating_sub(n);
+    }
+
+    // Frames that reach the dashboard in the same tick contend for their bus: on each bus they go out
+    // lowest CAN ID first, each waits one frame time per frame queued ahead of it on that bus, and every
+    // sender but the bus's lowest ID counts a lost arbitration. The wait goes in `arbitration_delay`
+    // rather than onto `at`, which must stay the time the blackbox row was stamped with so the DTC can
+    // still be acknowledged. Notices keep their order after the frames.
+    pub fn arbitrate(&mut self, updates: Vec<SensorUpdate>) -> Vec<SensorUpdate> {
+        let (mut frames, notices): (Vec<_>, Vec<_>) = updates.into_iter().partition(|u| matches!(u, SensorUpdate::Status { .. }));
+        frames.sort_by_key(|u| match u {
+            SensorUpdate::Status { id, .. } => Some((self.bus(*id), *id)),
+            _ => None,
+        });
+        let mut queued: HashMap<u8, (CanId, u32)> = HashMap::new();
+        for frame in frames.iter_mut() {
+            if let SensorUpdate::Status { id, .. } = frame {
+                let (winner, ahead) = queued.entry(self.bus(*id)).or_insert((*id, 0));
+                if *winner != *id {
+                    *self.arbitration_losses.entry(*id).or_default() += 1;
+                }
+                self.arbitration_delay.insert(*id, CAN_FRAME_TIME * *ahead);
+                *ahead += 1;
+            }
+        }
+        frames.extend(notices);
+        frames
+    }
+
+    pub fn apply(&mut self, update: SensorUpdate) {
+        match update {
+            SensorUpdate::Status { id, status, is_dtc, fault, at, freeze, acknowledged, position } => {
+                self.last_seen.insert(id, Instant::now());
+                if self.silent.remove(&id) {
+                    self.notify(format!("[LIVENESS] CAN ID {} is responding again", self.label(id)));
+                }
+                self.fault_states.insert(id, fault);
+                self.position = position.or(self.position);
+                if is_dtc {
+                    let stats = self.sensor_stats.entry(id).or_default();
+                    stats.dtc_count += 1;
+                    stats.last_dtc = DtcCode::parse(&status).or(stats.last_dtc);
+                    self.summary.record(id, DtcCode::parse(&status), at);
+                    self.record_dtc_event(Instant::now());
+                    self.add_log(LogEntry {
+                        can_id: Some(id),
+                        text: format!("[CAN ID {}] {}", self.label(id), status),
+                        fault,
+                        freeze,
+                        ack_keys: vec![AckKey { sensor_id: id, message: status.clone(), at }],
+                        acknowledged,
+                        count: 1,
+                    });
+                }
+                self.observe_fusion(id, &status);
+                self.update_sensor(id, status);
+            }
+            SensorUpdate::Notice(msg) => self.notify(msg),
+            SensorUpdate::ReplayComplete => self.replay_complete = true,
+        }
+    }
+
+    // Returns the U0100 messages raised this tick so the caller can record them in the blackbox.
+    pub fn check_liveness(&mut self, now: Instant) -> Vec<(CanId, String, DateTime<Utc>)> {
+        let expired: Vec<(CanId, Duration)> = self.liveness.iter()
+            .filter(|(id, _)| !self.silent.contains(id) && self.is_enabled(**id))
+            .map(|(&id, &timeout)| (id, timeout, now.duration_since(self.last_seen.get(&id).copied().unwrap_or(self.start))))
+            .filter(|&(_, timeout, silent_for)| silent_for > timeout)
+            .map(|(id, _, silent_for)| (id, silent_for))
+            .collect();
+        let mut raised = Vec::new();
+        for (id, silent_for) in expired {
+            let message = format!(
+                "DTC {}: Lost Communication With ECU (no response for {:.1}s)",
+                DtcCode::LostCommunication.as_str(), silent_for.as_secs_f64(),
+            );
+            let at = Utc::now();
+            self.silent.insert(id);
+            let stats = self.sensor_stats.entry(id).or_default();
+            stats.dtc_count += 1;
+            stats.last_dtc = Some(DtcCode::LostCommunication);
+            self.summary.record(id, Some(DtcCode::LostCommunication), at);
+            self.record_dtc_event(now);
+            self.add_log(LogEntry {
+                can_id: Some(id),
+                text: format!("[CAN ID {}] {}", self.label(id), message),
+                fault: FaultState::Confirmed,
+                freeze: None,
+                ack_keys: vec![AckKey { sensor_id: id, message: message.clone(), at }],
+                acknowledged: false,
+                count: 1,
+            });
+            self.fusion.forget(id);
+            self.update_sensor(id, NO_RESPONSE.to_string());
+            raised.push((id, message, at));
+        }
+        raised
+    }
+
+    pub fn update_sensor(&mut self, id: CanId, status: String) {
+        let second = self.start.elapsed().as_secs();
+        self.bus_load.record(second);
+        self.bus_loads.entry(self.bus(id)).or_default().record(second);
+        if self.paused {
+            match self.held_status.iter_mut().find(|(sid, _)| *sid == id) {
+                Some(s) => s.1 = status,
+                None => self.held_status.push((id, status)),
+            }
+            return;
+        }
+        if let Some(s) = self.sensor_status.iter_mut().find(|(sid, _)| *sid == id) {
+            s.1 = status;
+        }
+    }
+}
+
+pub fn confidence_of(msg: &str) -> Option<u32> {
+    let start = msg.find("Confidence ").or_else(|| msg.find("confidence "))?;
+    let rest = &msg[start + "Confidence ".len()..];
+    rest[..rest.find('%')?].trim().parse().ok()
+}
+
+// The ECU list as last drawn, in terminal cells and including its border, for hit-testing clicks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ListArea {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+// How alarming a derived status line is; the theme turns it into a style.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tone {
+    Ok,
+    Warning,
+    Dtc,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ThemeName {
+    #[default]
+    Dark,
+    Light,
+    HighContrast,
+    Colorblind,
+}
+
+pub fn scale_to_sparkline(history: &[f64]) -> Vec<u64> {
+    let min = history.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = history.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let span = max - min;
+    history.iter()
+        .map(|v| if span > 0.0 { 1 + ((v - min) / span * 99.0).round() as u64 } else { 50 })
+        .collect()
+}
+
+pub fn kind_icon(kind: &str) -> &'static str {
+    match kind {
+        "BMS" => "🔋",
+        "ADAS" => "📡",
+        "TPMS" => "🛞",
+        "ABS" => "🛑",
+        "GPS" => "🧭",
+        _ => "•",
+    }
+}
+
+pub fn write_snapshot(app: &AppState, sensors: &[Box<dyn SentinelComponent>], out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "rustyadv dashboard snapshot {}", timestamp_now())?;
+    writeln!(out, "Uptime {}   {} DTC/min   Bus load {} frames/s", app.uptime(), app.dtcs_last_minute(), app.bus_load.frames_per_second())?;
+    if app.multi_bus() {
+        let per_bus: Vec<String> = app.bus_loads.iter()
+            .map(|(bus, load)| format!("bus {} {} frames/s", bus, load.frames_per_second()))
+            .collect();
+        writeln!(out, "Per bus: {}", per_bus.join(", "))?;
+    }
+    writeln!(out, "DTC detection-to-commit latency: {}", lock(&app.latency).summary())?;
+    if let Some((line, _)) = app.fusion_line() {
+        writeln!(out, "{}", line)?;
+    }
+    if let Some(position) = app.position {
+        writeln!(out, "Position {}", position)?;
+    }
+    let dropped = app.db_dropped.load(Ordering::Relaxed);
+    if dropped > 0 {
+        writeln!(out, "Blackbox writes dropped: {}", dropped)?;
+    }
+    writeln!(
+        out,
+        "View: layout {:?}, {} order, filter {}, bus {}, search {}, {}{}",
+        app.layout,
+        if app.sort_by_severity { "severity" } else { "arrival" },
+        app.log_filter.map_or_else(|| "none".to_string(), |id| id.to_string()),
+        app.bus_filter.map_or_else(|| "all".to_string(), |bus| bus.to_string()),
+        if app.search_query.is_empty() { "none".to_string() } else { format!("\"{}\"", app.search_query) },
+        if app.paused { "paused" } else { "live" },
+        if app.replay_complete { ", replay complete" } else { "" },
+    )?;
+
+    writeln!(out, "\nECU status")?;
+    for (i, (id, status)) in app.sensor_status.iter().enumerate() {
+        let fault = app.fault_states.get(id).copied().unwrap_or(FaultState::Ok);
+        let stats = app.sensor_stats.get(id);
+        let health = sensors.iter().find(|s| s.get_id() == *id).map_or_else(|| "-".to_string(), |s| s.health_score().to_string());
+        writeln!(
+            out,
+            "{} {}{}CAN ID {}: {}{} [fault {}, health {}, {} DTCs, {} arbitration losses, last delayed {:?}{}]",
+            if i == app.selected { ">" } else { " " },
+            app.bus_prefix(*id),
+            app.kinds.get(id).map_or(String::new(), |kind| format!("[{}] ", kind)),
+            app.label(*id),
+            if app.is_enabled(*id) { "" } else { "[DISABLED] " },
+            status,
+            fault.label(),
+            health,
+            stats.map_or(0, |s| s.dtc_count),
+            app.arbitration_losses.get(id).copied().unwrap_or(0),
+            app.arbitration_delay.get(id).copied().unwrap_or_default(),
+            stats.and_then(|s| s.last_dtc).map_or(String::new(), |code| format!(", last {}", code.as_str())),
+        )?;
+    }
+    if let Some(sensor) = app.selected_id().and_then(|id| sensors.iter().find(|s| s.get_id() == id)) {
+        writeln!(out, "\nSelected: {}", sensor.name())?;
+        for line in sensor.describe() {
+            writeln!(out, "  {}", line)?;
+        }
+    }
+
+    let visible: Vec<&LogEntry> = app.visible_logs().collect();
+    writeln!(out, "\nLog ({} shown of {} buffered, {} unacknowledged)", visible.len(), app.logs.len(), app.unacknowledged_dtcs())?;
+    for entry in visible {
+        let marker = if entry.acknowledged { "ack" } else if !entry.ack_keys.is_empty() { "DTC" } else { "" };
+        writeln!(out, "{:>3} {}", marker, entry.display_text())?;
+    }
+    Ok(())
+}
+
+pub fn write_summary(out: &mut impl Write, app: &AppState, sensors: &[Box<dyn SentinelComponent>]) -> io::Result<()> {
+    let summary = &app.summary;
+    let local = |at: Option<DateTime<Utc>>| at.map_or("-".to_string(), |at| at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string());
+    writeln!(out, "Session summary")?;
+    writeln!(out, "  Runtime       {}", app.uptime())?;
+    writeln!(out, "  DTCs          {}", summary.per_sensor.values().sum::<usize>())?;
+    writeln!(out, "  Most frequent {}", summary.most_frequent()
+        .map_or("-".to_string(), |(code, n)| format!("{} {} ({}x)", code.as_str(), code.description(), n)))?;
+    writeln!(out, "  First fault   {}", local(summary.first))?;
+    writeln!(out, "  Last fault    {}", local(summary.last))?;
+    writeln!(out, "  DB latency    {}", lock(&app.latency).summary())?;
+    writeln!(out)?;
+    let mut rows: Vec<(CanId, String)> = app.sensor_status.iter()
+        .map(|(id, _)| (*id, sensors.iter().find(|s| s.get_id() == *id).map_or_else(|| "(recorded)".to_string(), |s| s.name())))
+        .collect();
+    rows.sort();
+    let width = rows.iter().map(|(_, name)| name.chars().count()).max().unwrap_or(0).max("Module".len());
+    writeln!(out, "  {:<12} {:<5} {:<width$} {:>6}", "CAN ID", "Type", "Module", "DTCs")?;
+    for (id, name) in rows {
+        writeln!(
+            out,
+            "  {:<12} {:<5} {:<width$} {:>6}",
+            id.to_string(),
+            app.kinds.get(&id).copied().unwrap_or("-"),
+            name,
+            summary.per_sensor.get(&id).copied().unwrap_or(0),
+        )?;
+    }
+    Ok(())
+}
+
+pub fn timestamp_now() -> String {
+    chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+    use crate::storage::{acknowledge_dtcs, epoch_millis, flush_batch, init_db, DbRecord};
+
+    fn id(raw: u32) -> CanId { CanId::new(raw).unwrap() }
+
+    fn dtc(sensor: u32, message: &str, at: DateTime<Utc>) -> SensorUpdate {
+        SensorUpdate::Status {
+            id: id(sensor),
+            status: message.to_string(),
+            is_dtc: true,
+            fault: FaultState::Confirmed,
+            at,
+            freeze: None,
+            acknowledged: false,
+            position: None,
+        }
+    }
+
+    #[test]
+    fn an_arbitrated_dtc_can_still_be_acknowledged() {
+        let at = epoch_millis(1_767_261_600_000);
+        let message = "DTC C1A67: Sensor Blind / Occluded";
+        let mut conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        let record = DbRecord { sensor_id: id(0x291), message: message.to_string(), at, freeze: None, detected: Instant::now(), measurement: None, bus: 0 };
+        flush_batch(&mut conn, &mut VecDeque::from([record]), None, &Mutex::new(LatencyHistogram::default())).unwrap();
+
+        let mut app = AppState::new(DEFAULT_LOG_CAPACITY, &[id(0x186), id(0x291)]);
+        // Four frames ahead of it, so it waits past a millisecond and a shifted stamp would miss the row.
+        let ahead = (0..4).map(|_| dtc(0x186, "DTC P0A80: Thermal Runaway Risk", at));
+        let arbitrated = app.arbitrate(ahead.chain([dtc(0x291, message, at)]).collect());
+        for update in arbitrated {
+            if let SensorUpdate::Status { id: sensor, at: stamped, .. } = &update && *sensor == id(0x291) {
+                assert_eq!(*stamped, at);
+            }
+            app.apply(update);
+        }
+        assert_eq!(app.arbitration_losses.get(&id(0x291)), Some(&1));
+        assert_eq!(app.arbitration_delay.get(&id(0x291)), Some(&(CAN_FRAME_TIME * 4)));
+
+        let mut keys = app.acknowledge_selected().unwrap();
+        assert_eq!(keys.iter().map(|key| key.sensor_id).collect::<Vec<_>>(), [id(0x291)]);
+        acknowledge_dtcs(&mut conn, &mut keys).unwrap();
+        let acknowledged: bool = conn.query_row("SELECT acknowledged FROM sensor_logs", [], |row| row.get(0)).unwrap();
+        assert!(acknowledged);
+    }
+
+    #[test]
+    fn acknowledging_a_collapsed_line_acknowledges_every_occurrence() {
+        let at = |ms: i64| epoch_millis(1_767_261_600_000 + ms);
+        let mut app = AppState::new(DEFAULT_LOG_CAPACITY, &[id(0x186)]);
+        for ms in [0, 1_000, 2_000, 3_000] {
+            app.apply(dtc(0x186, "DTC P0A80: Thermal Runaway Risk", at(ms)));
+        }
+        assert_eq!(app.logs.len(), 1);
+        assert!(app.logs[0].display_text().ends_with("\u{d7}4"));
+        let keys = app.acknowledge_selected().unwrap();
+        assert_eq!(keys.iter().map(|key| key.at).collect::<Vec<_>>(), [at(0), at(1_000), at(2_000), at(3_000)]);
+        assert!(app.acknowledge_selected().is_none());
+    }
+
+
+    #[test]
+    fn a_repeat_outside_the_window_or_of_another_code_gets_its_own_line() {
+        let at = |ms: i64| epoch_millis(1_767_261_600_000 + ms);
+        let mut app = AppState::new(DEFAULT_LOG_CAPACITY, &[id(0x186)]);
+        app.apply(dtc(0x186, "DTC P0A80: Thermal Runaway Risk", at(0)));
+        app.apply(dtc(0x186, "DTC P0A80: Thermal Runaway Risk", at(5_000)));
+        app.apply(dtc(0x186, "DTC P0A80: Thermal Runaway Risk", at(10_001)));
+        app.apply(dtc(0x186, "DTC P0A7F: Rapid Discharge Detected", at(10_002)));
+        let counts: Vec<u32> = app.logs.iter().map(|entry| entry.count).collect();
+        assert_eq!(counts, [2, 1, 1]);
+        app.dedup_window = Duration::ZERO;
+        app.apply(dtc(0x186, "DTC P0A7F: Rapid Discharge Detected", at(10_003)));
+        assert_eq!(app.logs.len(), 4);
+    }
+
+    #[test]
+    fn dtcs_raised_while_paused_are_logged_on_resume() {
+        let at = |ms: i64| epoch_millis(1_767_261_600_000 + ms);
+        let mut app = AppState::new(DEFAULT_LOG_CAPACITY, &[id(0x186), id(0x291)]);
+        app.toggle_pause();
+        app.apply(dtc(0x186, "DTC P0A80: Thermal Runaway Risk", at(0)));
+        app.apply(dtc(0x291, "DTC C1A67: Sensor Blind / Occluded", at(100)));
+        app.apply(dtc(0x186, "DTC P0A80: Thermal Runaway Risk", at(200)));
+        assert!(app.logs.is_empty());
+        app.toggle_pause();
+        let lines: Vec<(CanId, u32)> = app.logs.iter().map(|entry| (entry.can_id.unwrap(), entry.count)).collect();
+        assert_eq!(lines, [(id(0x186), 2), (id(0x291), 1)]);
+        assert!(app.held_logs.is_empty());
+    }
+
+    #[test]
+    fn a_scrolled_back_log_stays_on_its_line_as_entries_arrive() {
+        let mut app = AppState::new(3, &[id(0x186)]);
+        for n in 0..3 {
+            app.notify(format!("notice {}", n));
+        }
+        app.scroll_logs_up(1);
+        assert_eq!(app.log_scroll, 1);
+        app.notify("notice 3".to_string());
+        // The buffer is full, so the oldest line went and the view is pinned at the top.
+        assert_eq!((app.logs.len(), app.log_scroll), (3, 2));
+        app.log_filter = Some(id(0x186));
+        app.log_scroll = 0;
+        app.apply(dtc(0x186, "DTC P0A80: Thermal Runaway Risk", epoch_millis(0)));
+        app.apply(dtc(0x186, "DTC P0A7F: Rapid Discharge Detected", epoch_millis(1)));
+        app.scroll_logs_up(1);
+        app.notify("notice 4".to_string());
+        assert_eq!(app.log_scroll, 1, "a line hidden by the filter must not move the view");
+        app.scroll_logs_down(5);
+        assert_eq!(app.log_scroll, 0);
+    }
+
+    #[test]
+    fn arbitration_orders_each_bus_by_can_id_and_charges_the_losers() {
+        let at = epoch_millis(1_767_261_600_000);
+        let mut app = AppState::new(DEFAULT_LOG_CAPACITY, &[id(0x186), id(0x291), id(0x3B1)]);
+        app.buses = HashMap::from([(id(0x186), 0), (id(0x291), 1), (id(0x3B1), 1)]);
+        let updates = vec![
+            dtc(0x3B1, "DTC C0035: Wheel Speed Sensor Fault", at),
+            SensorUpdate::Notice("[CAN] notice".to_string()),
+            dtc(0x291, "DTC C1A67: Sensor Blind / Occluded", at),
+            dtc(0x186, "DTC P0A80: Thermal Runaway Risk", at),
+        ];
+        let order: Vec<Option<CanId>> = app.arbitrate(updates).iter()
+            .map(|update| match update {
+                SensorUpdate::Status { id, .. } => Some(*id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(order, [Some(id(0x186)), Some(id(0x291)), Some(id(0x3B1)), None]);
+        assert_eq!(app.arbitration_losses, HashMap::from([(id(0x3B1), 1)]));
+        assert_eq!(app.arbitration_delay[&id(0x186)], Duration::ZERO);
+        assert_eq!(app.arbitration_delay[&id(0x291)], Duration::ZERO);
+        assert_eq!(app.arbitration_delay[&id(0x3B1)], CAN_FRAME_TIME);
+    }
+
+    #[test]
+    fn a_silent_sensor_raises_one_lost_communication_dtc_until_it_answers() {
+        let mut app = AppState::new(DEFAULT_LOG_CAPACITY, &[id(0x186)]);
+        app.liveness.insert(id(0x186), Duration::from_secs(1));
+        assert!(app.check_liveness(app.start + Duration::from_millis(500)).is_empty());
+        let raised = app.check_liveness(app.start + Duration::from_secs(2));
+        assert_eq!(raised.len(), 1);
+        assert!(raised[0].1.starts_with("DTC U0100: Lost Communication With ECU (no response for 2.0s)"), "{}", raised[0].1);
+        assert_eq!(app.sensor_status[0].1, NO_RESPONSE);
+        assert_eq!(app.logs.back().and_then(|entry| entry.ack_keys.first()).map(|key| key.sensor_id), Some(id(0x186)));
+        assert!(app.check_liveness(app.start + Duration::from_secs(3)).is_empty());
+
+        app.apply(dtc(0x186, "DTC P0A80: Thermal Runaway Risk", Utc::now()));
+        assert!(app.logs.iter().any(|entry| entry.text.contains("is responding again")));
+        assert!(app.silent.is_empty());
+    }
+
+}
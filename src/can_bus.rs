@@ -0,0 +1,72 @@
+//! Reading frames from a SocketCAN interface and routing them to the matching component.
+use std::io;
+use std::collections::HashSet;
+use std::thread;
+use std::time::Duration;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use socketcan::{CanSocket, EmbeddedFrame, Frame, Id, Socket};
+use crate::SensorUpdate;
+use crate::dtc::CanId;
+use crate::sensors::{is_enabled, record_status, Outbox, Reading, SentinelComponent};
+
+pub const DEFAULT_IFACE: &str = "can0";
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+#[derive(Default)]
+pub struct FrameDecoder {
+    #[cfg(feature = "can-dbc")]
+    pub dbc: Option<crate::dbc::SignalDecoder>,
+}
+
+impl FrameDecoder {
+    fn decode(&self, sensor: &dyn SentinelComponent, data: &[u8]) -> Result<Option<String>, String> {
+        #[cfg(feature = "can-dbc")]
+        if let Some(dbc) = &self.dbc {
+            let id = sensor.get_id();
+            let (message, signals) = dbc.decode(id, data).ok_or_else(|| format!("no DBC message for CAN ID {}", id))?;
+            return match sensor.apply_signals(&signals) {
+                Some(status) => Ok(Some(status)),
+                None => Err(format!("DBC message {} has no signals {} can use", message, sensor.name())),
+            };
+        }
+        Ok(sensor.decode_frame(data))
+    }
+}
+
+pub fn spawn_reader(
+    iface: &str,
+    sensors: Arc<Vec<Box<dyn SentinelComponent>>>,
+    decoder: FrameDecoder,
+    outbox: Outbox,
+    shutdown: Arc<AtomicBool>,
+) -> io::Result<thread::JoinHandle<()>> {
+    let socket = CanSocket::open(iface)?;
+    Ok(thread::spawn(move || {
+        let mut warned = HashSet::new();
+        while !shutdown.load(Ordering::Relaxed) {
+            let frame = match socket.read_frame_timeout(READ_TIMEOUT) {
+                Ok(frame) => frame,
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => continue,
+                Err(_) => break,
+            };
+            if frame.is_remote_frame() || frame.is_error_frame() { continue; }
+
+            let id = match frame.id() {
+                Id::Standard(id) => CanId::Standard(id.as_raw()),
+                Id::Extended(id) => CanId::Extended(id.as_raw()),
+            };
+            let Some(sensor) = sensors.iter().find(|s| s.get_id() == id) else { continue };
+            if !is_enabled(&outbox.enabled, id) { continue; }
+            match decoder.decode(sensor.as_ref(), frame.data()) {
+                Ok(Some(status)) => record_status(&outbox, sensor.as_ref(), Reading::text(status)),
+                Ok(None) => {}
+                Err(warning) => {
+                    if warned.insert(id) {
+                        let _ = outbox.ui.send(SensorUpdate::Notice(format!("[CAN] {}, skipping these frames", warning)));
+                    }
+                }
+            }
+        }
+    }))
+}
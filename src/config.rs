@@ -0,0 +1,349 @@
+//! The `sensors.toml` topology and live reloading of its thresholds.
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::Deserialize;
+use crate::{DEFAULT_POLL_INTERVAL, Error};
+use crate::detectors::{thermal_detectors, ThermalConfig, ThermalDetector};
+use crate::dtc::{CanId, DtcCode, Severity, SeverityMap};
+use crate::sensors::{entropy_rng, seeded_rng, ABS_ECU, ABS_FAULT_PROBABILITY, ABS_FIRMWARE, ABS_WARM_UP, ADAS_Computer, ADAS_FAULT_PROBABILITY, ADAS_FIRMWARE, ADAS_WARM_UP, BMS_ECU, BMS_FAULT_PROBABILITY, BMS_FIRMWARE, BMS_WARM_UP, DEFAULT_BMS_CELLS, DEFAULT_GPS_ORIGIN, DEFAULT_MAX_CELL_SPREAD, EcuBuilder, GPS_FAULT_PROBABILITY, GPS_FIRMWARE, GPS_Module, GPS_WARM_UP, MAX_BMS_CELLS, NoiseModel, Position, SentinelComponent, TPMS, TPMS_FAULT_PROBABILITY, TPMS_FIRMWARE, TPMS_WARM_UP, Tuning, VoltageProfile};
+
+pub const DEFAULT_SENSORS: &str = include_str!("../sensors.toml");
+
+#[derive(Deserialize, Debug)]
+pub struct SensorsFile {
+    pub sensor: Vec<SensorConfig>,
+    #[serde(default)]
+    pub severity_map: HashMap<DtcCode, Severity>,
+    pub severity_default: Option<Severity>,
+}
+
+impl SensorsFile {
+    pub fn severities(&self) -> SeverityMap {
+        SeverityMap::with_overrides(&self.severity_map, self.severity_default)
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ThermalOverrides {
+    pub detector: Option<ThermalDetector>,
+    pub window: Option<usize>,
+    pub min_window: Option<usize>,
+    pub std_floor: Option<f64>,
+    pub sigma: Option<f64>,
+    pub alpha: Option<f64>,
+    pub max_drop_rate: Option<f64>,
+}
+
+impl ThermalOverrides {
+    pub fn apply(&self, base: ThermalConfig) -> ThermalConfig {
+        let window = self.window.unwrap_or(base.window);
+        ThermalConfig {
+            detector: self.detector.unwrap_or(base.detector),
+            window,
+            min_window: self.min_window.unwrap_or(if self.window.is_some() { base.min_window.min(window) } else { base.min_window }),
+            std_floor: self.std_floor.unwrap_or(base.std_floor),
+            sigma: self.sigma.unwrap_or(base.sigma),
+            alpha: self.alpha.unwrap_or(base.alpha),
+            max_drop_rate: self.max_drop_rate.unwrap_or(base.max_drop_rate),
+        }
+    }
+}
+
+pub fn default_poll_ms() -> u64 { DEFAULT_POLL_INTERVAL.as_millis() as u64 }
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum SensorConfig {
+    Bms {
+        can_id: CanId,
+        name: Option<String>,
+        #[serde(default)]
+        bus: u8,
+        firmware: Option<String>,
+        warm_up_ms: Option<u64>,
+        #[serde(default = "default_poll_ms")]
+        poll_ms: u64,
+        #[serde(default)]
+        thermal: ThermalOverrides,
+        #[serde(default)]
+        profile: VoltageProfile,
+        #[serde(default)]
+        noise: NoiseModel,
+        seed: Option<u64>,
+        cells: Option<usize>,
+        max_cell_spread: Option<f64>,
+        fault_probability: Option<f64>,
+    },
+    Adas {
+        can_id: CanId,
+        name: Option<String>,
+        #[serde(default)]
+        bus: u8,
+        firmware: Option<String>,
+        warm_up_ms: Option<u64>,
+        #[serde(default = "default_poll_ms")]
+        poll_ms: u64,
+        module_name: String,
+        fault_probability: Option<f64>,
+    },
+    Tpms {
+        can_id: CanId,
+        name: Option<String>,
+        #[serde(default)]
+        bus: u8,
+        firmware: Option<String>,
+        warm_up_ms: Option<u64>,
+        #[serde(default = "default_poll_ms")]
+        poll_ms: u64,
+        fault_probability: Option<f64>,
+    },
+    Abs {
+        can_id: CanId,
+        name: Option<String>,
+        #[serde(default)]
+        bus: u8,
+        firmware: Option<String>,
+        warm_up_ms: Option<u64>,
+        #[serde(default = "default_poll_ms")]
+        poll_ms: u64,
+        fault_probability: Option<f64>,
+    },
+    Gps {
+        can_id: CanId,
+        name: Option<String>,
+        #[serde(default)]
+        bus: u8,
+        firmware: Option<String>,
+        warm_up_ms: Option<u64>,
+        #[serde(default = "default_poll_ms")]
+        poll_ms: u64,
+        lat: Option<f64>,
+        lon: Option<f64>,
+        fault_probability: Option<f64>,
+    },
+}
+
+impl SensorConfig {
+    pub fn can_id(&self) -> CanId {
+        match self {
+            SensorConfig::Bms { can_id, .. }
+            | SensorConfig::Adas { can_id, .. }
+            | SensorConfig::Tpms { can_id, .. }
+            | SensorConfig::Abs { can_id, .. }
+            | SensorConfig::Gps { can_id, .. } => *can_id,
+        }
+    }
+
+    // Friendly name shown next to the CAN ID; ADAS modules fall back to their module_name.
+    pub fn display_name(&self) -> Option<&str> {
+        match self {
+            SensorConfig::Adas { name, module_name, .. } => Some(name.as_deref().unwrap_or(module_name)),
+            SensorConfig::Bms { name, .. }
+            | SensorConfig::Tpms { name, .. }
+            | SensorConfig::Abs { name, .. }
+            | SensorConfig::Gps { name, .. } => name.as_deref(),
+        }
+    }
+
+    pub fn bus(&self) -> u8 {
+        match self {
+            SensorConfig::Bms { bus, .. }
+            | SensorConfig::Adas { bus, .. }
+            | SensorConfig::Tpms { bus, .. }
+            | SensorConfig::Abs { bus, .. }
+            | SensorConfig::Gps { bus, .. } => *bus,
+        }
+    }
+
+    pub fn warm_up(&self) -> Option<Duration> {
+        match self {
+            SensorConfig::Bms { warm_up_ms, .. }
+            | SensorConfig::Adas { warm_up_ms, .. }
+            | SensorConfig::Tpms { warm_up_ms, .. }
+            | SensorConfig::Abs { warm_up_ms, .. }
+            | SensorConfig::Gps { warm_up_ms, .. } => warm_up_ms.map(Duration::from_millis),
+        }
+    }
+
+    pub fn fault_probability(&self) -> Option<f64> {
+        match self {
+            SensorConfig::Bms { fault_probability, .. }
+            | SensorConfig::Adas { fault_probability, .. }
+            | SensorConfig::Tpms { fault_probability, .. }
+            | SensorConfig::Abs { fault_probability, .. }
+            | SensorConfig::Gps { fault_probability, .. } => *fault_probability,
+        }
+    }
+
+    pub fn tuning(&self, thermal: ThermalConfig, fault_rate: Option<f64>) -> Tuning {
+        let (thermal, default_probability) = match self {
+            SensorConfig::Bms { thermal: overrides, .. } => (overrides.apply(thermal), BMS_FAULT_PROBABILITY),
+            SensorConfig::Adas { .. } => (thermal, ADAS_FAULT_PROBABILITY),
+            SensorConfig::Tpms { .. } => (thermal, TPMS_FAULT_PROBABILITY),
+            SensorConfig::Abs { .. } => (thermal, ABS_FAULT_PROBABILITY),
+            SensorConfig::Gps { .. } => (thermal, GPS_FAULT_PROBABILITY),
+        };
+        Tuning { thermal, fault_probability: fault_rate.or(self.fault_probability()).unwrap_or(default_probability) }
+    }
+
+    pub fn build(&self, thermal: ThermalConfig, fault_rate: Option<f64>) -> Result<Box<dyn SentinelComponent>, Error> {
+        let fault_probability = fault_rate.or(self.fault_probability());
+        if let Some(p) = fault_probability && !(0.0..=1.0).contains(&p) {
+            return Err(Error::Config(format!("sensor {}: fault_probability must be in 0.0..=1.0", self.can_id())));
+        }
+        Ok(match self {
+            SensorConfig::Bms { can_id, poll_ms, thermal: overrides, profile, noise, seed, cells, max_cell_spread, firmware, .. } => {
+                let thermal = overrides.apply(thermal);
+                if thermal.window == 0 || thermal.min_window > thermal.window {
+                    return Err(Error::Config(format!("sensor {}: thermal window must satisfy 0 < min_window <= window", can_id)));
+                }
+                for (name, value) in [("sigma", thermal.sigma), ("std_floor", thermal.std_floor), ("max_drop_rate", thermal.max_drop_rate)] {
+                    if !(value.is_finite() && value > 0.0) {
+                        return Err(Error::Config(format!("sensor {}: thermal {} must be a positive number", can_id, name)));
+                    }
+                }
+                if !(thermal.alpha > 0.0 && thermal.alpha <= 1.0) {
+                    return Err(Error::Config(format!("sensor {}: thermal alpha must be in (0, 1]", can_id)));
+                }
+                noise.validate().map_err(|e| Error::Config(format!("sensor {}: {}", can_id, e)))?;
+                let cells = cells.unwrap_or(DEFAULT_BMS_CELLS);
+                if !(1..=MAX_BMS_CELLS).contains(&cells) {
+                    return Err(Error::Config(format!("sensor {}: cells must be in 1..={}", can_id, MAX_BMS_CELLS)));
+                }
+                let max_cell_spread = max_cell_spread.unwrap_or(DEFAULT_MAX_CELL_SPREAD);
+                if max_cell_spread <= 0.0 {
+                    return Err(Error::Config(format!("sensor {}: max_cell_spread must be positive", can_id)));
+                }
+                Box::new(
+                    BMS_ECU::new(*can_id, Duration::from_millis(*poll_ms), seeded_rng(*seed))
+                        .with_thermal(thermal)
+                        .with_detectors(thermal_detectors(thermal))
+                        .with_profile(*profile)
+                        .with_noise(*noise)
+                        .with_cells(cells, max_cell_spread)
+                        .with_fault_probability(fault_probability.unwrap_or(BMS_FAULT_PROBABILITY))
+                        .with_firmware(firmware.as_deref().unwrap_or(BMS_FIRMWARE))
+                        .with_warm_up(self.warm_up().unwrap_or(BMS_WARM_UP)),
+                )
+            }
+            SensorConfig::Adas { can_id, poll_ms, module_name, firmware, .. } => Box::new(
+                ADAS_Computer::new(*can_id, module_name, Duration::from_millis(*poll_ms), entropy_rng())
+                    .with_fault_probability(fault_probability.unwrap_or(ADAS_FAULT_PROBABILITY))
+                    .with_firmware(firmware.as_deref().unwrap_or(ADAS_FIRMWARE))
+                    .with_warm_up(self.warm_up().unwrap_or(ADAS_WARM_UP)),
+            ),
+            SensorConfig::Tpms { can_id, poll_ms, firmware, .. } => Box::new(
+                TPMS::new(*can_id, Duration::from_millis(*poll_ms), entropy_rng())
+                    .with_fault_probability(fault_probability.unwrap_or(TPMS_FAULT_PROBABILITY))
+                    .with_firmware(firmware.as_deref().unwrap_or(TPMS_FIRMWARE))
+                    .with_warm_up(self.warm_up().unwrap_or(TPMS_WARM_UP)),
+            ),
+            SensorConfig::Abs { can_id, poll_ms, firmware, .. } => Box::new(
+                ABS_ECU::new(*can_id, Duration::from_millis(*poll_ms), entropy_rng())
+                    .with_fault_probability(fault_probability.unwrap_or(ABS_FAULT_PROBABILITY))
+                    .with_firmware(firmware.as_deref().unwrap_or(ABS_FIRMWARE))
+                    .with_warm_up(self.warm_up().unwrap_or(ABS_WARM_UP)),
+            ),
+            SensorConfig::Gps { can_id, poll_ms, lat, lon, firmware, .. } => {
+                let origin = Position { lat: lat.unwrap_or(DEFAULT_GPS_ORIGIN.lat), lon: lon.unwrap_or(DEFAULT_GPS_ORIGIN.lon) };
+                if !(-90.0..=90.0).contains(&origin.lat) || !(-180.0..=180.0).contains(&origin.lon) {
+                    return Err(Error::Config(format!("sensor {}: lat must be in -90..=90 and lon in -180..=180", can_id)));
+                }
+                Box::new(
+                    GPS_Module::new(*can_id, origin, Duration::from_millis(*poll_ms), entropy_rng())
+                        .with_fault_probability(fault_probability.unwrap_or(GPS_FAULT_PROBABILITY))
+                        .with_firmware(firmware.as_deref().unwrap_or(GPS_FIRMWARE))
+                        .with_warm_up(self.warm_up().unwrap_or(GPS_WARM_UP)),
+                )
+            }
+        })
+    }
+}
+
+pub fn load_sensors_file(path: Option<&Path>) -> Result<SensorsFile, Error> {
+    let source = path.map_or_else(|| "built-in sensors.toml".to_string(), |p| p.display().to_string());
+    let text = match path {
+        Some(path) => std::fs::read_to_string(path).map_err(|e| Error::Config(format!("{}: {}", source, e)))?,
+        None => DEFAULT_SENSORS.to_string(),
+    };
+    let file = toml::from_str::<SensorsFile>(&text).map_err(|e| Error::Config(format!("{}: {}", source, e.message())))?;
+    let mut seen = HashSet::new();
+    if let Some(dup) = file.sensor.iter().map(SensorConfig::can_id).find(|id| !seen.insert(*id)) {
+        return Err(Error::Config(format!("CAN ID {} is configured more than once", dup)));
+    }
+    Ok(file)
+}
+
+pub fn load_sensor_configs(path: Option<&Path>) -> Result<Vec<SensorConfig>, Error> {
+    Ok(load_sensors_file(path)?.sensor)
+}
+
+// Where 'r' re-reads thresholds from; the command-line values stay the base the file overrides.
+pub struct ConfigSource {
+    pub path: PathBuf,
+    pub thermal: ThermalConfig,
+    pub fault_rate: Option<f64>,
+}
+
+// Builds every entry first so a bad file is rejected before any running sensor changes.
+pub fn reload_config(source: &ConfigSource, sensors: &[Box<dyn SentinelComponent>]) -> Result<String, Error> {
+    let configs = load_sensor_configs(Some(&source.path))?;
+    let mut retuned = Vec::new();
+    let mut ignored = 0;
+    for config in &configs {
+        let built = config.build(source.thermal, source.fault_rate)?;
+        let Some(sensor) = sensors.iter().find(|s| s.get_id() == config.can_id()) else {
+            ignored += 1;
+            continue;
+        };
+        if sensor.kind() != built.kind() {
+            return Err(Error::Config(format!("sensor {}: type changed from {} to {}, restart to apply", config.can_id(), sensor.kind(), built.kind())));
+        }
+        retuned.push((sensor, config.tuning(source.thermal, source.fault_rate)));
+    }
+    let count = retuned.len();
+    let mut reset = Vec::new();
+    for (sensor, tuning) in retuned {
+        if sensor.thermal().is_some_and(|live| live.live_tuning_differs(&tuning.thermal)) {
+            reset.push(sensor.get_id().to_string());
+        }
+        sensor.retune(tuning);
+    }
+    let mut summary = match ignored {
+        0 => format!("{} sensors retuned", count),
+        n => format!("{} sensors retuned, {} new entries need a restart", count, n),
+    };
+    if !reset.is_empty() {
+        summary.push_str(&format!("; live detector and sigma/floor tuning of {} reset to the file's values", reset.join(", ")));
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bms(thermal: &str) -> SensorConfig {
+        let text = format!("[[sensor]]\ntype = \"bms\"\ncan_id = 0x186\nthermal = {{ {} }}\n", thermal);
+        toml::from_str::<SensorsFile>(&text).unwrap().sensor.remove(0)
+    }
+
+    #[test]
+    fn build_rejects_non_positive_thermal_tuning() {
+        for field in ["sigma", "std_floor", "alpha", "max_drop_rate"] {
+            for value in ["0.0", "-1.0", "nan"] {
+                let err = bms(&format!("{} = {}", field, value)).build(ThermalConfig::default(), None).err();
+                assert!(matches!(err, Some(Error::Config(ref m)) if m.contains(field)), "{} = {} was accepted", field, value);
+            }
+        }
+        assert!(bms("sigma = 2.5, std_floor = 0.02, alpha = 0.5, max_drop_rate = 1.0").build(ThermalConfig::default(), None).is_ok());
+    }
+
+    #[test]
+    fn a_narrow_thermal_window_caps_the_default_min_window() {
+        let thermal = ThermalOverrides { window: Some(3), ..ThermalOverrides::default() }.apply(ThermalConfig::default());
+        assert_eq!((thermal.window, thermal.min_window), (3, 3));
+    }
+}
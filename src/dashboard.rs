@@ -0,0 +1,500 @@
+//! The dashboard's event loop: what each key does in each input mode, and the TUI and headless
+//! run loops that feed sensor updates into `AppState`.
+use std::io::{self, Write};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use std::sync::{mpsc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use chrono::Utc;
+use crossterm::event::{Event, KeyCode};
+#[cfg(feature = "async")]
+use futures::StreamExt;
+use rusqlite::Connection;
+use crate::{lock, Error, SHUTDOWN_POLL, SensorUpdate};
+use crate::app_state::{timestamp_now, write_snapshot, AppState, EventLog};
+use crate::config::reload_config;
+use crate::detectors::{ThermalConfig, ThermalDetector};
+use crate::dotfile::value_name;
+use crate::dtc::SUPPORTED_PIDS;
+use crate::sensors::SentinelComponent;
+use crate::storage::{acknowledge_dtcs, export_csv, export_obd_json, insert_markers, query_dtcs, run_console_command, DbCommand, DbRecord, Marker};
+use crate::ui::{draw_frame, enter_tui, handle_mouse, FramePacer, HISTORY_WINDOW_MINUTES};
+
+const EXPORT_PATH: &str = "export.csv";
+const OBD_EXPORT_PATH: &str = "export-obd.json";
+const SNAPSHOT_PATH: &str = "snapshot-%Y%m%d-%H%M%S.txt";
+
+pub fn check_liveness(app: &mut AppState, db_tx: Option<&mpsc::SyncSender<DbCommand>>) {
+    for (sensor_id, message, at) in app.check_liveness(Instant::now()) {
+        app.dirty = true;
+        if let Some(tx) = db_tx {
+            let bus = app.bus(sensor_id);
+            let _ = tx.try_send(DbCommand::Insert(DbRecord { sensor_id, message, at, freeze: None, detected: Instant::now(), measurement: None, bus }));
+        }
+    }
+}
+
+pub fn drain_updates(app: &mut AppState, update_rx: &mpsc::Receiver<SensorUpdate>, event_log: &mut Option<EventLog>) -> io::Result<()> {
+    let updates = update_rx.try_iter().collect();
+    for update in app.arbitrate(updates) {
+        if let Some(log) = event_log.as_mut() {
+            log.record(&update)?;
+        }
+        app.apply(update);
+        app.dirty = true;
+    }
+    if let Some(log) = event_log.as_mut() {
+        log.flush()?;
+    }
+    Ok(())
+}
+
+// Applies one terminal event to the dashboard. Returns false once the user asks to quit.
+pub fn handle_event(
+    app: &mut AppState,
+    event: Event,
+    sensors: &[Box<dyn SentinelComponent>],
+    db_lock: &Mutex<Connection>,
+    db_tx: Option<&mpsc::SyncSender<DbCommand>>,
+) -> bool {
+    let key = match event {
+        Event::Key(key) => key,
+        Event::Mouse(mouse) => {
+            if !app.show_help {
+                handle_mouse(app, mouse);
+            }
+            return true;
+        }
+        Event::Resize(_, height) => {
+            app.handle_resize(height);
+            return true;
+        }
+        _ => return true,
+    };
+    let page = app.log_page;
+    if app.show_help {
+        match key.code {
+            KeyCode::Char('q') => return false,
+            KeyCode::Char('?') | KeyCode::Esc => app.show_help = false,
+            _ => {}
+        }
+        return true;
+    }
+    if let Some(input) = app.filter_input.as_mut() {
+        match key.code {
+            KeyCode::Enter => app.apply_filter_input(),
+            KeyCode::Esc => app.filter_input = None,
+            KeyCode::Backspace => { input.pop(); }
+            KeyCode::Char(c) if c.is_ascii_hexdigit() || c == 'x' => input.push(c),
+            _ => {}
+        }
+        return true;
+    }
+    if let Some((at, note)) = app.marker_input.as_mut() {
+        match key.code {
+            KeyCode::Enter => {
+                let marker = Marker { at: *at, note: note.trim().to_string() };
+                app.marker_input = None;
+                app.notify(format!(
+                    "[MARKER] {} {}",
+                    marker.at.with_timezone(&chrono::Local).format("%H:%M:%S%.3f"),
+                    if marker.note.is_empty() { "(no note)" } else { &marker.note },
+                ));
+                // Like acknowledgements, a replay writes its markers straight into the recording.
+                let stored = match db_tx {
+                    Some(db) => db.try_send(DbCommand::Marker(marker)).map_err(|_| "blackbox writer is saturated".to_string()),
+                    None => insert_markers(&mut lock(db_lock), &mut vec![marker], None).map_err(|e| e.to_string()),
+                };
+                if let Err(e) = stored {
+                    app.notify(format!("[MARKER] marker was not stored: {}", e));
+                }
+            }
+            KeyCode::Esc => app.marker_input = None,
+            KeyCode::Backspace => { note.pop(); }
+            KeyCode::Char(c) => note.push(c),
+            _ => {}
+        }
+        return true;
+    }
+    if let Some(input) = app.command_input.as_mut() {
+        match key.code {
+            KeyCode::Enter => {
+                let command = input.trim().to_string();
+                app.command_input = None;
+                let output = run_console_command(&lock(db_lock), &command);
+                app.console = Some((command, output));
+            }
+            KeyCode::Esc => app.command_input = None,
+            KeyCode::Backspace => { input.pop(); }
+            KeyCode::Char(c) => input.push(c),
+            _ => {}
+        }
+        return true;
+    }
+    if app.searching {
+        match key.code {
+            KeyCode::Enter => app.next_search_match(),
+            KeyCode::Esc => app.close_search(),
+            KeyCode::Backspace => {
+                app.search_query.pop();
+                app.search_match = None;
+            }
+            KeyCode::Char(c) => {
+                app.search_query.push(c);
+                app.search_match = None;
+            }
+            _ => {}
+        }
+        return true;
+    }
+    match key.code {
+        KeyCode::Char('q') => return false,
+        KeyCode::Char(' ') => app.toggle_pause(),
+        KeyCode::Char('?') => app.show_help = true,
+        KeyCode::Char('b') => app.cycle_bus_filter(),
+        KeyCode::Char('f') => app.filter_input = Some(String::new()),
+        KeyCode::Char('/') => app.searching = true,
+        KeyCode::Char(':') => app.command_input = Some(String::new()),
+        KeyCode::Enter => app.toggle_selected_sensor(),
+        KeyCode::Esc if app.console.is_some() => app.console = None,
+        KeyCode::Esc => app.clear_filter(),
+        KeyCode::Char('i') => app.show_details = !app.show_details,
+        KeyCode::Char('g') => app.show_histogram = !app.show_histogram,
+        KeyCode::Char('v') => app.show_chart = !app.show_chart,
+        KeyCode::Char('m') => app.marker_input = Some((Utc::now(), String::new())),
+        KeyCode::Char('r') => {
+            let message = match &app.config_source {
+                Some(source) => match reload_config(source, sensors) {
+                    Ok(summary) => format!("[CONFIG] {} reloaded: {}", source.path.display(), summary),
+                    Err(e) => format!("[CONFIG] reload rejected, keeping the current values: {}", e),
+                },
+                None => "[CONFIG] nothing to reload (start with --config)".to_string(),
+            };
+            app.notify(message);
+        }
+        KeyCode::Char('d') => {
+            let sensor = app.selected_id().and_then(|id| sensors.iter().find(|s| s.get_id() == id));
+            match sensor.and_then(|sensor| sensor.cycle_detector().map(|detector| (sensor.get_id(), detector))) {
+                Some((id, detector)) => app.notify(format!("[DETECTOR] {} now uses {}", id, detector.label())),
+                None => app.notify("[DETECTOR] Select a BMS to switch its detector".to_string()),
+            }
+        }
+        KeyCode::Char(c @ ('+' | '=' | '-' | '[' | ']')) => {
+            let adjust = |thermal: &mut ThermalConfig| match c {
+                '+' | '=' => thermal.nudge_sigma(1.0),
+                '-' => thermal.nudge_sigma(-1.0),
+                ']' => thermal.nudge_std_floor(1.0),
+                _ => thermal.nudge_std_floor(-1.0),
+            };
+            let sensor = app.selected_id().and_then(|id| sensors.iter().find(|s| s.get_id() == id));
+            match sensor.and_then(|sensor| sensor.adjust_thermal(&adjust).map(|thermal| (sensor.get_id(), thermal))) {
+                Some((id, thermal)) if thermal.detector == ThermalDetector::Rate => app.notify(format!(
+                    "[TUNING] {} sigma {:.1}, std-dev floor {:.2}V (unused by the dV/dt detector, d cycles)", id, thermal.sigma, thermal.std_floor,
+                )),
+                Some((id, thermal)) => app.notify(format!("[TUNING] {} sigma {:.1}, std-dev floor {:.2}V", id, thermal.sigma, thermal.std_floor)),
+                None => app.notify("[TUNING] Select a BMS to adjust its thresholds".to_string()),
+            }
+        }
+        KeyCode::Char('l') => app.layout = app.layout.next(),
+        KeyCode::Char('s') => app.toggle_sort(),
+        KeyCode::Char('h') if app.history.is_some() => app.history = None,
+        KeyCode::Char('h') => {
+            let to = Utc::now();
+            let from = to - chrono::Duration::minutes(HISTORY_WINDOW_MINUTES);
+            match query_dtcs(&lock(db_lock), from, to) {
+                Ok(records) => app.history = Some(records),
+                Err(e) => app.notify(format!("[HISTORY] query failed: {}", e)),
+            }
+        }
+        KeyCode::Char('e') => {
+            let path = Path::new(EXPORT_PATH);
+            let scope = app.bus_filter.map_or(String::new(), |bus| format!("bus {} ", bus));
+            match export_csv(&lock(db_lock), path, app.bus_filter) {
+                Ok(()) => app.notify(format!("[EXPORT] {}sensor_logs written to {}", scope, path.display())),
+                Err(e) => app.notify(format!("[EXPORT] failed: {}", e)),
+            }
+        }
+        KeyCode::Char('o') => {
+            let path = Path::new(OBD_EXPORT_PATH);
+            match export_obd_json(&lock(db_lock), path) {
+                Ok(count) => app.notify(format!("[EXPORT] {} DTC freeze frames written to {}", count, path.display())),
+                Err(e) => app.notify(format!("[EXPORT] failed: {}", e)),
+            }
+        }
+        KeyCode::Char('c') => {
+            for sensor in sensors {
+                sensor.clear_faults();
+                lock(sensor.fault_tracker()).reset();
+            }
+            if let Some(db) = db_tx && db.try_send(DbCommand::ClearDtcs).is_err() {
+                app.notify("[CLEAR] Blackbox writer is saturated, stored DTCs were not cleared".to_string());
+            }
+            app.clear_dtcs();
+        }
+        KeyCode::Char('a') => match app.acknowledge_selected() {
+            Some(mut keys) => {
+                // Replay has no writer thread, so the acknowledgement goes straight into the recording.
+                let stored = match db_tx {
+                    Some(db) => db.try_send(DbCommand::Acknowledge(keys)).map_err(|_| "blackbox writer is saturated".to_string()),
+                    None => acknowledge_dtcs(&mut lock(db_lock), &mut keys).map_err(|e| e.to_string()),
+                };
+                if let Err(e) = stored {
+                    app.notify(format!("[ACK] acknowledgement was not stored: {}", e));
+                }
+            }
+            None => app.notify("[ACK] No unacknowledged DTC in view".to_string()),
+        },
+        KeyCode::Char('W') => {
+            let message = match &mut app.dotfile {
+                Some(dotfile) => {
+                    dotfile.set("layout", value_name(app.layout));
+                    match dotfile.save() {
+                        Ok(()) => format!("[SETTINGS] defaults saved to {}", dotfile.path.display()),
+                        Err(e) => format!("[SETTINGS] save failed: {}", e),
+                    }
+                }
+                None => "[SETTINGS] no config directory (set HOME or XDG_CONFIG_HOME)".to_string(),
+            };
+            app.notify(message);
+        }
+        KeyCode::F(2) => {
+            let path = PathBuf::from(chrono::Local::now().format(SNAPSHOT_PATH).to_string());
+            let written = File::create(&path).and_then(|file| {
+                let mut out = io::BufWriter::new(file);
+                write_snapshot(app, sensors, &mut out)?;
+                out.flush()
+            });
+            match written {
+                Ok(()) => app.notify(format!("[SNAPSHOT] dashboard written to {}", path.display())),
+                Err(e) => app.notify(format!("[SNAPSHOT] failed: {}", e)),
+            }
+        }
+        KeyCode::Char('p') => {
+            if let Some(id) = app.selected_id() {
+                let values = sensors.iter().find(|s| s.get_id() == id)
+                    .map(|sensor| SUPPORTED_PIDS.iter().filter_map(|&pid| sensor.read_pid(pid)).collect())
+                    .unwrap_or_default();
+                app.pid_response = Some((id, values));
+            }
+        }
+        KeyCode::Up => app.select_prev(),
+        KeyCode::Down => app.select_next(),
+        KeyCode::PageUp => app.scroll_logs_up(page),
+        KeyCode::PageDown => app.scroll_logs_down(page),
+        KeyCode::Home => app.log_scroll = app.max_log_scroll(),
+        KeyCode::End => app.log_scroll = 0,
+        _ => {}
+    }
+    true
+}
+
+#[cfg(feature = "async")]
+pub async fn run_tui(
+    app: &mut AppState,
+    sensors: &[Box<dyn SentinelComponent>],
+    update_rx: &mpsc::Receiver<SensorUpdate>,
+    db_lock: &Mutex<Connection>,
+    db_tx: Option<&mpsc::SyncSender<DbCommand>>,
+    mut event_log: Option<EventLog>,
+) -> Result<(), Error> {
+    let (_guard, mut terminal) = enter_tui()?;
+    let mut events = crossterm::event::EventStream::new();
+    let mut redraw = tokio::time::interval(app.frame_interval);
+    let mut pacer = FramePacer::new(app.frame_interval);
+    loop {
+        drain_updates(app, update_rx, &mut event_log)?;
+        check_liveness(app, db_tx);
+        if pacer.should_draw(app.dirty, Instant::now()) {
+            draw_frame(&mut terminal, app, sensors)?;
+            app.dirty = false;
+        }
+        tokio::select! {
+            event = events.next() => match event {
+                Some(Ok(event)) => {
+                    pacer.redraw_now();
+                    if !handle_event(app, event, sensors, db_lock, db_tx) { break }
+                }
+                Some(Err(e)) => return Err(e.into()),
+                None => break,
+            },
+            _ = redraw.tick() => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "async"))]
+pub fn run_tui(
+    app: &mut AppState,
+    sensors: &[Box<dyn SentinelComponent>],
+    update_rx: &mpsc::Receiver<SensorUpdate>,
+    db_lock: &Mutex<Connection>,
+    db_tx: Option<&mpsc::SyncSender<DbCommand>>,
+    mut event_log: Option<EventLog>,
+) -> Result<(), Error> {
+    let (_guard, mut terminal) = enter_tui()?;
+    let mut pacer = FramePacer::new(app.frame_interval);
+    loop {
+        drain_updates(app, update_rx, &mut event_log)?;
+        check_liveness(app, db_tx);
+        if pacer.should_draw(app.dirty, Instant::now()) {
+            draw_frame(&mut terminal, app, sensors)?;
+            app.dirty = false;
+        }
+        if crossterm::event::poll(app.frame_interval)? {
+            pacer.redraw_now();
+            if !handle_event(app, crossterm::event::read()?, sensors, db_lock, db_tx) { break; }
+        }
+    }
+    Ok(())
+}
+
+pub fn run_headless(app: &AppState, update_rx: &mpsc::Receiver<SensorUpdate>, event_log: &mut EventLog, shutdown: &AtomicBool) -> io::Result<()> {
+    for entry in &app.logs {
+        eprintln!("{} {}", timestamp_now(), entry.text);
+    }
+    while !shutdown.load(Ordering::Relaxed) {
+        match update_rx.recv_timeout(SHUTDOWN_POLL) {
+            Ok(SensorUpdate::ReplayComplete) => {
+                eprintln!("{} [REPLAY COMPLETE]", timestamp_now());
+                break;
+            }
+            Ok(SensorUpdate::Notice(msg)) => eprintln!("{} {}", timestamp_now(), msg),
+            Ok(update) => {
+                event_log.record(&update)?;
+                event_log.flush()?;
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crossterm::event::KeyEvent;
+    use crate::dtc::{CanId, FaultState};
+    use crate::storage::{epoch_millis, init_db};
+
+    fn blackbox() -> Mutex<Connection> {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        Mutex::new(conn)
+    }
+
+    fn dashboard() -> AppState {
+        AppState::new(crate::app_state::DEFAULT_LOG_CAPACITY, &[CanId::new(0x186).unwrap()])
+    }
+
+    fn press(app: &mut AppState, db: &Mutex<Connection>, code: KeyCode) -> bool {
+        handle_event(app, Event::Key(KeyEvent::from(code)), &[], db, None)
+    }
+
+    fn type_keys(app: &mut AppState, db: &Mutex<Connection>, text: &str) {
+        for c in text.chars() {
+            assert!(press(app, db, KeyCode::Char(c)));
+        }
+    }
+
+    fn last_notice(app: &AppState) -> &str {
+        app.logs.back().map_or("", |entry| entry.text.as_str())
+    }
+
+    #[test]
+    fn q_quits_unless_a_text_input_has_the_keyboard() {
+        let db = blackbox();
+        let mut app = dashboard();
+        assert!(press(&mut app, &db, KeyCode::Char('/')));
+        type_keys(&mut app, &db, "q");
+        assert_eq!(app.search_query, "q");
+        assert!(press(&mut app, &db, KeyCode::Esc));
+        assert!(!app.searching && app.search_query.is_empty());
+        assert!(!press(&mut app, &db, KeyCode::Char('q')));
+    }
+
+    #[test]
+    fn help_swallows_every_key_but_quit_and_close() {
+        let db = blackbox();
+        let mut app = dashboard();
+        assert!(press(&mut app, &db, KeyCode::Char('?')));
+        assert!(press(&mut app, &db, KeyCode::Char('f')));
+        assert!(press(&mut app, &db, KeyCode::Char(' ')));
+        assert!(app.show_help && app.filter_input.is_none() && !app.paused);
+        assert!(press(&mut app, &db, KeyCode::Esc));
+        assert!(!app.show_help);
+        assert!(press(&mut app, &db, KeyCode::Char('?')));
+        assert!(!press(&mut app, &db, KeyCode::Char('q')));
+    }
+
+    #[test]
+    fn the_filter_prompt_takes_only_hex_and_applies_on_enter() {
+        let db = blackbox();
+        let mut app = dashboard();
+        assert!(press(&mut app, &db, KeyCode::Char('f')));
+        type_keys(&mut app, &db, "0xz1q86");
+        assert_eq!(app.filter_input.as_deref(), Some("0x186"));
+        assert!(press(&mut app, &db, KeyCode::Enter));
+        assert_eq!((app.filter_input.as_deref(), app.log_filter), (None, Some(CanId::new(0x186).unwrap())));
+        assert!(press(&mut app, &db, KeyCode::Esc));
+        assert_eq!(app.log_filter, None);
+    }
+
+    #[test]
+    fn a_marker_typed_during_replay_goes_straight_into_the_recording() {
+        let db = blackbox();
+        let mut app = dashboard();
+        assert!(press(&mut app, &db, KeyCode::Char('m')));
+        type_keys(&mut app, &db, " brake test ");
+        assert!(press(&mut app, &db, KeyCode::Enter));
+        assert!(app.marker_input.is_none());
+        assert!(last_notice(&app).ends_with(" brake test"), "{}", last_notice(&app));
+        let note: String = lock(&db).query_row("SELECT note FROM markers", [], |row| row.get(0)).unwrap();
+        assert_eq!(note, "brake test");
+    }
+
+    #[test]
+    fn acknowledging_with_no_dtc_in_view_says_so() {
+        let db = blackbox();
+        let mut app = dashboard();
+        assert!(press(&mut app, &db, KeyCode::Char('a')));
+        assert_eq!(last_notice(&app), "[ACK] No unacknowledged DTC in view");
+    }
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> { lock(&self.0).write(buf) }
+        fn flush(&mut self) -> io::Result<()> { Ok(()) }
+    }
+
+    #[test]
+    fn headless_logs_dtcs_until_the_replay_completes() {
+        let (tx, rx) = mpsc::channel();
+        let at = epoch_millis(1_767_261_600_000);
+        let status = |status: &str, is_dtc| SensorUpdate::Status {
+            id: CanId::new(0x186).unwrap(),
+            status: status.to_string(),
+            is_dtc,
+            fault: FaultState::Confirmed,
+            at,
+            freeze: None,
+            acknowledged: false,
+            position: None,
+        };
+        tx.send(status("Cell Voltage: 3.91V (Optimal)", false)).unwrap();
+        tx.send(status("DTC P0A80: Thermal Runaway Risk", true)).unwrap();
+        tx.send(SensorUpdate::ReplayComplete).unwrap();
+        tx.send(status("DTC P0A80: after the end", true)).unwrap();
+        let out = Captured::default();
+        let mut event_log = EventLog { format: crate::app_state::LogFormat::Text, out: Box::new(out.clone()), position: None };
+        run_headless(&dashboard(), &rx, &mut event_log, &AtomicBool::new(false)).unwrap();
+        let written = String::from_utf8(lock(&out.0).clone()).unwrap();
+        assert_eq!(written.lines().count(), 1);
+        assert!(written.trim_end().ends_with("[CAN ID 0x186] DTC P0A80: Thermal Runaway Risk"), "{}", written);
+    }
+}
@@ -0,0 +1,83 @@
+//! Decoding CAN frames with the signal definitions of a DBC file.
+use std::collections::HashMap;
+use std::path::Path;
+use can_dbc::{ByteOrder, DBC, MessageId, MultiplexIndicator, Signal, ValueType};
+use crate::Error;
+use crate::dtc::CanId;
+
+pub struct Signals(Vec<(String, f64)>);
+
+impl Signals {
+    pub fn get(&self, name: &str) -> Option<f64> {
+        self.0.iter().find(|(n, _)| n == name).map(|&(_, value)| value)
+    }
+}
+
+pub struct SignalDecoder {
+    messages: HashMap<CanId, (String, Vec<Signal>)>,
+}
+
+impl SignalDecoder {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let text = std::fs::read_to_string(path).map_err(|e| Error::Config(format!("{}: {}", path.display(), e)))?;
+        let dbc = DBC::try_from(text.as_str()).map_err(|e| {
+            let reason = match e {
+                can_dbc::Error::Incomplete(_, rest) => {
+                    format!("unexpected input near '{}'", rest.lines().next().unwrap_or_default())
+                }
+                can_dbc::Error::Nom(e) => e.to_string(),
+                can_dbc::Error::MultipleMultiplexors => "extended multiplexing is not supported".to_string(),
+            };
+            Error::Config(format!("{}: {}", path.display(), reason))
+        })?;
+        let mut messages = HashMap::new();
+        for message in dbc.messages() {
+            let id = match *message.message_id() {
+                MessageId::Standard(id) => CanId::new(id as u32),
+                MessageId::Extended(id) => CanId::extended(id),
+            }
+            .map_err(|e| Error::Config(format!("{}: message {}: {}", path.display(), message.message_name(), e)))?;
+            messages.insert(id, (message.message_name().clone(), message.signals().clone()));
+        }
+        Ok(SignalDecoder { messages })
+    }
+
+    pub fn decode(&self, id: CanId, data: &[u8]) -> Option<(&str, Signals)> {
+        let (name, signals) = self.messages.get(&id)?;
+        let values = signals.iter()
+            .filter(|signal| !matches!(signal.multiplexer_indicator(), MultiplexIndicator::MultiplexedSignal(_)))
+            .filter_map(|signal| Some((signal.name().clone(), physical_value(signal, data)?)))
+            .collect();
+        Some((name, Signals(values)))
+    }
+}
+
+fn bit(data: &[u8], index: u64) -> Option<u64> {
+    Some((*data.get((index / 8) as usize)? >> (index % 8) & 1) as u64)
+}
+
+fn physical_value(signal: &Signal, data: &[u8]) -> Option<f64> {
+    let size = signal.signal_size;
+    if size == 0 || size > 64 { return None; }
+    let mut raw = 0u64;
+    match signal.byte_order() {
+        ByteOrder::LittleEndian => {
+            for i in (0..size).rev() {
+                raw = raw << 1 | bit(data, signal.start_bit + i)?;
+            }
+        }
+        ByteOrder::BigEndian => {
+            let mut index = signal.start_bit;
+            for _ in 0..size {
+                raw = raw << 1 | bit(data, index)?;
+                index = if index.is_multiple_of(8) { index + 15 } else { index - 1 };
+            }
+        }
+    }
+    let value = match signal.value_type() {
+        ValueType::Signed if size < 64 => ((raw << (64 - size)) as i64 >> (64 - size)) as f64,
+        ValueType::Signed => raw as i64 as f64,
+        ValueType::Unsigned => raw as f64,
+    };
+    Some(value * signal.factor + signal.offset)
+}
@@ -0,0 +1,467 @@
+//! Fault detection strategies fed one BMS voltage sample per poll.
+use std::collections::VecDeque;
+use std::time::Instant;
+use clap::ValueEnum;
+use serde::Deserialize;
+use crate::dtc::{DtcCode, FreezeFrame};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThermalDetector {
+    Window,
+    Ewma,
+    // Only the dV/dt check, without a sigma test on the voltage itself.
+    Rate,
+}
+
+impl ThermalDetector {
+    pub fn next(self) -> Self {
+        match self {
+            ThermalDetector::Window => ThermalDetector::Ewma,
+            ThermalDetector::Ewma => ThermalDetector::Rate,
+            ThermalDetector::Rate => ThermalDetector::Window,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ThermalDetector::Window => "sliding-window sigma",
+            ThermalDetector::Ewma => "EWMA sigma",
+            ThermalDetector::Rate => "dV/dt only",
+        }
+    }
+}
+
+pub const SIGMA_STEP: f64 = 0.1;
+pub const SIGMA_RANGE: (f64, f64) = (0.5, 6.0);
+pub const STD_FLOOR_STEP: f64 = 0.01;
+pub const STD_FLOOR_RANGE: (f64, f64) = (0.01, 1.0);
+
+#[derive(Clone, Copy, Debug)]
+pub struct ThermalConfig {
+    pub detector: ThermalDetector,
+    pub window: usize,
+    pub min_window: usize,
+    pub std_floor: f64,
+    pub sigma: f64,
+    pub alpha: f64,
+    pub max_drop_rate: f64,
+}
+
+impl Default for ThermalConfig {
+    fn default() -> Self {
+        Self { detector: ThermalDetector::Window, window: 10, min_window: 5, std_floor: 0.05, sigma: 2.0, alpha: 0.1, max_drop_rate: 2.0 }
+    }
+}
+
+impl ThermalConfig {
+    // True when 'd' or the tuning keys moved this config away from `other`.
+    pub fn live_tuning_differs(&self, other: &ThermalConfig) -> bool {
+        (self.detector, self.sigma, self.std_floor) != (other.detector, other.sigma, other.std_floor)
+    }
+
+    // Steps of the live tuning keys, rounded so repeated presses land on clean values.
+    pub fn nudge_sigma(&mut self, steps: f64) {
+        self.sigma = ((self.sigma + steps * SIGMA_STEP) * 10.0).round().clamp(SIGMA_RANGE.0 * 10.0, SIGMA_RANGE.1 * 10.0) / 10.0;
+    }
+
+    pub fn nudge_std_floor(&mut self, steps: f64) {
+        self.std_floor = ((self.std_floor + steps * STD_FLOOR_STEP) * 100.0).round().clamp(STD_FLOOR_RANGE.0 * 100.0, STD_FLOOR_RANGE.1 * 100.0) / 100.0;
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EwmaStats {
+    pub mean: Option<f64>,
+    pub variance: f64,
+    pub samples: usize,
+}
+
+impl EwmaStats {
+    pub fn update(&mut self, value: f64, alpha: f64) -> (f64, f64) {
+        self.samples += 1;
+        let mean = self.mean.get_or_insert(value);
+        let diff = value - *mean;
+        let increment = alpha * diff;
+        *mean += increment;
+        self.variance = (1.0 - alpha) * (self.variance + diff * increment);
+        (*mean, self.variance.sqrt())
+    }
+
+    // The variance starts at zero and is biased low until about 2/alpha samples have been seen.
+    pub fn warmed_up(&self, alpha: f64) -> bool {
+        self.samples as f64 >= 2.0 / alpha
+    }
+}
+
+// The voltages outside which a sample raises P0A80 under the sigma test: none while the std-dev
+// is at or below the floor, since nothing fires then.
+pub fn trip_band(mean: f64, std_dev: f64, config: &ThermalConfig) -> Option<(f64, f64)> {
+    (std_dev > config.std_floor).then_some((mean - config.sigma * std_dev, mean + config.sigma * std_dev))
+}
+
+pub fn outside(sample: f64, band: Option<(f64, f64)>) -> bool {
+    band.is_some_and(|(low, high)| sample < low || sample > high)
+}
+
+pub fn window_stats(data: &[f64]) -> (f64, f64) {
+    let n = data.len() as f64;
+    let mean = data.iter().sum::<f64>() / n;
+    let variance = data.iter().map(|v| (mean - v).powi(2)).sum::<f64>() / n;
+    (mean, variance.sqrt())
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct WindowSummary {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub std_dev: f64,
+}
+
+impl WindowSummary {
+    pub fn of(data: &[f64]) -> Self {
+        let (mean, std_dev) = window_stats(data);
+        let min = data.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = data.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        Self { min, max, mean, std_dev }
+    }
+
+    pub fn table(summary: Option<Self>, unit: &str) -> [String; 2] {
+        let cells = match summary {
+            Some(s) => [s.min, s.max, s.mean, s.std_dev].map(|v| format!("{:.3}{}", v, unit)),
+            None => ["n/a"; 4].map(String::from),
+        };
+        [
+            format!("{:<8}{:>10}{:>10}{:>10}{:>10}", "", "Min", "Max", "Mean", "Std-dev"),
+            format!("{:<8}{:>10}{:>10}{:>10}{:>10}", "Window", cells[0], cells[1], cells[2], cells[3]),
+        ]
+    }
+}
+
+// A detection strategy fed one BMS voltage sample per poll. BMS_ECU runs its detectors in order
+// and reports the first DTC raised, so new algorithms plug in through `BMS_ECU::with_detectors`.
+pub trait FaultDetector: Send {
+    fn evaluate(&mut self, sample: f64, ts: Instant) -> Option<DtcCode>;
+    // Shown in the detail pane.
+    fn describe(&self) -> String;
+    // Reading quoted in the status line when this detector fires.
+    fn reading(&self, sample: f64) -> String { format!("{:.2}V", sample) }
+    fn freeze_frame(&self) -> Option<FreezeFrame> { None }
+    // The band the latest sample was tested against, for detectors that test the voltage itself.
+    fn trip_band(&self) -> Option<(f64, f64)> { None }
+    // Seeds the detector with samples saved by a previous run without evaluating them.
+    fn restore(&mut self, _history: &[f64]) {}
+    // Continues from the newest live sample when the detector replaces another mid-run.
+    fn resume(&mut self, _last: (Instant, f64)) {}
+    // Swaps in new thresholds and keeps the samples seen so far.
+    fn set_config(&mut self, config: ThermalConfig);
+    fn reset(&mut self) {}
+}
+
+#[derive(Default)]
+pub struct SampleWindow {
+    pub samples: VecDeque<f64>,
+}
+
+impl SampleWindow {
+    pub fn push(&mut self, sample: f64, capacity: usize) {
+        while !self.samples.is_empty() && self.samples.len() >= capacity { self.samples.pop_front(); }
+        self.samples.push_back(sample);
+    }
+
+    pub fn to_vec(&self) -> Vec<f64> {
+        self.samples.iter().copied().collect()
+    }
+}
+
+// The sliding-window sigma test: fires when a sample is more than `sigma` std-devs from the window mean.
+pub struct SigmaDetector {
+    pub config: ThermalConfig,
+    pub window: SampleWindow,
+    pub last: Option<(f64, f64)>,
+    pub freeze_frame: Option<FreezeFrame>,
+}
+
+impl SigmaDetector {
+    pub fn new(config: ThermalConfig) -> Self {
+        Self { config, window: SampleWindow::default(), last: None, freeze_frame: None }
+    }
+}
+
+impl FaultDetector for SigmaDetector {
+    fn evaluate(&mut self, sample: f64, _ts: Instant) -> Option<DtcCode> {
+        let cfg = &self.config;
+        self.window.push(sample, cfg.window);
+        if self.window.samples.len() < cfg.min_window { return None; }
+        let data = self.window.to_vec();
+        let (mean, std_dev) = window_stats(&data);
+        self.last = Some((mean, std_dev));
+        if outside(sample, trip_band(mean, std_dev, cfg)) {
+            self.freeze_frame = Some(FreezeFrame { history: data, mean, std_dev });
+            return Some(DtcCode::CellImbalance);
+        }
+        None
+    }
+
+    fn describe(&self) -> String {
+        let (mean, std_dev) = self.last.unwrap_or_else(|| window_stats(&self.window.to_vec()));
+        format!("Sliding window: mean {:.3}V  std-dev {:.3}V  trigger > {:.1} sigma", mean, std_dev, self.config.sigma)
+    }
+
+    fn freeze_frame(&self) -> Option<FreezeFrame> { self.freeze_frame.clone() }
+
+    fn trip_band(&self) -> Option<(f64, f64)> {
+        let (mean, std_dev) = self.last?;
+        trip_band(mean, std_dev, &self.config)
+    }
+
+    fn restore(&mut self, history: &[f64]) {
+        history.iter().for_each(|&v| self.window.push(v, self.config.window));
+    }
+
+    fn set_config(&mut self, config: ThermalConfig) { self.config = config; }
+
+    fn reset(&mut self) {
+        *self = Self::new(self.config);
+    }
+}
+
+// Same test against an exponentially weighted mean and variance, which react faster than a window.
+pub struct EwmaDetector {
+    pub config: ThermalConfig,
+    pub stats: EwmaStats,
+    pub window: SampleWindow,
+    pub freeze_frame: Option<FreezeFrame>,
+}
+
+impl EwmaDetector {
+    pub fn new(config: ThermalConfig) -> Self {
+        Self { config, stats: EwmaStats::default(), window: SampleWindow::default(), freeze_frame: None }
+    }
+}
+
+impl FaultDetector for EwmaDetector {
+    fn evaluate(&mut self, sample: f64, _ts: Instant) -> Option<DtcCode> {
+        let cfg = &self.config;
+        self.window.push(sample, cfg.window);
+        let (mean, std_dev) = self.stats.update(sample, cfg.alpha);
+        if self.window.samples.len() < cfg.min_window || !self.stats.warmed_up(cfg.alpha) { return None; }
+        if outside(sample, trip_band(mean, std_dev, cfg)) {
+            self.freeze_frame = Some(FreezeFrame { history: self.window.to_vec(), mean, std_dev });
+            return Some(DtcCode::CellImbalance);
+        }
+        None
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "EWMA (alpha {:.2}): mean {:.3}V  std-dev {:.3}V  trigger > {:.1} sigma",
+            self.config.alpha, self.stats.mean.unwrap_or_default(), self.stats.variance.sqrt(), self.config.sigma,
+        )
+    }
+
+    fn freeze_frame(&self) -> Option<FreezeFrame> { self.freeze_frame.clone() }
+
+    fn trip_band(&self) -> Option<(f64, f64)> {
+        let cfg = &self.config;
+        if self.window.samples.len() < cfg.min_window || !self.stats.warmed_up(cfg.alpha) { return None; }
+        trip_band(self.stats.mean?, self.stats.variance.sqrt(), cfg)
+    }
+
+    fn restore(&mut self, history: &[f64]) {
+        for &v in history {
+            self.window.push(v, self.config.window);
+            self.stats.update(v, self.config.alpha);
+        }
+    }
+
+    fn set_config(&mut self, config: ThermalConfig) { self.config = config; }
+
+    fn reset(&mut self) {
+        *self = Self::new(self.config);
+    }
+}
+
+// Fires on a voltage drop faster than `max_drop_rate` V/s between consecutive samples.
+pub struct DischargeRateDetector {
+    pub config: ThermalConfig,
+    pub previous: Option<(Instant, f64)>,
+    pub rate: f64,
+    pub window: SampleWindow,
+}
+
+impl DischargeRateDetector {
+    pub fn new(config: ThermalConfig) -> Self {
+        Self { config, previous: None, rate: 0.0, window: SampleWindow::default() }
+    }
+}
+
+impl FaultDetector for DischargeRateDetector {
+    fn evaluate(&mut self, sample: f64, ts: Instant) -> Option<DtcCode> {
+        self.window.push(sample, self.config.window);
+        let (t0, v0) = self.previous.replace((ts, sample))?;
+        let elapsed = ts.duration_since(t0).as_secs_f64();
+        if elapsed <= 0.0 { return None; }
+        self.rate = (v0 - sample) / elapsed;
+        (self.rate > self.config.max_drop_rate).then_some(DtcCode::RapidDischarge)
+    }
+
+    fn describe(&self) -> String {
+        format!("dV/dt: last {:.2} V/s  alarm > {:.1} V/s", self.rate.max(0.0), self.config.max_drop_rate)
+    }
+
+    fn reading(&self, _sample: f64) -> String { format!("{:.2} V/s", self.rate) }
+
+    fn freeze_frame(&self) -> Option<FreezeFrame> { Some(FreezeFrame::capture(self.window.to_vec())) }
+
+    fn restore(&mut self, history: &[f64]) {
+        history.iter().for_each(|&v| self.window.push(v, self.config.window));
+    }
+
+    fn resume(&mut self, last: (Instant, f64)) {
+        self.previous = Some(last);
+    }
+
+    fn set_config(&mut self, config: ThermalConfig) { self.config = config; }
+
+    fn reset(&mut self) {
+        *self = Self::new(self.config);
+    }
+}
+
+pub fn thermal_detectors(config: ThermalConfig) -> Vec<Box<dyn FaultDetector>> {
+    let thermal: Box<dyn FaultDetector> = match config.detector {
+        ThermalDetector::Window => Box::new(SigmaDetector::new(config)),
+        ThermalDetector::Ewma => Box::new(EwmaDetector::new(config)),
+        ThermalDetector::Rate => return vec![Box::new(DischargeRateDetector::new(config))],
+    };
+    vec![thermal, Box::new(DischargeRateDetector::new(config))]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use rand_distr::{Distribution, Normal};
+
+    // Ten samples alternating around 4.0V (mean 4.0V, std-dev 0.1V) and room for one more.
+    fn steady(config: ThermalConfig) -> ThermalConfig {
+        ThermalConfig { window: 11, min_window: 11, ..config }
+    }
+
+    fn primed<D: FaultDetector>(mut detector: D) -> D {
+        detector.restore(&[3.9, 4.1, 3.9, 4.1, 3.9, 4.1, 3.9, 4.1, 3.9, 4.1]);
+        detector
+    }
+
+    // The window includes the sample under test, so a dip of `d` below the old mean leaves it
+    // exactly `sigma` std-devs out when d^2 = sigma^2 * n * var / (n - 1 - sigma^2).
+    fn sigma_boundary(config: &ThermalConfig) -> f64 {
+        let n = config.window as f64;
+        (config.sigma.powi(2) * n * 0.01 / (n - 1.0 - config.sigma.powi(2))).sqrt()
+    }
+
+    #[test]
+    fn sigma_detector_fires_only_past_sigma_std_devs() {
+        let config = steady(ThermalConfig::default());
+        let boundary = sigma_boundary(&config);
+        let now = Instant::now();
+        assert_eq!(primed(SigmaDetector::new(config)).evaluate(4.0 - boundary * 0.98, now), None);
+        assert_eq!(primed(SigmaDetector::new(config)).evaluate(4.0 - boundary * 1.02, now), Some(DtcCode::CellImbalance));
+        assert_eq!(primed(SigmaDetector::new(config)).evaluate(4.0 + boundary * 1.02, now), Some(DtcCode::CellImbalance));
+
+        let strict = ThermalConfig { sigma: 2.5, ..config };
+        let boundary = sigma_boundary(&strict);
+        assert_eq!(primed(SigmaDetector::new(strict)).evaluate(4.0 - boundary * 0.98, now), None);
+        assert_eq!(primed(SigmaDetector::new(strict)).evaluate(4.0 - boundary * 1.02, now), Some(DtcCode::CellImbalance));
+    }
+
+    #[test]
+    fn sigma_detector_stays_quiet_at_or_below_the_std_floor() {
+        let config = ThermalConfig { std_floor: 0.5, ..steady(ThermalConfig::default()) };
+        let mut detector = primed(SigmaDetector::new(config));
+        assert_eq!(detector.evaluate(3.0, Instant::now()), None);
+        assert_eq!(detector.trip_band(), None);
+    }
+
+    // A healthy cell: Gaussian noise around 3.9V with no fault in it, so every DTC is a false positive.
+    #[test]
+    fn ewma_raises_fewer_false_positives_than_the_window_on_noise() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let noise = Normal::new(3.9, 0.1).unwrap();
+        let series: Vec<f64> = (0..5000).map(|_| noise.sample(&mut rng)).collect();
+        let now = Instant::now();
+        let false_positives = |mut detector: Box<dyn FaultDetector>| series.iter().filter(|&&v| detector.evaluate(v, now).is_some()).count();
+        let config = ThermalConfig::default();
+        let window = false_positives(Box::new(SigmaDetector::new(config)));
+        let ewma = false_positives(Box::new(EwmaDetector::new(config)));
+        assert!(ewma > 0 && ewma * 3 < window * 2, "window {} vs EWMA {} false positives in {} samples", window, ewma, series.len());
+    }
+
+    #[test]
+    fn three_sample_window_evaluates_from_the_third_sample() {
+        let config = ThermalConfig { window: 3, min_window: 3, sigma: 1.0, ..ThermalConfig::default() };
+        let mut detector = SigmaDetector::new(config);
+        let now = Instant::now();
+        assert_eq!(detector.evaluate(4.0, now), None);
+        assert_eq!(detector.evaluate(4.2, now), None);
+        assert!(detector.last.is_none() && detector.trip_band().is_none());
+        assert_eq!(detector.evaluate(3.0, now), Some(DtcCode::CellImbalance));
+        assert!(detector.trip_band().is_some());
+        for sample in [4.0, 4.1, 4.0] {
+            detector.evaluate(sample, now);
+        }
+        assert_eq!(detector.window.to_vec(), [4.0, 4.1, 4.0]);
+    }
+
+    #[test]
+    fn thermal_detectors_follow_the_chosen_strategy() {
+        let kinds = |detector| -> Vec<String> {
+            thermal_detectors(ThermalConfig { detector, ..ThermalConfig::default() }).iter()
+                .map(|d| d.describe().split(':').next().unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(kinds(ThermalDetector::Window), ["Sliding window", "dV/dt"]);
+        assert_eq!(kinds(ThermalDetector::Ewma), ["EWMA (alpha 0.10)", "dV/dt"]);
+        assert_eq!(kinds(ThermalDetector::Rate), ["dV/dt"]);
+    }
+
+    #[test]
+    fn ewma_detector_fires_on_a_dip_after_warming_up() {
+        let config = ThermalConfig { detector: ThermalDetector::Ewma, ..ThermalConfig::default() };
+        let mut detector = EwmaDetector::new(config);
+        let now = Instant::now();
+        let noisy = (0..30).map(|i| if i % 2 == 0 { 3.9 } else { 4.1 });
+        assert!(noisy.map(|v| detector.evaluate(v, now)).all(|dtc| dtc.is_none()));
+        assert!(detector.trip_band().is_some());
+        assert_eq!(detector.evaluate(3.5, now), Some(DtcCode::CellImbalance));
+    }
+
+    #[test]
+    fn ewma_detector_waits_for_warm_up() {
+        let mut detector = EwmaDetector::new(ThermalConfig::default());
+        let now = Instant::now();
+        for v in [3.9, 4.1, 3.9, 4.1, 3.9] {
+            assert_eq!(detector.evaluate(v, now), None);
+        }
+        assert_eq!(detector.evaluate(2.5, now), None);
+        assert_eq!(detector.trip_band(), None);
+    }
+
+    #[test]
+    fn discharge_rate_detector_fires_on_a_fast_drop_only() {
+        let mut detector = DischargeRateDetector::new(ThermalConfig::default());
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        assert_eq!(detector.evaluate(4.0, at(0)), None);
+        // 0.1V in one second is well under the default 2 V/s.
+        assert_eq!(detector.evaluate(3.9, at(1000)), None);
+        assert_eq!(detector.evaluate(3.3, at(1250)), Some(DtcCode::RapidDischarge));
+        assert_eq!(detector.reading(3.3), "2.40 V/s");
+        // Recovering voltage is never a discharge.
+        assert_eq!(detector.evaluate(4.0, at(1500)), None);
+    }
+}
@@ -0,0 +1,72 @@
+//! Per-user defaults for the command-line options, kept in `~/.config/rusty-adas/config.toml`.
+use std::io;
+use std::path::{Path, PathBuf};
+use clap::ValueEnum;
+use crate::Error;
+
+pub const DOTFILE: &str = "rusty-adas/config.toml";
+
+pub struct Dotfile {
+    pub path: PathBuf,
+    pub settings: toml::Table,
+}
+
+impl Dotfile {
+    pub fn default_path() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(base.join(DOTFILE))
+    }
+
+    // A missing file is the same as an empty one.
+    pub fn load(path: PathBuf) -> Result<Self, Error> {
+        let settings = match std::fs::read_to_string(&path) {
+            Ok(text) => text.parse().map_err(|e| Error::Config(format!("{}: {}", path.display(), e)))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => toml::Table::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { path, settings })
+    }
+
+    // Each key becomes its `--kebab-case` flag. These go ahead of the real arguments, so any
+    // option also given on the command line overrides the file.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for (key, value) in &self.settings {
+            let flag = format!("--{}", key.replace('_', "-"));
+            let values = match value {
+                toml::Value::Array(items) => items.clone(),
+                other => vec![other.clone()],
+            };
+            for value in values {
+                match value {
+                    toml::Value::Boolean(true) => args.push(flag.clone()),
+                    toml::Value::Boolean(false) => {}
+                    toml::Value::String(s) => args.extend([flag.clone(), s]),
+                    other => args.extend([flag.clone(), other.to_string()]),
+                }
+            }
+        }
+        args
+    }
+
+    pub fn set(&mut self, key: &str, value: impl Into<toml::Value>) {
+        self.settings.insert(key.to_string(), value.into());
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let text = toml::to_string(&self.settings).map_err(|e| Error::Config(e.to_string()))?;
+        std::fs::write(&self.path, text)?;
+        Ok(())
+    }
+}
+
+// The spelling clap accepts for a value-enum option, e.g. "high-contrast".
+pub fn value_name(value: impl ValueEnum) -> String {
+    value.to_possible_value().map(|v| v.get_name().to_string()).unwrap_or_default()
+}
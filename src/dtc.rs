@@ -0,0 +1,442 @@
+//! CAN identifiers, DTC codes and the per-ECU fault state machine.
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use crate::NO_RESPONSE;
+
+pub const PID_VEHICLE_SPEED: u8 = 0x0D;
+pub const PID_CELL_VOLTAGE: u8 = 0xA6;
+pub const PID_TRACKING_CONFIDENCE: u8 = 0xA7;
+pub const PID_TIRE_PRESSURE: u8 = 0xA8;
+pub const SUPPORTED_PIDS: [u8; 4] = [PID_VEHICLE_SPEED, PID_CELL_VOLTAGE, PID_TRACKING_CONFIDENCE, PID_TIRE_PRESSURE];
+pub const DEFAULT_CONFIRM_CYCLES: u8 = 3;
+pub const DEFAULT_HEAL_CYCLES: u8 = 5;
+pub const HEALTH_DECAY: f64 = 0.1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "u32")]
+pub enum CanId {
+    Standard(u16),
+    Extended(u32),
+}
+
+impl CanId {
+    pub const STANDARD_MAX: u32 = 0x7FF;
+    pub const EXTENDED_MAX: u32 = 0x1FFF_FFFF;
+    // Same flag Linux uses in can_id; only needed to tell extended IDs <= 0x7FF apart in the blackbox.
+    pub const EXTENDED_FLAG: u32 = 0x8000_0000;
+
+    pub fn new(raw: u32) -> Result<Self, String> {
+        match raw {
+            0..=Self::STANDARD_MAX => Ok(CanId::Standard(raw as u16)),
+            _ => CanId::extended(raw),
+        }
+    }
+
+    pub fn extended(raw: u32) -> Result<Self, String> {
+        if raw > Self::EXTENDED_MAX {
+            return Err(format!("CAN ID {:#X} does not fit in 29 bits", raw));
+        }
+        Ok(CanId::Extended(raw))
+    }
+
+    pub fn raw(self) -> u32 {
+        match self {
+            CanId::Standard(id) => id as u32,
+            CanId::Extended(id) => id,
+        }
+    }
+
+    pub fn stored(self) -> u32 {
+        match self {
+            CanId::Extended(id) if id <= Self::STANDARD_MAX => id | Self::EXTENDED_FLAG,
+            id => id.raw(),
+        }
+    }
+
+    pub fn from_stored(value: u32) -> Result<Self, String> {
+        match value & Self::EXTENDED_FLAG {
+            0 => CanId::new(value),
+            _ => CanId::extended(value & !Self::EXTENDED_FLAG),
+        }
+    }
+}
+
+impl TryFrom<u32> for CanId {
+    type Error = String;
+
+    fn try_from(raw: u32) -> Result<Self, String> { CanId::new(raw) }
+}
+
+impl std::str::FromStr for CanId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let hex = s.trim_start_matches("0x").trim_start_matches("0X");
+        let raw = u32::from_str_radix(hex, 16).map_err(|_| format!("'{}' is not a hex CAN ID", s))?;
+        CanId::new(raw)
+    }
+}
+
+impl std::fmt::Display for CanId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CanId::Standard(id) => write!(f, "{:#05X}", id),
+            CanId::Extended(id) => write!(f, "{:#010X}", id),
+        }
+    }
+}
+
+impl ToSql for CanId {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> { Ok(self.stored().into()) }
+}
+
+impl FromSql for CanId {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        CanId::from_stored(u32::column_result(value)?).map_err(|e| FromSqlError::Other(e.into()))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub enum DtcCode {
+    CellImbalance,
+    SensorBlind,
+    LowTirePressure,
+    WheelSpeedCircuit,
+    AbsPumpMotor,
+    RapidDischarge,
+    NavInvalidData,
+    CellSpread,
+    LostCommunication,
+    PerceptionDegraded,
+    LostCommunicationBcm,
+}
+
+impl DtcCode {
+    pub const ALL: [DtcCode; 11] = [
+        DtcCode::CellImbalance,
+        DtcCode::SensorBlind,
+        DtcCode::LowTirePressure,
+        DtcCode::WheelSpeedCircuit,
+        DtcCode::AbsPumpMotor,
+        DtcCode::RapidDischarge,
+        DtcCode::NavInvalidData,
+        DtcCode::CellSpread,
+        DtcCode::LostCommunication,
+        DtcCode::PerceptionDegraded,
+        DtcCode::LostCommunicationBcm,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DtcCode::CellImbalance => "P0A80",
+            DtcCode::SensorBlind => "C1A67",
+            DtcCode::LowTirePressure => "C2120",
+            DtcCode::WheelSpeedCircuit => "C0045",
+            DtcCode::AbsPumpMotor => "C1095",
+            DtcCode::RapidDischarge => "P0A7F",
+            DtcCode::NavInvalidData => "U0422",
+            DtcCode::CellSpread => "P0B3B",
+            DtcCode::LostCommunication => "U0100",
+            DtcCode::PerceptionDegraded => "C1A68",
+            DtcCode::LostCommunicationBcm => "U0140",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            DtcCode::CellImbalance => "Hybrid Battery Pack Deterioration",
+            DtcCode::SensorBlind => "ADAS Sensor Field of View Blocked",
+            DtcCode::LowTirePressure => "Low Tire Pressure",
+            DtcCode::WheelSpeedCircuit => "Wheel Speed Sensor Circuit",
+            DtcCode::AbsPumpMotor => "ABS Pump Motor Fault",
+            DtcCode::RapidDischarge => "Rapid Cell Discharge",
+            DtcCode::NavInvalidData => "Invalid Data Received From Navigation Unit",
+            DtcCode::CellSpread => "Battery Cell Voltage Spread Out Of Range",
+            DtcCode::LostCommunication => "Lost Communication With ECU",
+            DtcCode::PerceptionDegraded => "ADAS Sensor Performance Degraded",
+            DtcCode::LostCommunicationBcm => "Lost Communication With Body Control Module",
+        }
+    }
+
+    // A blocked camera is a nuisance; a battery heading for thermal runaway or a dead ECU is not.
+    pub fn default_severity(&self) -> Severity {
+        match self {
+            DtcCode::CellImbalance | DtcCode::RapidDischarge | DtcCode::AbsPumpMotor | DtcCode::LostCommunication => Severity::Critical,
+            DtcCode::CellSpread | DtcCode::LowTirePressure | DtcCode::WheelSpeedCircuit | DtcCode::PerceptionDegraded => Severity::Warning,
+            DtcCode::SensorBlind | DtcCode::NavInvalidData | DtcCode::LostCommunicationBcm => Severity::Info,
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.as_str() == code)
+    }
+
+    pub fn parse(msg: &str) -> Option<Self> {
+        let rest = &msg[msg.find("DTC ")? + "DTC ".len()..];
+        Self::from_code(rest.split(':').next()?.trim())
+    }
+}
+
+impl TryFrom<String> for DtcCode {
+    type Error = String;
+
+    fn try_from(code: String) -> Result<Self, String> {
+        DtcCode::from_code(&code).ok_or_else(|| format!("unknown DTC code '{}'", code))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    #[default]
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+// Built-in severities of the known codes with any configured ones on top; DTCs with a code
+// this build does not know, e.g. from a real CAN bus, get `default`.
+#[derive(Clone, Debug)]
+pub struct SeverityMap {
+    pub codes: HashMap<DtcCode, Severity>,
+    pub default: Severity,
+}
+
+impl Default for SeverityMap {
+    fn default() -> Self {
+        Self {
+            codes: DtcCode::ALL.into_iter().map(|code| (code, code.default_severity())).collect(),
+            default: Severity::default(),
+        }
+    }
+}
+
+impl SeverityMap {
+    pub fn with_overrides(codes: &HashMap<DtcCode, Severity>, default: Option<Severity>) -> Self {
+        let mut map = Self::default();
+        map.codes.extend(codes);
+        map.default = default.unwrap_or(map.default);
+        map
+    }
+
+    pub fn of(&self, code: DtcCode) -> Severity {
+        self.codes.get(&code).copied().unwrap_or(self.default)
+    }
+
+    // None for anything that is not a DTC. A silent ECU counts as its U0100.
+    pub fn classify(&self, msg: &str) -> Option<Severity> {
+        if msg == NO_RESPONSE {
+            return Some(self.of(DtcCode::LostCommunication));
+        }
+        if !msg.contains("DTC") { return None; }
+        Some(DtcCode::parse(msg).map_or(self.default, |code| self.of(code)))
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FreezeFrame {
+    pub history: Vec<f64>,
+    pub mean: f64,
+    pub std_dev: f64,
+}
+
+impl FreezeFrame {
+    pub fn capture(history: Vec<f64>) -> Self {
+        let n = history.len() as f64;
+        let mean = history.iter().sum::<f64>() / n;
+        let std_dev = (history.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n).sqrt();
+        Self { history, mean, std_dev }
+    }
+
+    pub fn summary(&self) -> String {
+        let samples: Vec<String> = self.history.iter().map(|v| format!("{:.2}", v)).collect();
+        format!("[{}] mean {:.3} sd {:.3}", samples.join(" "), self.mean, self.std_dev)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PidValue {
+    pub pid: u8,
+    pub name: &'static str,
+    pub value: f64,
+    pub unit: &'static str,
+}
+
+impl PidValue {
+    pub fn new(pid: u8, name: &'static str, value: f64, unit: &'static str) -> Self {
+        Self { pid, name, value, unit }
+    }
+
+    pub fn label(&self) -> String {
+        format!("PID {:#04X} {}: {:.2} {}", self.pid, self.name, self.value, self.unit)
+    }
+
+    // Mode 01 style data bytes: speed and confidence are one byte, voltage is mV and pressure kPa in two.
+    pub fn raw(&self) -> Vec<u8> {
+        match self.pid {
+            PID_CELL_VOLTAGE => ((self.value * 1000.0).round().clamp(0.0, u16::MAX as f64) as u16).to_be_bytes().to_vec(),
+            PID_TIRE_PRESSURE => (self.value.round().clamp(0.0, u16::MAX as f64) as u16).to_be_bytes().to_vec(),
+            _ => vec![self.value.round().clamp(0.0, u8::MAX as f64) as u8],
+        }
+    }
+
+    pub fn scaled(&self) -> f64 {
+        let raw = self.raw();
+        match self.pid {
+            PID_CELL_VOLTAGE => u16::from_be_bytes([raw[0], raw[1]]) as f64 / 1000.0,
+            PID_TIRE_PRESSURE => u16::from_be_bytes([raw[0], raw[1]]) as f64,
+            _ => raw[0] as f64,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FaultState {
+    Ok,
+    Pending(u8),
+    Confirmed,
+    Healing(u8),
+}
+
+impl FaultState {
+    pub fn label(&self) -> String {
+        match self {
+            FaultState::Ok => "OK".to_string(),
+            FaultState::Pending(n) => format!("Pending ({} failed cycles)", n),
+            FaultState::Confirmed => "Confirmed".to_string(),
+            FaultState::Healing(n) => format!("Healing ({} good cycles)", n),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct FaultTracker {
+    pub state: FaultState,
+    pub confirm_after: u8,
+    pub heal_after: u8,
+    pub health: f64,
+}
+
+impl Default for FaultTracker {
+    fn default() -> Self {
+        Self { state: FaultState::Ok, confirm_after: DEFAULT_CONFIRM_CYCLES, heal_after: DEFAULT_HEAL_CYCLES, health: 100.0 }
+    }
+}
+
+impl FaultTracker {
+    pub fn observe(&mut self, detected: bool) -> FaultState {
+        self.state = match (self.state, detected) {
+            (FaultState::Ok, false) => FaultState::Ok,
+            (FaultState::Ok, true) => self.pending(1),
+            (FaultState::Pending(n), true) => self.pending(n.saturating_add(1)),
+            (FaultState::Pending(_), false) => FaultState::Ok,
+            (FaultState::Confirmed | FaultState::Healing(_), true) => FaultState::Confirmed,
+            (FaultState::Confirmed, false) => self.healing(1),
+            (FaultState::Healing(n), false) => self.healing(n.saturating_add(1)),
+        };
+        let target = match (detected, self.state) {
+            (true, _) => 0.0,
+            (false, FaultState::Healing(_)) => 50.0,
+            (false, _) => 100.0,
+        };
+        self.health += HEALTH_DECAY * (target - self.health);
+        self.state
+    }
+
+    pub fn health(&self) -> u8 {
+        self.health.round().clamp(0.0, 100.0) as u8
+    }
+
+    pub fn pending(&self, failed: u8) -> FaultState {
+        if failed >= self.confirm_after { FaultState::Confirmed } else { FaultState::Pending(failed) }
+    }
+
+    pub fn healing(&self, good: u8) -> FaultState {
+        if good >= self.heal_after { FaultState::Ok } else { FaultState::Healing(good) }
+    }
+
+    pub fn reset(&mut self) {
+        self.state = FaultState::Ok;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_ids_up_to_eleven_bits_parse_and_display_as_standard() {
+        let id: CanId = "0x186".parse().unwrap();
+        assert_eq!(id, CanId::Standard(0x186));
+        assert_eq!(id.to_string(), "0x186");
+        let id: CanId = "0x18FEF100".parse().unwrap();
+        assert_eq!(id, CanId::Extended(0x18FE_F100));
+        assert_eq!(id.to_string(), "0x18FEF100");
+        assert_eq!(CanId::from_stored(CanId::extended(0x186).unwrap().stored()), Ok(CanId::Extended(0x186)));
+    }
+
+    fn tracker() -> FaultTracker {
+        FaultTracker { confirm_after: 3, heal_after: 3, ..FaultTracker::default() }
+    }
+
+    #[test]
+    fn a_good_cycle_drops_a_pending_fault_back_to_ok() {
+        let mut fault = tracker();
+        assert_eq!(fault.observe(true), FaultState::Pending(1));
+        assert_eq!(fault.observe(true), FaultState::Pending(2));
+        assert_eq!(fault.observe(false), FaultState::Ok);
+        assert_eq!(fault.observe(true), FaultState::Pending(1));
+    }
+
+    #[test]
+    fn a_relapse_while_healing_reconfirms_the_fault() {
+        let mut fault = tracker();
+        for _ in 0..3 {
+            fault.observe(true);
+        }
+        assert_eq!(fault.state, FaultState::Confirmed);
+        assert_eq!(fault.observe(false), FaultState::Healing(1));
+        assert_eq!(fault.observe(false), FaultState::Healing(2));
+        assert_eq!(fault.observe(true), FaultState::Confirmed);
+        // The relapse restarts the heal count, so it takes the full three good cycles again.
+        assert_eq!(fault.observe(false), FaultState::Healing(1));
+        assert_eq!(fault.observe(false), FaultState::Healing(2));
+        assert_eq!(fault.observe(false), FaultState::Ok);
+    }
+
+    #[test]
+    fn health_decays_toward_zero_while_failing_and_half_while_healing() {
+        let mut fault = tracker();
+        fault.observe(true);
+        assert!((fault.health - 90.0).abs() < 1e-9);
+        for _ in 0..200 {
+            fault.observe(true);
+        }
+        assert_eq!(fault.health(), 0);
+        // A Healing cycle pulls toward 50; the last good cycle reaches Ok and aims for 100 again.
+        fault.observe(false);
+        assert!((fault.health - 5.0).abs() < 1e-6);
+        fault.observe(false);
+        fault.observe(false);
+        assert_eq!(fault.state, FaultState::Ok);
+        fault.health = 80.0;
+        fault.observe(false);
+        assert!((fault.health - 82.0).abs() < 1e-9);
+        fault.state = FaultState::Healing(1);
+        fault.health = 80.0;
+        fault.observe(false);
+        assert!((fault.health - 77.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,78 @@
+//! Streaming sensor records as JSON lines to clients of a Unix domain socket.
+use std::io::{self, Write};
+use std::path::Path;
+use std::thread;
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use crate::{lock, SHUTDOWN_POLL};
+use crate::dtc::{CanId, DtcCode, FaultState};
+
+const CLIENT_QUEUE: usize = 256;
+
+#[derive(Serialize)]
+struct FaultRecord<'a> {
+    can_id: String,
+    code: Option<&'a str>,
+    message: &'a str,
+    fault: String,
+    timestamp: String,
+}
+
+#[derive(Clone)]
+pub struct Publisher {
+    clients: Arc<Mutex<Vec<mpsc::SyncSender<Arc<str>>>>>,
+}
+
+impl Publisher {
+    // Each client has its own bounded queue, so a slow reader only loses its own records.
+    pub fn publish(&self, can_id: CanId, message: &str, fault: FaultState, at: DateTime<Utc>) {
+        let record = FaultRecord {
+            can_id: format!("{}", can_id),
+            code: DtcCode::parse(message).map(|c| c.as_str()),
+            message,
+            fault: fault.label(),
+            timestamp: at.to_rfc3339_opts(SecondsFormat::Millis, true),
+        };
+        let Ok(mut line) = serde_json::to_string(&record) else { return };
+        line.push('\n');
+        let line: Arc<str> = line.into();
+        lock(&self.clients).retain(|client| !matches!(client.try_send(Arc::clone(&line)), Err(mpsc::TrySendError::Disconnected(_))));
+    }
+}
+
+fn serve(mut stream: UnixStream, lines: mpsc::Receiver<Arc<str>>) {
+    for line in lines {
+        if stream.write_all(line.as_bytes()).is_err() { break; }
+    }
+}
+
+pub fn bind(path: &Path, shutdown: Arc<AtomicBool>) -> io::Result<(Publisher, thread::JoinHandle<()>)> {
+    if std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    listener.set_nonblocking(true)?;
+    let publisher = Publisher { clients: Arc::default() };
+    let clients = Arc::clone(&publisher.clients);
+    let path = path.to_path_buf();
+    let handle = thread::spawn(move || {
+        while !shutdown.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    if stream.set_nonblocking(false).is_err() { continue; }
+                    let (tx, rx) = mpsc::sync_channel(CLIENT_QUEUE);
+                    lock(&clients).push(tx);
+                    thread::spawn(move || serve(stream, rx));
+                }
+                Err(_) => thread::sleep(SHUTDOWN_POLL),
+            }
+        }
+        lock(&clients).clear();
+        let _ = std::fs::remove_file(&path);
+    });
+    Ok((publisher, handle))
+}
@@ -0,0 +1,87 @@
+//! Engine of the rustyadv EV diagnostic monitor: the simulated ECUs, their fault detectors,
+//! the SQLite blackbox and the dashboard state. The `rustyadv` binary draws it in a terminal.
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use chrono::{DateTime, Utc};
+use crate::dtc::{CanId, FaultState, FreezeFrame};
+use crate::sensors::Position;
+
+pub mod app_state;
+pub mod config;
+pub mod dashboard;
+pub mod detectors;
+pub mod dotfile;
+pub mod dtc;
+pub mod sensors;
+pub mod storage;
+pub mod ui;
+#[cfg(feature = "socketcan")]
+pub mod can_bus;
+#[cfg(feature = "can-dbc")]
+pub mod dbc;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "syslog")]
+pub mod system_log;
+pub mod on_dtc;
+#[cfg(unix)]
+pub mod ipc;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+
+pub const SHUTDOWN_POLL: Duration = Duration::from_millis(50);
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(1000);
+pub const NO_RESPONSE: &str = "NO RESPONSE (timeout)";
+
+// Failures of the engine; the binary still boxes whatever reaches main.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("blackbox: {0}")]
+    Db(#[from] rusqlite::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    Config(String),
+    #[error("{0}")]
+    Export(String),
+    #[error("{0}")]
+    Clip(String),
+    #[error("{0}")]
+    Console(String),
+}
+
+pub enum SensorUpdate {
+    Status {
+        id: CanId,
+        status: String,
+        is_dtc: bool,
+        fault: FaultState,
+        at: DateTime<Utc>,
+        freeze: Option<FreezeFrame>,
+        acknowledged: bool,
+        position: Option<Position>,
+    },
+    Notice(String),
+    ReplayComplete,
+}
+
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn sleep_unless_shutdown(shutdown: &AtomicBool, dur: Duration) -> bool {
+    let deadline = Instant::now() + dur;
+    while !shutdown.load(Ordering::Relaxed) {
+        let now = Instant::now();
+        if now >= deadline { return true; }
+        thread::sleep((deadline - now).min(SHUTDOWN_POLL));
+    }
+    false
+}
@@ -1,202 +1,590 @@
 use std::io;
+use std::collections::HashMap;
+use std::path::PathBuf;
+#[cfg(any(feature = "metrics", feature = "websocket"))]
+use std::net::{IpAddr, SocketAddr};
+use std::process::ExitCode;
+#[cfg(not(feature = "async"))]
 use std::thread;
 use std::time::Duration;
-use std::sync::{Arc, Mutex};
-use rusqlite::{params, Connection};
-use rand::Rng;
-
-use ratatui::{
-    backend::CrosstermBackend,
-    widgets::{Block, Borders, List, ListItem},
-    layout::{Layout, Constraint, Direction},
-    Terminal,
-};
-use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
-    execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-};
-
-struct AppState {
-    logs: Vec<String>,
-    sensor_status: Vec<(u32, String)>,
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use rusqlite::Connection;
+
+use rustyadv::{lock, SensorUpdate};
+#[cfg(not(feature = "async"))]
+use rustyadv::sleep_unless_shutdown;
+use rustyadv::app_state::{write_summary, AppState, DEFAULT_DEDUP_WINDOW_SECS, DEFAULT_FPS, DEFAULT_LIVENESS_POLLS, DEFAULT_LOG_CAPACITY, EventLog, LogFormat, PanelLayout, ThemeName};
+use rustyadv::config::{load_sensors_file, ConfigSource};
+use rustyadv::dashboard::{run_headless, run_tui};
+use rustyadv::detectors::{ThermalConfig, ThermalDetector};
+use rustyadv::dotfile::{value_name, Dotfile};
+use rustyadv::dtc::{CanId, SeverityMap};
+use rustyadv::sensors::{is_enabled, parse_injection, parse_tick_range, record_status, FaultInjection, InjectedComponent, Outbox, SentinelComponent, TickRange};
+use rustyadv::storage::{export_clip, export_influx, init_db, load_state, open_blackbox, parse_downsample, record_firmware, recorded_buses, replay_sensor_ids, save_state, spawn_db_writer, spawn_replay, upgrade_schema, DB_QUEUE_CAPACITY, DbCommand, Downsample, LatencyHistogram, Retention, SCHEMA_VERSION};
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Multi-threaded EV diagnostic monitor", args_override_self = true)]
+struct Args {
+    #[arg(long, default_value = "blackbox.db", help = "SQLite blackbox path (use :memory: for an ephemeral run)")]
+    db: String,
+    #[arg(long, value_parser = parse_tick_range, help = "Override every sensor's poll interval, e.g. 200 or 500..1500")]
+    tick_ms: Option<TickRange>,
+    #[arg(long, help = "Run headless: skip the dashboard and print DTCs to stdout")]
+    no_tui: bool,
+    #[arg(long, help = "Delete blackbox rows older than this many days")]
+    retain_days: Option<u32>,
+    #[arg(long, help = "Delete non-DTC rows older than this many days (defaults to --retain-days)")]
+    retain_normal_days: Option<u32>,
+    #[arg(long, value_parser = parse_downsample, help = "Write only 1 in N normal readings per sensor (e.g. 10) or one every N seconds (e.g. 30s); DTCs are always written")]
+    db_downsample: Option<Downsample>,
+    #[arg(long, help = "VACUUM the blackbox every this many hours")]
+    vacuum_hours: Option<u64>,
+    #[arg(long, help = "Load the sensor topology from a TOML file (see sensors.toml)")]
+    config: Option<PathBuf>,
+    #[arg(long, help = "Replay a recorded blackbox database instead of running live sensors")]
+    replay: Option<PathBuf>,
+    #[arg(long, default_value_t = 1.0, value_parser = parse_speed, help = "Replay speed multiplier")]
+    speed: f64,
+    #[arg(long, requires_all = ["clip_from", "clip_to"], help = "Copy the --db rows between --clip-from and --clip-to into a new replayable blackbox, then exit")]
+    clip: Option<PathBuf>,
+    #[arg(long, value_parser = parse_clip_time, help = "Start of the clip, local time (YYYY-MM-DD HH:MM:SS[.mmm]) or RFC 3339")]
+    clip_from: Option<DateTime<Utc>>,
+    #[arg(long, value_parser = parse_clip_time, help = "End of the clip, local time (YYYY-MM-DD HH:MM:SS[.mmm]) or RFC 3339")]
+    clip_to: Option<DateTime<Utc>>,
+    #[arg(long, help = "Write the numeric readings in the --db blackbox to this file as InfluxDB line protocol, then exit")]
+    export_influx: Option<PathBuf>,
+    #[arg(long, value_enum, default_value_t = ThermalDetector::Window, help = "Thermal runaway detector used by the BMS")]
+    thermal_detector: ThermalDetector,
+    #[arg(long, default_value_t = 0.1, value_parser = parse_alpha, help = "EWMA smoothing factor, 0 < alpha <= 1")]
+    ewma_alpha: f64,
+    #[arg(long, default_value_t = 10, value_parser = parse_window, help = "Voltage samples kept per BMS for the thermal detector")]
+    thermal_window: usize,
+    #[arg(long, value_parser = parse_window, help = "Samples a BMS needs before the thermal detector evaluates (default: 5, capped at --thermal-window)")]
+    thermal_min_window: Option<usize>,
+    #[arg(long, default_value_t = 2.0, value_parser = parse_drop_rate, help = "Cell voltage drop rate in V/s that raises a rapid discharge DTC")]
+    max_drop_rate: f64,
+    #[arg(long, value_parser = parse_probability, help = "Simulated fault probability for every sensor, 0.0..=1.0 (overrides the config)")]
+    fault_rate: Option<f64>,
+    #[arg(long, value_parser = parse_injection, help = "Drive a sensor through a fixed good/bad cycle pattern, e.g. 0x186:3good,1bad (repeatable)")]
+    inject: Vec<FaultInjection>,
+    #[arg(long, help = "Vehicle identifier recorded with this session in the blackbox")]
+    vehicle_id: Option<String>,
+    #[arg(long, value_enum, default_value_t = PanelLayout::Horizontal, help = "Dashboard panel layout")]
+    layout: PanelLayout,
+    #[arg(long, help = "Mark ECU rows with unicode symbols instead of ASCII tags")]
+    icons: bool,
+    #[arg(long, value_enum, default_value_t = ThemeName::Dark, help = "Dashboard color theme")]
+    theme: ThemeName,
+    #[arg(long, default_value_t = DEFAULT_LIVENESS_POLLS, value_parser = clap::value_parser!(u32).range(1..), help = "Flag a sensor as not responding after this many missed poll intervals")]
+    liveness_polls: u32,
+    #[arg(long, default_value_t = DEFAULT_DEDUP_WINDOW_SECS, help = "Collapse repeats of a sensor's DTC into one log line when they arrive within this many seconds (0 to disable)")]
+    dedup_window: u64,
+    #[arg(long, default_value_t = DEFAULT_FPS, value_parser = clap::value_parser!(u32).range(1..=60), help = "Maximum dashboard frames per second; an idle dashboard redraws once a second")]
+    fps: u32,
+    #[arg(long, default_value_t = DEFAULT_LOG_CAPACITY, value_parser = parse_log_capacity, help = "Log entries kept in the dashboard's scrollback")]
+    log_capacity: usize,
+    #[arg(long, value_enum, default_value_t = LogFormat::Text, help = "Event log format")]
+    log_format: LogFormat,
+    #[arg(long, help = "Write the event log to this file (required for JSON logging in TUI mode)")]
+    log_file: Option<PathBuf>,
+    #[cfg(feature = "metrics")]
+    #[arg(long, help = "Serve Prometheus metrics on this port at /metrics")]
+    metrics_port: Option<u16>,
+    #[cfg(feature = "metrics")]
+    #[arg(long, default_value = "127.0.0.1", help = "Address the metrics server listens on (0.0.0.0 for every interface)")]
+    metrics_bind: IpAddr,
+    #[cfg(feature = "mqtt")]
+    #[arg(long, value_parser = rustyadv::mqtt::parse_broker, help = "Publish confirmed DTCs to this MQTT broker (host:port)")]
+    mqtt_broker: Option<(String, u16)>,
+    #[cfg(feature = "mqtt")]
+    #[arg(long, default_value = rustyadv::mqtt::DEFAULT_TOPIC, help = "MQTT topic for confirmed DTCs")]
+    mqtt_topic: String,
+    #[arg(long, help = "Stream DTCs as JSON lines to clients of a Unix domain socket at this path")]
+    ipc_socket: Option<PathBuf>,
+    #[arg(long, help = "Run this shell command on every confirmed DTC, with CAN_ID, DTC_CODE and TIMESTAMP set (rate-limited)")]
+    on_dtc: Option<String>,
+    #[cfg(feature = "websocket")]
+    #[arg(long, help = "Broadcast live sensor statuses to WebSocket clients on this port")]
+    ws_port: Option<u16>,
+    #[cfg(feature = "websocket")]
+    #[arg(long, default_value = "127.0.0.1", help = "Address the WebSocket server listens on (0.0.0.0 for every interface)")]
+    ws_bind: IpAddr,
+    #[cfg(feature = "syslog")]
+    #[arg(long, help = "Mirror DTC events to the local syslog")]
+    syslog: bool,
+    #[cfg(feature = "syslog")]
+    #[arg(long, default_value = "daemon", value_parser = rustyadv::system_log::parse_facility, help = "Syslog facility, e.g. daemon or local0")]
+    syslog_facility: syslog::Facility,
+    #[cfg(feature = "socketcan")]
+    #[arg(long, default_value = rustyadv::can_bus::DEFAULT_IFACE, help = "SocketCAN interface to read frames from")]
+    can_iface: String,
+    #[cfg(feature = "can-dbc")]
+    #[arg(long, help = "Decode CAN frames with the signals in this DBC file")]
+    dbc: Option<PathBuf>,
 }
 
-impl AppState {
-    fn new() -> Self {
-        Self {
-            logs: Vec::new(),
-            sensor_status: vec![
-                (0x186A, "Initializing...".to_string()),
-                (0x2901, "Initializing...".to_string()),
-                (0x186B, "Initializing...".to_string()),
-                (0x2902, "Initializing...".to_string()),
-            ],
-        }
+fn parse_alpha(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(alpha) if alpha > 0.0 && alpha <= 1.0 => Ok(alpha),
+        _ => Err(format!("alpha must be in (0, 1], got {}", s)),
     }
+}
 
-    fn add_log(&mut self, msg: String) {
-        self.logs.push(msg);
-        if self.logs.len() > 20 {
-            self.logs.remove(0);
-        }
+fn parse_window(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("window must be a positive number of samples, got {}", s)),
     }
+}
 
-    fn update_sensor(&mut self, id: u32, status: String) {
-        if let Some(s) = self.sensor_status.iter_mut().find(|(sid, _)| *sid == id) {
-            s.1 = status;
-        }
+fn parse_log_capacity(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("log capacity must be a positive number of entries, got {}", s)),
     }
 }
 
-trait SentinelComponent: Send + Sync {
-    fn check_status(&self) -> String;
-    fn get_id(&self) -> u32;
+fn parse_probability(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(p) if (0.0..=1.0).contains(&p) => Ok(p),
+        _ => Err(format!("probability must be in 0.0..=1.0, got {}", s)),
+    }
 }
 
-struct BMS_ECU {
-    can_id: u32,
-    history: Mutex<Vec<f64>>,
+fn parse_drop_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+        _ => Err(format!("drop rate must be a positive number of V/s, got {}", s)),
+    }
 }
 
-impl BMS_ECU {
-    fn detect_thermal_runaway(&self, cell_voltage: f64) -> bool {
-        let mut data = self.history.lock().unwrap();
-        if data.len() >= 10 { data.remove(0); }
-        data.push(cell_voltage);
-        
-        if data.len() < 5 { return false; }
-
-        let sum: f64 = data.iter().sum();
-        let mean = sum / data.len() as f64;
-        let variance: f64 = data.iter().map(|v| (mean - *v).powi(2)).sum::<f64>() / data.len() as f64;
-        let std_dev = variance.sqrt();
+fn parse_speed(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(speed) if speed.is_finite() && speed > 0.0 => Ok(speed),
+        _ => Err(format!("speed must be a positive number, got {}", s)),
+    }
+}
 
-        std_dev > 0.05 && (cell_voltage - mean).abs() > (2.0 * std_dev)
+fn parse_clip_time(s: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(at) = DateTime::parse_from_rfc3339(s) {
+        return Ok(at.with_timezone(&Utc));
     }
+    let naive = chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f")
+        .map_err(|_| format!("expected YYYY-MM-DD HH:MM:SS[.mmm] or RFC 3339, got {}", s))?;
+    naive.and_local_timezone(chrono::Local).earliest()
+        .map(|at| at.with_timezone(&Utc))
+        .ok_or_else(|| format!("{} does not exist in the local time zone", s))
 }
 
-impl SentinelComponent for BMS_ECU {
-    fn check_status(&self) -> String {
-        let mut rng = rand::thread_rng();
-        let voltage: f64 = if rng.gen_bool(0.1) { 2.5 } else { rng.gen_range(3.7..4.1) };
+#[cfg(feature = "async")]
+mod async_runtime {
+    use super::*;
+    use rustyadv::SHUTDOWN_POLL;
+    use tokio::runtime::Runtime;
+    use tokio::sync::watch;
+    use tokio::task::JoinHandle;
 
-        if self.detect_thermal_runaway(voltage) {
-            format!("DTC P0A80: Cell Imbalance Detected! ({:.2}V)", voltage)
-        } else {
-            format!("Cell Voltage: {:.2}V (Optimal)", voltage)
-        }
+    pub fn spawn_sensors(
+        runtime: &Runtime,
+        sensors: &Arc<Vec<Box<dyn SentinelComponent>>>,
+        tick: Option<TickRange>,
+        outbox: &Outbox,
+        shutdown: Arc<AtomicBool>,
+    ) -> Vec<JoinHandle<()>> {
+        let (stop_tx, stop_rx) = watch::channel(false);
+        runtime.spawn(async move {
+            while !shutdown.load(Ordering::Relaxed) {
+                tokio::time::sleep(SHUTDOWN_POLL).await;
+            }
+            let _ = stop_tx.send(true);
+        });
+
+        (0..sensors.len()).map(|i| {
+            let sensors = Arc::clone(sensors);
+            let outbox = outbox.clone();
+            let mut stop = stop_rx.clone();
+            runtime.spawn(async move {
+                let sensor = &sensors[i];
+                let mut interval = tokio::time::interval(sensor.poll_interval());
+                interval.tick().await;
+                loop {
+                    let wait = async {
+                        match tick {
+                            Some(tick) => tokio::time::sleep(tick.sample()).await,
+                            None => { interval.tick().await; }
+                        }
+                    };
+                    tokio::select! {
+                        _ = wait => {}
+                        _ = stop.wait_for(|&stopped| stopped) => break,
+                    }
+                    if !is_enabled(&outbox.enabled, sensor.get_id()) { continue; }
+                    record_status(&outbox, sensor.as_ref(), sensor.read());
+                }
+            })
+        })
+        .collect()
     }
-    fn get_id(&self) -> u32 { self.can_id }
 }
 
-struct ADAS_Computer {
-    can_id: u32,
-    module_name: String,
+// Parses the dotfile settings ahead of the real arguments, so the command line wins.
+fn parse_args(dotfile: Option<&Dotfile>) -> Args {
+    let file_args = dotfile.map(Dotfile::args).unwrap_or_default();
+    let mut argv = std::env::args_os();
+    let merged = argv.next().into_iter().chain(file_args.iter().map(Into::into)).chain(argv);
+    Args::try_parse_from(merged).unwrap_or_else(|e| {
+        if let Some(dotfile) = dotfile.filter(|_| e.use_stderr() && !file_args.is_empty()) {
+            eprintln!("note: defaults were read from {}", dotfile.path.display());
+        }
+        e.exit()
+    })
+}
+
+// The options `W` writes back to the dotfile, as the run was started.
+fn remember_settings(dotfile: &mut Dotfile, args: &Args) {
+    dotfile.set("theme", value_name(args.theme));
+    dotfile.set("icons", args.icons);
+    dotfile.set("fps", args.fps as i64);
+    dotfile.set("thermal_detector", value_name(args.thermal_detector));
+    dotfile.set("dedup_window", args.dedup_window as i64);
+    dotfile.set("log_capacity", args.log_capacity as i64);
+    if let Some(rate) = args.fault_rate {
+        dotfile.set("fault_rate", rate);
+    }
 }
 
-impl SentinelComponent for ADAS_Computer {
-    fn check_status(&self) -> String {
-        let mut rng = rand::thread_rng();
-        
-        if rng.gen_bool(0.1) {
-            "DTC C1A67: Sensor Blind / Occluded".to_string()
-        } else {
-            let confidence = rng.gen_range(95..100);
-            format!("Tracking [{}]: Confidence {}%", self.module_name, confidence)
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
         }
     }
-    fn get_id(&self) -> u32 { self.can_id }
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let conn = Connection::open("blackbox.db")?;
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS sensor_logs (id INTEGER PRIMARY KEY, sensor_id INTEGER, message TEXT, timestamp TEXT DEFAULT CURRENT_TIMESTAMP)",
-        [],
-    )?;
+fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let dotfile = Dotfile::default_path().map(Dotfile::load).transpose()?;
+    let args = parse_args(dotfile.as_ref());
+    if !args.no_tui && args.log_format == LogFormat::Json && args.log_file.is_none() {
+        return Err("--log-format json needs --log-file when the dashboard is running".into());
+    }
+    if let (Some(path), Some(from), Some(to)) = (&args.clip, args.clip_from, args.clip_to) {
+        let conn = Connection::open(&args.db)?;
+        init_db(&conn)?;
+        let rows = export_clip(&conn, path, from, to)?;
+        println!("Wrote {} rows from {} to {}", rows, args.db, path.display());
+        return Ok(());
+    }
+    if let Some(path) = &args.export_influx {
+        let conn = Connection::open(&args.db)?;
+        init_db(&conn)?;
+        let points = export_influx(&conn, path)?;
+        println!("Wrote {} points from {} to {}", points, args.db, path.display());
+        return Ok(());
+    }
+    let (conn, migrated, mut session) = match &args.replay {
+        Some(path) => {
+            let conn = Connection::open(path)?;
+            let migrated = upgrade_schema(&conn)?;
+            (conn, migrated, None)
+        }
+        None => {
+            let conn = Connection::open(&args.db)?;
+            let migrated = init_db(&conn)?;
+            let session = open_blackbox(&conn, args.vehicle_id.clone())?;
+            (conn, migrated, Some(session))
+        }
+    };
     let db_lock = Arc::new(Mutex::new(conn));
 
-    let app_state = Arc::new(Mutex::new(AppState::new()));
-
-    let sensors: Vec<Box<dyn SentinelComponent>> = vec![
-        Box::new(BMS_ECU { can_id: 0x186A, history: Mutex::new(Vec::new()) }), 
-        Box::new(ADAS_Computer { can_id: 0x2901, module_name: "Front_Radar".to_string() }),
-        Box::new(BMS_ECU { can_id: 0x186B, history: Mutex::new(Vec::new()) }),
-        Box::new(ADAS_Computer { can_id: 0x2902, module_name: "Lane_Cam".to_string() }),
-    ];
+    let (update_tx, update_rx) = mpsc::channel::<SensorUpdate>();
+    let (db_tx, db_rx) = mpsc::sync_channel::<DbCommand>(DB_QUEUE_CAPACITY);
+    let retention = match args.replay {
+        Some(_) => Retention::default(),
+        None => Retention {
+            dtc_days: args.retain_days,
+            normal_days: args.retain_normal_days.or(args.retain_days),
+            vacuum_every: args.vacuum_hours.map(|h| Duration::from_secs(h * 3600)),
+            downsample: args.db_downsample.unwrap_or_default(),
+        },
+    };
+    let latency = Arc::new(Mutex::new(LatencyHistogram::default()));
+    let db_writer = spawn_db_writer(
+        Arc::clone(&db_lock),
+        db_rx,
+        retention,
+        session.as_ref().map(|s| s.id.clone()),
+        update_tx.clone(),
+        Arc::clone(&latency),
+    );
+    let shutdown = Arc::new(AtomicBool::new(false));
+    #[cfg(feature = "metrics")]
+    let registry = Arc::new(rustyadv::metrics::Registry::default());
+    #[cfg(feature = "mqtt")]
+    let (mqtt_publisher, mqtt_connection) = match &args.mqtt_broker {
+        Some(broker) => {
+            let (publisher, handle) = rustyadv::mqtt::connect(broker, &args.mqtt_topic, Arc::clone(&shutdown));
+            (Some(publisher), Some(handle))
+        }
+        None => (None, None),
+    };
+    #[cfg(unix)]
+    let (ipc_publisher, ipc_listener) = match &args.ipc_socket {
+        Some(path) => {
+            let (publisher, handle) = rustyadv::ipc::bind(path, Arc::clone(&shutdown))
+                .map_err(|e| format!("--ipc-socket {}: {}", path.display(), e))?;
+            (Some(publisher), Some(handle))
+        }
+        None => (None, None),
+    };
+    #[cfg(not(unix))]
+    if args.ipc_socket.is_some() {
+        return Err("--ipc-socket needs Unix domain sockets, which this platform does not support".into());
+    }
+    #[cfg(feature = "websocket")]
+    let (ws_publisher, ws_listener) = match args.ws_port {
+        Some(port) => {
+            let addr = SocketAddr::new(args.ws_bind, port);
+            let (publisher, handle) = rustyadv::websocket::serve(addr, Arc::clone(&shutdown))
+                .map_err(|e| format!("--ws-port {}: {}", addr, e))?;
+            (Some(publisher), Some(handle))
+        }
+        None => (None, None),
+    };
+    let thermal = ThermalConfig {
+        detector: args.thermal_detector,
+        window: args.thermal_window,
+        min_window: args.thermal_min_window.unwrap_or(ThermalConfig::default().min_window.min(args.thermal_window)),
+        alpha: args.ewma_alpha,
+        max_drop_rate: args.max_drop_rate,
+        ..ThermalConfig::default()
+    };
+    if thermal.min_window > thermal.window {
+        return Err("--thermal-min-window must not exceed --thermal-window".into());
+    }
+    let (configs, severities) = match args.replay {
+        Some(_) => (Vec::new(), SeverityMap::default()),
+        None => {
+            let file = load_sensors_file(args.config.as_deref())?;
+            let severities = file.severities();
+            (file.sensor, severities)
+        }
+    };
+    let mut sensors: Vec<Box<dyn SentinelComponent>> = configs.iter()
+        .map(|config| config.build(thermal, args.fault_rate))
+        .collect::<Result<_, _>>()?;
+    for injection in &args.inject {
+        let Some(pos) = sensors.iter().position(|s| s.get_id() == injection.can_id) else {
+            return Err(format!("--inject: no sensor with CAN ID {}", injection.can_id).into());
+        };
+        let sensor = sensors.remove(pos);
+        sensors.insert(pos, Box::new(InjectedComponent::new(sensor, injection.pattern.clone())));
+    }
+    let on_dtc_hook = args.on_dtc.as_ref().map(|command| rustyadv::on_dtc::Hook::spawn(command, update_tx.clone()));
+    let outbox = Outbox {
+        ui: update_tx,
+        db: db_tx,
+        db_dropped: Arc::new(AtomicU64::new(0)),
+        enabled: Arc::new(sensors.iter().map(|s| (s.get_id(), AtomicBool::new(true))).collect()),
+        buses: Arc::new(configs.iter().map(|config| (config.can_id(), config.bus())).collect()),
+        severities: Arc::new(severities.clone()),
+        #[cfg(feature = "metrics")]
+        metrics: Arc::clone(&registry),
+        #[cfg(feature = "mqtt")]
+        mqtt: mqtt_publisher,
+        #[cfg(feature = "syslog")]
+        syslog: match args.syslog {
+            true => Some(rustyadv::system_log::Logger::connect(args.syslog_facility)?),
+            false => None,
+        },
+        #[cfg(unix)]
+        ipc: ipc_publisher,
+        on_dtc: on_dtc_hook,
+        #[cfg(feature = "websocket")]
+        websocket: ws_publisher,
+    };
+    let (mut ids, buses): (Vec<CanId>, HashMap<CanId, u8>) = match args.replay {
+        Some(_) => (replay_sensor_ids(&lock(&db_lock))?, recorded_buses(&lock(&db_lock))?),
+        None => (sensors.iter().map(|s| s.get_id()).collect(), outbox.buses.as_ref().clone()),
+    };
+    ids.sort_by_key(|id| buses.get(id).copied().unwrap_or_default());
+    let mut app = AppState::new(args.log_capacity, &ids);
+    app.buses = buses;
+    app.severities = severities;
+    app.db_dropped = Arc::clone(&outbox.db_dropped);
+    app.enabled = Arc::clone(&outbox.enabled);
+    app.latency = latency;
+    app.layout = args.layout;
+    app.icons = args.icons;
+    app.dedup_window = Duration::from_secs(args.dedup_window);
+    app.frame_interval = Duration::from_secs(1) / args.fps;
+    app.theme = args.theme;
+    app.dotfile = dotfile.map(|mut dotfile| {
+        remember_settings(&mut dotfile, &args);
+        dotfile
+    });
+    app.kinds = sensors.iter().map(|s| (s.get_id(), s.kind())).collect();
+    app.fusion.modules = sensors.iter().filter(|s| s.kind() == "ADAS").count();
+    app.config_source = args.config.clone().filter(|_| args.replay.is_none()).map(|path| ConfigSource {
+        path,
+        thermal,
+        fault_rate: args.fault_rate,
+    });
+    app.names = configs.iter()
+        .filter_map(|config| Some((config.can_id(), config.display_name()?.to_string())))
+        .collect();
+    app.liveness = sensors.iter()
+        .map(|s| {
+            let interval = args.tick_ms.as_ref().map_or_else(|| s.poll_interval(), |t| Duration::from_millis(t.max_ms));
+            (s.get_id(), interval * args.liveness_polls)
+        })
+        .collect();
+    {
+        let conn = lock(&db_lock);
+        if let Some(session) = session.as_mut() {
+            record_firmware(&conn, session, &sensors)?;
+        }
+        for sensor in &sensors {
+            let history = load_state(&conn, sensor.get_id())?;
+            if !history.is_empty() {
+                sensor.restore_history(&history);
+            }
+        }
+    }
     let shared_sensors = Arc::new(sensors);
+    let mut workers = Vec::new();
 
-    for i in 0..shared_sensors.len() {
-        let sensor_ref = Arc::clone(&shared_sensors);
-        let app_ref = Arc::clone(&app_state);
-        let db_ref = Arc::clone(&db_lock);
+    if !migrated.is_empty() {
+        let path = args.replay.clone().unwrap_or_else(|| PathBuf::from(&args.db));
+        app.notify(format!("[SCHEMA] {} upgraded to version {}: {}", path.display(), SCHEMA_VERSION, migrated.join(", ")));
+    }
+    if let Some(session) = &session {
+        app.notify(format!("[SESSION] {}", session.describe()));
+    }
+    if let Some(path) = &args.replay {
+        workers.push(spawn_replay(path, args.speed, outbox.ui.clone(), Arc::clone(&shutdown))?);
+        app.notify(format!("[REPLAY] {} at {}x", path.display(), args.speed));
+    }
 
-        thread::spawn(move || {
-            loop {
-                let sensor = &sensor_ref[i];
-                thread::sleep(Duration::from_millis(rand::thread_rng().gen_range(500..1500)));
+    #[cfg(feature = "metrics")]
+    let metrics_server = match args.metrics_port {
+        Some(port) => {
+            let addr = SocketAddr::new(args.metrics_bind, port);
+            let server = rustyadv::metrics::serve(addr, Arc::clone(&registry), Arc::clone(&shutdown))?;
+            app.notify(format!("[METRICS] Serving http://{}/metrics", addr));
+            Some(server)
+        }
+        None => None,
+    };
 
-                let status = sensor.check_status();
-                let id = sensor.get_id();
+    #[cfg(feature = "socketcan")]
+    if args.replay.is_none() {
+        let iface = &args.can_iface;
+        let decoder = rustyadv::can_bus::FrameDecoder {
+            #[cfg(feature = "can-dbc")]
+            dbc: args.dbc.as_deref().map(rustyadv::dbc::SignalDecoder::load).transpose()?,
+        };
+        match rustyadv::can_bus::spawn_reader(iface, Arc::clone(&shared_sensors), decoder, outbox.clone(), Arc::clone(&shutdown)) {
+            Ok(handle) => {
+                workers.push(handle);
+                app.notify(format!("[CAN] Reading frames from {}", iface));
+            }
+            Err(e) => app.notify(format!("[CAN] Could not open {}: {} (falling back to simulation)", iface, e)),
+        }
+    }
 
-                {
-                    let mut app = app_ref.lock().unwrap();
-                    app.update_sensor(id, status.clone());
-                    
-                    if status.contains("DTC") {
-                         app.add_log(format!("[CAN ID {:#X}] {}", id, status));
-                    }
-                }
+    #[cfg(feature = "async")]
+    let runtime = tokio::runtime::Runtime::new()?;
+    #[cfg(feature = "async")]
+    let sensor_tasks = if workers.is_empty() {
+        async_runtime::spawn_sensors(&runtime, &shared_sensors, args.tick_ms, &outbox, Arc::clone(&shutdown))
+    } else {
+        Vec::new()
+    };
+    #[cfg(not(feature = "async"))]
+    let simulated = if workers.is_empty() { shared_sensors.len() } else { 0 };
+    #[cfg(not(feature = "async"))]
+    for i in 0..simulated {
+        let sensor_ref = Arc::clone(&shared_sensors);
+        let outbox = outbox.clone();
+        let shutdown_ref = Arc::clone(&shutdown);
+        let tick = args.tick_ms;
 
-                let conn = db_ref.lock().unwrap();
-                conn.execute("INSERT INTO sensor_logs (sensor_id, message) VALUES (?1, ?2)", params![id, status]).unwrap();
+        workers.push(thread::spawn(move || {
+            while !shutdown_ref.load(Ordering::Relaxed) {
+                let sensor = &sensor_ref[i];
+                let delay = tick.map_or_else(|| sensor.poll_interval(), |t| t.sample());
+                if !sleep_unless_shutdown(&shutdown_ref, delay) { break; }
+                if !is_enabled(&outbox.enabled, sensor.get_id()) { continue; }
+
+                record_status(&outbox, sensor.as_ref(), sensor.read());
             }
-        });
+        }));
     }
 
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
-
-    loop {
-        terminal.draw(|f| {
-            let chunks = Layout::default()
-                .direction(Direction::Horizontal)
-                .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
-                .split(f.size());
-
-            let app = app_state.lock().unwrap();
-
-            let status_items: Vec<ListItem> = app.sensor_status.iter()
-                .map(|(id, msg)| ListItem::new(format!("CAN ID {:#X}: {}", id, msg)))
-                .collect();
-            
-            let status_list = List::new(status_items)
-                .block(Block::default().borders(Borders::ALL).title("ECU Network Status (CAN Bus)"));
-            f.render_widget(status_list, chunks[0]);
-
-            let log_items: Vec<ListItem> = app.logs.iter()
-                .map(|msg| ListItem::new(msg.clone()))
-                .collect();
-            
-            let log_list = List::new(log_items)
-                .block(Block::default().borders(Borders::ALL).title("OBD-II Diagnostic Trouble Codes (DTC)"));
-            f.render_widget(log_list, chunks[1]);
-        })?;
-
-        if event::poll(Duration::from_millis(100))? {
-            if let Event::Key(key) = event::read()? {
-                if key.code == KeyCode::Char('q') {
-                    break;
-                }
+    if args.no_tui {
+        signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&shutdown))?;
+        signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&shutdown))?;
+        let mut event_log = EventLog::open(args.log_format, args.log_file.as_deref())?;
+        if let Some(session) = &session {
+            event_log.write_session(session)?;
+        }
+        run_headless(&app, &update_rx, &mut event_log, &shutdown)?;
+    } else {
+        let mut event_log = args.log_file.as_deref().map(|path| EventLog::open(args.log_format, Some(path))).transpose()?;
+        if let (Some(log), Some(session)) = (event_log.as_mut(), &session) {
+            log.write_session(session)?;
+        }
+        let db_tx = if args.replay.is_none() { Some(&outbox.db) } else { None };
+        #[cfg(feature = "async")]
+        runtime.block_on(run_tui(&mut app, &shared_sensors, &update_rx, &db_lock, db_tx, event_log))?;
+        #[cfg(not(feature = "async"))]
+        run_tui(&mut app, &shared_sensors, &update_rx, &db_lock, db_tx, event_log)?;
+    }
+
+    shutdown.store(true, Ordering::Relaxed);
+    #[cfg(feature = "async")]
+    runtime.block_on(async {
+        for task in sensor_tasks {
+            let _ = task.await;
+        }
+    });
+    for worker in workers {
+        let _ = worker.join();
+    }
+    {
+        let conn = lock(&db_lock);
+        for sensor in shared_sensors.iter() {
+            if let Some(history) = sensor.persistent_history() {
+                save_state(&conn, sensor.get_id(), &history)?;
             }
         }
     }
+    #[cfg(feature = "metrics")]
+    if let Some(server) = metrics_server {
+        let _ = server.join();
+    }
+    drop(outbox);
+    #[cfg(feature = "mqtt")]
+    if let Some(connection) = mqtt_connection {
+        let _ = connection.join();
+    }
+    #[cfg(unix)]
+    if let Some(listener) = ipc_listener {
+        let _ = listener.join();
+    }
+    #[cfg(feature = "websocket")]
+    if let Some(listener) = ws_listener {
+        let _ = listener.join();
+    }
+    let _ = db_writer.join();
+    if !args.no_tui {
+        write_summary(&mut io::stdout().lock(), &app, &shared_sensors)?;
+    }
 
-    disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen, DisableMouseCapture)?;
     Ok(())
-}
\ No newline at end of file
+}
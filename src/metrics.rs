@@ -0,0 +1,87 @@
+//! The Prometheus `/metrics` endpoint and the counters it exposes.
+use std::io;
+use std::collections::BTreeMap;
+use std::thread;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::fmt::Write as _;
+use tiny_http::{Header, Response, Server};
+use crate::{lock, SHUTDOWN_POLL};
+use crate::dtc::CanId;
+
+#[derive(Default)]
+struct SensorMetrics {
+    gauges: Vec<(&'static str, f64)>,
+    dtc_total: u64,
+}
+
+#[derive(Default)]
+pub struct Registry {
+    sensors: Mutex<BTreeMap<CanId, SensorMetrics>>,
+    bus_frames: Mutex<BTreeMap<u8, u64>>,
+    db_dropped: AtomicU64,
+}
+
+impl Registry {
+    pub fn record(&self, can_id: CanId, bus: u8, is_dtc: bool, gauges: Vec<(&'static str, f64)>) {
+        *lock(&self.bus_frames).entry(bus).or_default() += 1;
+        let mut sensors = lock(&self.sensors);
+        let entry = sensors.entry(can_id).or_default();
+        if is_dtc { entry.dtc_total += 1; }
+        if !gauges.is_empty() { entry.gauges = gauges; }
+    }
+
+    pub fn record_db_drop(&self) {
+        self.db_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn render(&self) -> String {
+        let sensors = lock(&self.sensors);
+        let mut gauges: BTreeMap<&str, Vec<(CanId, f64)>> = BTreeMap::new();
+        for (id, m) in sensors.iter() {
+            for &(name, value) in &m.gauges {
+                gauges.entry(name).or_default().push((*id, value));
+            }
+        }
+
+        let mut out = String::new();
+        for (name, samples) in gauges {
+            let _ = writeln!(out, "# TYPE rustyadv_{} gauge", name);
+            for (id, value) in samples {
+                let _ = writeln!(out, "rustyadv_{}{{can_id=\"{}\"}} {}", name, id, value);
+            }
+        }
+        let _ = writeln!(out, "# TYPE rustyadv_dtc_total counter");
+        for (id, m) in sensors.iter() {
+            let _ = writeln!(out, "rustyadv_dtc_total{{can_id=\"{}\"}} {}", id, m.dtc_total);
+        }
+        let _ = writeln!(out, "# TYPE rustyadv_bus_frames_total counter");
+        for (bus, frames) in lock(&self.bus_frames).iter() {
+            let _ = writeln!(out, "rustyadv_bus_frames_total{{bus=\"{}\"}} {}", bus, frames);
+        }
+        let _ = writeln!(out, "# TYPE rustyadv_db_dropped_total counter");
+        let _ = writeln!(out, "rustyadv_db_dropped_total {}", self.db_dropped.load(Ordering::Relaxed));
+        out
+    }
+}
+
+pub fn serve(addr: SocketAddr, registry: Arc<Registry>, shutdown: Arc<AtomicBool>) -> io::Result<thread::JoinHandle<()>> {
+    let server = Server::http(addr).map_err(io::Error::other)?;
+    Ok(thread::spawn(move || {
+        while !shutdown.load(Ordering::Relaxed) {
+            let request = match server.recv_timeout(SHUTDOWN_POLL) {
+                Ok(Some(request)) => request,
+                Ok(None) => continue,
+                Err(_) => break,
+            };
+            let response = if request.url() == "/metrics" {
+                let content_type = Header::from_bytes("Content-Type", "text/plain; version=0.0.4").unwrap();
+                Response::from_string(registry.render()).with_header(content_type)
+            } else {
+                Response::from_string("not found").with_status_code(404)
+            };
+            let _ = request.respond(response);
+        }
+    }))
+}
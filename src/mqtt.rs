@@ -0,0 +1,73 @@
+//! Publishing confirmed DTCs to an MQTT broker from a background client.
+use std::thread;
+use std::time::Duration;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use rumqttc::{Client, Event, MqttOptions, Packet, QoS, RecvTimeoutError};
+use crate::sleep_unless_shutdown;
+use crate::dtc::{CanId, DtcCode, Severity};
+
+pub const DEFAULT_TOPIC: &str = "rustyadv/dtc";
+const QUEUE_CAPACITY: usize = 256;
+const POLL_TIMEOUT: Duration = Duration::from_secs(1);
+const MIN_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+#[derive(Serialize)]
+struct DtcEvent<'a> {
+    can_id: String,
+    code: Option<&'a str>,
+    severity: Option<&'a str>,
+    message: &'a str,
+    timestamp: String,
+}
+
+#[derive(Clone)]
+pub struct Publisher {
+    client: Client,
+    topic: String,
+}
+
+impl Publisher {
+    pub fn publish(&self, can_id: CanId, message: &str, severity: Option<Severity>, at: DateTime<Utc>) {
+        let event = DtcEvent {
+            can_id: format!("{}", can_id),
+            code: DtcCode::parse(message).map(|c| c.as_str()),
+            severity: severity.map(|s| s.as_str()),
+            message,
+            timestamp: at.to_rfc3339_opts(SecondsFormat::Millis, true),
+        };
+        let Ok(payload) = serde_json::to_vec(&event) else { return };
+        let _ = self.client.try_publish(self.topic.as_str(), QoS::AtLeastOnce, false, payload);
+    }
+}
+
+pub fn parse_broker(s: &str) -> Result<(String, u16), String> {
+    let (host, port) = s.rsplit_once(':').ok_or_else(|| format!("expected host:port, got {}", s))?;
+    let port = port.parse().map_err(|e| format!("invalid port '{}': {}", port, e))?;
+    Ok((host.to_string(), port))
+}
+
+pub fn connect(broker: &(String, u16), topic: &str, shutdown: Arc<AtomicBool>) -> (Publisher, thread::JoinHandle<()>) {
+    let mut options = MqttOptions::new(format!("rustyadv-{}", std::process::id()), broker.0.as_str(), broker.1);
+    options.set_keep_alive(Duration::from_secs(10));
+    let (client, mut connection) = Client::new(options, QUEUE_CAPACITY);
+
+    let handle = thread::spawn(move || {
+        let mut backoff = MIN_BACKOFF;
+        while !shutdown.load(Ordering::Relaxed) {
+            match connection.recv_timeout(POLL_TIMEOUT) {
+                Ok(Ok(Event::Incoming(Packet::ConnAck(_)))) => backoff = MIN_BACKOFF,
+                Ok(Ok(_)) | Err(RecvTimeoutError::Timeout) => {}
+                Ok(Err(_)) => {
+                    if !sleep_unless_shutdown(&shutdown, backoff) { break; }
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    });
+    (Publisher { client, topic: topic.to_string() }, handle)
+}
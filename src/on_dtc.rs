@@ -0,0 +1,97 @@
+//! Running a user command for every DTC, rate-limited, off the sensor threads.
+use std::collections::VecDeque;
+use std::thread;
+use std::time::{Duration, Instant};
+use std::sync::mpsc;
+use std::process::{Child, Command};
+use chrono::{DateTime, SecondsFormat, Utc};
+use crate::SensorUpdate;
+use crate::dtc::{CanId, DtcCode, Severity};
+
+const QUEUE: usize = 64;
+const MAX_PER_MINUTE: usize = 30;
+const MAX_RUNNING: usize = 4;
+const REAP_INTERVAL: Duration = Duration::from_millis(200);
+
+struct Fault {
+    can_id: CanId,
+    code: Option<&'static str>,
+    severity: Option<Severity>,
+    message: String,
+    at: DateTime<Utc>,
+}
+
+// Sensor threads only queue the fault; one runner thread spawns, reaps and rate-limits the commands.
+#[derive(Clone)]
+pub struct Hook {
+    faults: mpsc::SyncSender<Fault>,
+}
+
+impl Hook {
+    pub fn spawn(command: &str, ui: mpsc::Sender<SensorUpdate>) -> Self {
+        let (faults, rx) = mpsc::sync_channel(QUEUE);
+        let command = command.to_string();
+        thread::spawn(move || run(&command, rx, ui));
+        Self { faults }
+    }
+
+    pub fn fire(&self, can_id: CanId, message: &str, severity: Option<Severity>, at: DateTime<Utc>) {
+        let fault = Fault { can_id, code: DtcCode::parse(message).map(|c| c.as_str()), severity, message: message.to_string(), at };
+        let _ = self.faults.try_send(fault);
+    }
+}
+
+fn shell(command: &str) -> Command {
+    let (program, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+    let mut shell = Command::new(program);
+    shell.arg(flag).arg(command);
+    shell
+}
+
+fn run(command: &str, faults: mpsc::Receiver<Fault>, ui: mpsc::Sender<SensorUpdate>) {
+    let warn = |msg: String| { let _ = ui.send(SensorUpdate::Notice(format!("[ON-DTC] {}", msg))); };
+    let mut started: VecDeque<Instant> = VecDeque::new();
+    let mut running: Vec<Child> = Vec::new();
+    let mut skipped = 0;
+    loop {
+        let fault = match faults.recv_timeout(REAP_INTERVAL) {
+            Ok(fault) => Some(fault),
+            Err(mpsc::RecvTimeoutError::Timeout) => None,
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+        running.retain_mut(|child| match child.try_wait() {
+            Ok(Some(status)) if !status.success() => { warn(format!("'{}' exited with {}", command, status)); false }
+            Ok(Some(_)) | Err(_) => false,
+            Ok(None) => true,
+        });
+        let now = Instant::now();
+        while started.front().is_some_and(|&at| now.duration_since(at) >= Duration::from_secs(60)) {
+            started.pop_front();
+        }
+        let Some(fault) = fault else { continue };
+        if started.len() >= MAX_PER_MINUTE || running.len() >= MAX_RUNNING {
+            skipped += 1;
+            continue;
+        }
+        if skipped > 0 {
+            warn(format!("skipped {} DTCs over the limit of {} commands a minute, {} at a time", skipped, MAX_PER_MINUTE, MAX_RUNNING));
+            skipped = 0;
+        }
+        let spawned = shell(command)
+            .env("CAN_ID", fault.can_id.to_string())
+            .env("DTC_CODE", fault.code.unwrap_or_default())
+            .env("DTC_SEVERITY", fault.severity.map_or("", |s| s.as_str()))
+            .env("DTC_MESSAGE", &fault.message)
+            .env("TIMESTAMP", fault.at.to_rfc3339_opts(SecondsFormat::Millis, true))
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .spawn();
+        match spawned {
+            Ok(child) => {
+                started.push_back(now);
+                running.push(child);
+            }
+            Err(e) => warn(format!("could not run '{}': {}", command, e)),
+        }
+    }
+}
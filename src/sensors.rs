@@ -0,0 +1,1546 @@
+//! The simulated ECUs behind `SentinelComponent` and how their readings fan out.
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use rand::{Rng, RngCore, SeedableRng};
+use rand::rngs::StdRng;
+use rand_distr::{Distribution, Normal};
+use crate::{lock, DEFAULT_POLL_INTERVAL, SensorUpdate};
+use crate::detectors::{thermal_detectors, FaultDetector, ThermalConfig, ThermalDetector, WindowSummary};
+use crate::dtc::{CanId, DtcCode, FaultState, FaultTracker, FreezeFrame, PID_CELL_VOLTAGE, PID_TIRE_PRESSURE, PID_TRACKING_CONFIDENCE, PID_VEHICLE_SPEED, PidValue, SeverityMap};
+use crate::storage::{DbCommand, DbRecord};
+
+// Wheel positions in the order both the TPMS pressures and the ABS wheel speeds are carried.
+pub const WHEELS: [&str; 4] = ["FL", "FR", "RL", "RR"];
+
+pub const BMS_FAULT_PROBABILITY: f64 = 0.1;
+pub const DEFAULT_BMS_CELLS: usize = 8;
+pub const MAX_BMS_CELLS: usize = 32;
+pub const DEFAULT_MAX_CELL_SPREAD: f64 = 0.15;
+pub const CELL_BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+pub const ADAS_FAULT_PROBABILITY: f64 = 0.1;
+pub const ADAS_TREND_WINDOW: usize = 8;
+pub const ADAS_TREND_POLLS: usize = 3;
+pub const ADAS_TREND_FLOOR: u32 = 90;
+pub const ADAS_TREND_MIN_DROP: u32 = 5;
+pub const TPMS_FAULT_PROBABILITY: f64 = 0.05;
+pub const ABS_FAULT_PROBABILITY: f64 = 0.2;
+pub const GPS_FAULT_PROBABILITY: f64 = 0.05;
+pub const BMS_FIRMWARE: &str = "3.2.1";
+pub const ADAS_FIRMWARE: &str = "3.4.0";
+pub const TPMS_FIRMWARE: &str = "3.0.7";
+pub const ABS_FIRMWARE: &str = "3.1.2";
+pub const GPS_FIRMWARE: &str = "2.9.4";
+pub const BCM_FIRMWARE_MAJOR: u32 = 3;
+pub const BMS_WARM_UP: Duration = Duration::from_secs(2);
+pub const ADAS_WARM_UP: Duration = Duration::from_secs(5);
+pub const TPMS_WARM_UP: Duration = Duration::from_secs(1);
+pub const ABS_WARM_UP: Duration = Duration::from_secs(1);
+pub const GPS_WARM_UP: Duration = Duration::from_secs(3);
+
+#[derive(Clone, Copy, Debug)]
+pub struct TickRange {
+    pub min_ms: u64,
+    pub max_ms: u64,
+}
+
+impl TickRange {
+    pub fn sample(&self) -> Duration {
+        Duration::from_millis(rand::thread_rng().gen_range(self.min_ms..=self.max_ms))
+    }
+}
+
+pub fn parse_tick_range(s: &str) -> Result<TickRange, String> {
+    let (min, max) = s.split_once("..").or_else(|| s.split_once('-')).unwrap_or((s, s));
+    let parse = |v: &str| v.trim().parse::<u64>().map_err(|e| format!("invalid tick '{}': {}", v, e));
+    let (min_ms, max_ms) = (parse(min)?, parse(max)?);
+    if min_ms == 0 || min_ms > max_ms {
+        return Err(format!("tick range must satisfy 0 < min <= max, got {}", s));
+    }
+    Ok(TickRange { min_ms, max_ms })
+}
+
+pub type SensorRng = Mutex<Box<dyn RngCore + Send>>;
+
+pub fn entropy_rng() -> Box<dyn RngCore + Send> {
+    Box::new(StdRng::from_entropy())
+}
+
+pub fn seeded_rng(seed: Option<u64>) -> Box<dyn RngCore + Send> {
+    match seed {
+        Some(seed) => Box::new(StdRng::seed_from_u64(seed)),
+        None => entropy_rng(),
+    }
+}
+
+pub trait SentinelComponent: Send + Sync {
+    fn check_status(&self) -> String;
+    // Polls like check_status, and also returns the number behind the status line when the poll measured one.
+    fn read(&self) -> Reading { Reading::text(self.check_status()) }
+    fn get_id(&self) -> CanId;
+    fn poll_interval(&self) -> Duration { DEFAULT_POLL_INTERVAL }
+    fn take_freeze_frame(&self) -> Option<FreezeFrame> { None }
+    fn position(&self) -> Option<Position> { None }
+    fn history_snapshot(&self) -> Vec<f64> { Vec::new() }
+    // The voltage history with the time of each sample, and the band outside which a sample raises a DTC; BMS only.
+    fn timed_history(&self) -> Vec<(Instant, f64)> { Vec::new() }
+    fn trip_band(&self) -> Option<(f64, f64)> { None }
+    fn persistent_history(&self) -> Option<Vec<f64>> { None }
+    fn restore_history(&self, _history: &[f64]) {}
+    fn name(&self) -> String;
+    // Short ASCII tag shown next to the CAN ID, e.g. "BMS".
+    fn kind(&self) -> &'static str;
+    fn describe(&self) -> Vec<String> { Vec::new() }
+    // A 0..=100 series drawn under the details in the ECU detail pane.
+    fn trend(&self) -> Option<(&'static str, Vec<u64>)> { None }
+    fn read_pid(&self, _pid: u8) -> Option<PidValue> { None }
+    fn clear_faults(&self) {}
+    fn fault_tracker(&self) -> &Mutex<FaultTracker>;
+    fn firmware_version(&self) -> String;
+    // Applies reloaded thresholds in place; accumulated history is kept.
+    fn retune(&self, tuning: Tuning);
+    // Switches to the next thermal detector and returns it; None for ECUs without one.
+    fn cycle_detector(&self) -> Option<ThermalDetector> { None }
+    // The thermal thresholds in use right now, including changes made with 'd' and the tuning keys.
+    fn thermal(&self) -> Option<ThermalConfig> { None }
+    // Changes the thermal thresholds of the running detectors in place and returns the result.
+    fn adjust_thermal(&self, _adjust: &dyn Fn(&mut ThermalConfig)) -> Option<ThermalConfig> { None }
+    fn health_score(&self) -> u8 { lock(self.fault_tracker()).health() }
+
+    #[cfg(feature = "socketcan")]
+    fn decode_frame(&self, _data: &[u8]) -> Option<String> { None }
+    #[cfg(feature = "can-dbc")]
+    fn apply_signals(&self, _signals: &crate::dbc::Signals) -> Option<String> { None }
+    #[cfg(feature = "metrics")]
+    fn gauges(&self) -> Vec<(&'static str, f64)> { Vec::new() }
+}
+
+// The simulated body control module only talks to ECUs on its own major firmware release;
+// an ECU on an older or newer build now and then drops off its bus.
+pub fn bcm_link_lost(firmware: &str, fault_probability: f64, rng: &mut dyn RngCore) -> Option<String> {
+    let major: u32 = firmware.split('.').next()?.parse().ok()?;
+    (major != BCM_FIRMWARE_MAJOR && rng.gen_bool(fault_probability / 5.0)).then(|| format!(
+        "DTC {}: Lost Communication With BCM (firmware {}, BCM expects {}.x)",
+        DtcCode::LostCommunicationBcm.as_str(), firmware, BCM_FIRMWARE_MAJOR,
+    ))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Measurement {
+    pub name: &'static str,
+    pub value: f64,
+    pub unit: &'static str,
+}
+
+impl Measurement {
+    pub fn new(name: &'static str, value: f64, unit: &'static str) -> Self {
+        Self { name, value, unit }
+    }
+}
+
+// One poll's result. The dashboard shows the status text; the blackbox, exports and metrics use the number.
+pub struct Reading {
+    pub status: String,
+    pub measurement: Option<Measurement>,
+}
+
+impl Reading {
+    pub fn text(status: String) -> Self {
+        Self { status, measurement: None }
+    }
+
+    pub fn with(status: String, measurement: Measurement) -> Self {
+        Self { status, measurement: Some(measurement) }
+    }
+}
+
+// Cold-start window after power-up: the ECU only answers "Initializing" and no detector sees a sample.
+pub struct WarmUp {
+    pub started: Instant,
+    pub period: Duration,
+}
+
+impl WarmUp {
+    pub fn new(period: Duration) -> Self {
+        Self { started: Instant::now(), period }
+    }
+
+    pub fn status(&self) -> Option<String> {
+        let remaining = self.period.saturating_sub(self.started.elapsed());
+        (!remaining.is_zero()).then(|| format!("Initializing ({} s remaining)", remaining.as_secs_f64().ceil() as u64))
+    }
+}
+
+// What every simulated ECU carries besides its own signal model.
+pub struct EcuCore {
+    pub rng: SensorRng,
+    pub fault_probability: Mutex<f64>,
+    pub firmware_version: String,
+    pub warm_up: WarmUp,
+}
+
+impl EcuCore {
+    pub fn new(rng: Box<dyn RngCore + Send>, fault_probability: f64, firmware: &str, warm_up: Duration) -> Self {
+        Self {
+            rng: Mutex::new(rng),
+            fault_probability: Mutex::new(fault_probability),
+            firmware_version: firmware.to_string(),
+            warm_up: WarmUp::new(warm_up),
+        }
+    }
+
+    // Runs before every poll. A warming-up ECU or one that has lost the BCM answers with that
+    // instead; otherwise the poll goes ahead with the returned fault probability.
+    pub fn preamble(&self) -> Result<f64, Reading> {
+        if let Some(status) = self.warm_up.status() {
+            return Err(Reading::text(status));
+        }
+        let fault_probability = *lock(&self.fault_probability);
+        match bcm_link_lost(&self.firmware_version, fault_probability, &mut **lock(&self.rng)) {
+            Some(dtc) => Err(Reading::text(dtc)),
+            None => Ok(fault_probability),
+        }
+    }
+}
+
+// Builders shared by the ECUs; each one only says where its EcuCore lives.
+pub trait EcuBuilder: Sized {
+    fn core_mut(&mut self) -> &mut EcuCore;
+
+    fn with_fault_probability(mut self, probability: f64) -> Self {
+        self.core_mut().fault_probability = Mutex::new(probability);
+        self
+    }
+
+    fn with_firmware(mut self, version: &str) -> Self {
+        self.core_mut().firmware_version = version.to_string();
+        self
+    }
+
+    fn with_warm_up(mut self, period: Duration) -> Self {
+        self.core_mut().warm_up = WarmUp::new(period);
+        self
+    }
+}
+
+#[derive(Clone)]
+pub struct Outbox {
+    pub ui: mpsc::Sender<SensorUpdate>,
+    pub db: mpsc::SyncSender<DbCommand>,
+    pub db_dropped: Arc<AtomicU64>,
+    pub enabled: SensorSwitches,
+    pub buses: Arc<HashMap<CanId, u8>>,
+    pub severities: Arc<SeverityMap>,
+    #[cfg(feature = "metrics")]
+    pub metrics: Arc<crate::metrics::Registry>,
+    #[cfg(feature = "mqtt")]
+    pub mqtt: Option<crate::mqtt::Publisher>,
+    #[cfg(feature = "syslog")]
+    pub syslog: Option<crate::system_log::Logger>,
+    #[cfg(unix)]
+    pub ipc: Option<crate::ipc::Publisher>,
+    pub on_dtc: Option<crate::on_dtc::Hook>,
+    #[cfg(feature = "websocket")]
+    pub websocket: Option<crate::websocket::Publisher>,
+}
+
+// One flag per sensor, flipped from the dashboard and read by the workers before every poll.
+pub type SensorSwitches = Arc<HashMap<CanId, AtomicBool>>;
+
+pub fn is_enabled(switches: &HashMap<CanId, AtomicBool>, id: CanId) -> bool {
+    switches.get(&id).is_none_or(|flag| flag.load(Ordering::Relaxed))
+}
+
+pub fn record_status(outbox: &Outbox, sensor: &dyn SentinelComponent, reading: Reading) {
+    let Reading { status, measurement } = reading;
+    let detected = Instant::now();
+    let id = sensor.get_id();
+    let is_dtc = status.contains("DTC");
+    let freeze = if is_dtc { sensor.take_freeze_frame() } else { None };
+    let mut tracker = lock(sensor.fault_tracker());
+    #[cfg(feature = "syslog")]
+    let previous = tracker.state;
+    let fault = tracker.observe(is_dtc);
+    drop(tracker);
+    let at = Utc::now();
+    let bus = outbox.buses.get(&id).copied().unwrap_or_default();
+    let severity = outbox.severities.classify(&status);
+    #[cfg(feature = "metrics")]
+    outbox.metrics.record(id, bus, is_dtc, sensor.gauges());
+    #[cfg(feature = "mqtt")]
+    if is_dtc && fault == FaultState::Confirmed && let Some(publisher) = &outbox.mqtt {
+        publisher.publish(id, &status, severity, at);
+    }
+    #[cfg(unix)]
+    if is_dtc && let Some(publisher) = &outbox.ipc {
+        publisher.publish(id, &status, fault, at);
+    }
+    if is_dtc && fault == FaultState::Confirmed && let Some(hook) = &outbox.on_dtc {
+        hook.fire(id, &status, severity, at);
+    }
+    #[cfg(feature = "syslog")]
+    if let Some(logger) = &outbox.syslog {
+        logger.log(id, &sensor.name(), &status, severity, previous, fault);
+    }
+
+    let record = DbRecord { sensor_id: id, message: status.clone(), at, freeze: freeze.clone(), detected, measurement, bus };
+    if let Err(mpsc::TrySendError::Full(_)) = outbox.db.try_send(DbCommand::Insert(record)) {
+        outbox.db_dropped.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        outbox.metrics.record_db_drop();
+    }
+    let position = sensor.position();
+    #[cfg(feature = "websocket")]
+    if let Some(publisher) = &outbox.websocket {
+        publisher.publish(id, &status, is_dtc, fault, at, position);
+    }
+    let _ = outbox.ui.send(SensorUpdate::Status { id, status, is_dtc, fault, at, freeze, acknowledged: false, position });
+}
+
+// The thresholds 'r' can change on running sensors.
+#[derive(Clone, Copy, Debug)]
+pub struct Tuning {
+    pub thermal: ThermalConfig,
+    pub fault_probability: f64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VoltageProfile {
+    // Independent draws from 3.7..4.1 V with an occasional dip to 2.5 V.
+    #[default]
+    Nominal,
+    // Discharges a few mV per poll and recharges at 3.3 V; a fault starts a faster thermal drift.
+    SlowDrain,
+    // Holds steady; a fault steps the voltage down, after which it recovers gradually.
+    SuddenDrop,
+    // Correlated noise around 3.9 V; a fault adds a single spike.
+    Noisy,
+}
+
+#[derive(Default)]
+pub struct ProfileState {
+    pub level: Option<f64>,
+    pub noise: f64,
+    pub drift_polls: u32,
+}
+
+impl VoltageProfile {
+    pub fn next(self, state: &mut ProfileState, rng: &mut dyn RngCore, fault_probability: f64) -> f64 {
+        let fault = rng.gen_bool(fault_probability);
+        match self {
+            VoltageProfile::Nominal => if fault { 2.5 } else { rng.gen_range(3.7..4.1) },
+            VoltageProfile::SlowDrain => {
+                if fault && state.drift_polls == 0 {
+                    state.drift_polls = rng.gen_range(10..20);
+                }
+                let drain = match state.drift_polls {
+                    0 => rng.gen_range(0.001..0.004),
+                    _ => {
+                        state.drift_polls -= 1;
+                        rng.gen_range(0.02..0.04)
+                    }
+                };
+                let level = state.level.get_or_insert(4.1);
+                *level -= drain;
+                if *level < 3.3 {
+                    *level = 4.1;
+                    state.drift_polls = 0;
+                }
+                *level + rng.gen_range(-0.005..0.005)
+            }
+            VoltageProfile::SuddenDrop => {
+                let level = state.level.get_or_insert(3.95);
+                if fault {
+                    *level -= rng.gen_range(0.3..0.6);
+                }
+                *level = (*level + 0.01).min(3.95);
+                *level + rng.gen_range(-0.01..0.01)
+            }
+            VoltageProfile::Noisy => {
+                state.noise = 0.7 * state.noise + rng.gen_range(-0.06..0.06);
+                let spike = if fault { rng.gen_range(0.3..0.5) * if rng.gen_bool(0.5) { 1.0 } else { -1.0 } } else { 0.0 };
+                3.9 + state.noise + spike
+            }
+        }
+    }
+}
+
+pub const DEFAULT_SPIKE_VOLTS: f64 = 0.5;
+
+// Additive measurement noise on top of the voltage profile, drawn from the sensor's own RNG so a
+// seeded BMS replays the same noise every run.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NoiseModel {
+    pub sigma: f64,
+    pub spike_probability: f64,
+    pub spike_volts: f64,
+}
+
+impl Default for NoiseModel {
+    fn default() -> Self {
+        Self { sigma: 0.0, spike_probability: 0.0, spike_volts: DEFAULT_SPIKE_VOLTS }
+    }
+}
+
+impl NoiseModel {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.sigma.is_finite() && self.sigma >= 0.0) {
+            return Err("noise sigma must be a non-negative number".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.spike_probability) {
+            return Err("noise spike_probability must be in 0.0..=1.0".to_string());
+        }
+        if !self.spike_volts.is_finite() {
+            return Err("noise spike_volts must be a number".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn apply(&self, voltage: f64, rng: &mut dyn RngCore) -> f64 {
+        let mut noisy = voltage;
+        if self.sigma > 0.0 && let Ok(normal) = Normal::new(0.0, self.sigma) {
+            noisy += normal.sample(rng);
+        }
+        if self.spike_probability > 0.0 && rng.gen_bool(self.spike_probability) {
+            noisy += if rng.gen_bool(0.5) { self.spike_volts } else { -self.spike_volts };
+        }
+        noisy
+    }
+}
+
+#[derive(Default)]
+pub struct CellState {
+    pub offsets: Vec<f64>,
+    pub weak: Option<(usize, u32)>,
+    pub last: Vec<f64>,
+}
+
+impl CellState {
+    // Cells follow the pack voltage with a small fixed offset each; a fault picks one cell that
+    // sags a little further every poll until it recovers, so the spread grows gradually.
+    pub fn next(&mut self, pack: f64, count: usize, rng: &mut dyn RngCore, fault_probability: f64) -> Vec<f64> {
+        if self.offsets.len() != count {
+            self.offsets = (0..count).map(|_| rng.gen_range(-0.01..0.01)).collect();
+            self.weak = None;
+        }
+        if self.weak.is_none() && count > 1 && rng.gen_bool(fault_probability / 2.0) {
+            self.weak = Some((rng.gen_range(0..count), rng.gen_range(8..16)));
+        }
+        if let Some((cell, polls)) = &mut self.weak {
+            self.offsets[*cell] -= rng.gen_range(0.02..0.04);
+            *polls -= 1;
+            if *polls == 0 {
+                self.offsets[*cell] = rng.gen_range(-0.01..0.01);
+                self.weak = None;
+            }
+        }
+        self.offsets.iter().map(|o| pack + o + rng.gen_range(-0.003..0.003)).collect()
+    }
+}
+
+// Index of the lowest cell and the max-min spread across the pack.
+pub fn cell_spread(cells: &[f64]) -> Option<(usize, f64)> {
+    let (low, min) = cells.iter().copied().enumerate().min_by(|a, b| a.1.total_cmp(&b.1))?;
+    let max = cells.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    Some((low, max - min))
+}
+
+// One bar per cell, scaled so a healthy pack reads near full and a sagging cell stands out.
+pub fn cell_bars(cells: &[f64], max_spread: f64) -> String {
+    let max = cells.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let min = cells.iter().copied().fold(f64::INFINITY, f64::min).min(max - 2.0 * max_spread);
+    cells.iter()
+        .map(|v| CELL_BARS[((v - min) / (max - min) * (CELL_BARS.len() - 1) as f64).round() as usize])
+        .collect()
+}
+
+#[allow(non_camel_case_types)]
+pub struct BMS_ECU {
+    pub can_id: CanId,
+    pub history: Mutex<Vec<(Instant, f64)>>,
+    pub poll_interval: Duration,
+    pub thermal: Mutex<ThermalConfig>,
+    pub detectors: Mutex<Vec<Box<dyn FaultDetector>>>,
+    pub core: EcuCore,
+    pub profile: VoltageProfile,
+    pub profile_state: Mutex<ProfileState>,
+    pub noise: NoiseModel,
+    pub cell_count: usize,
+    pub max_cell_spread: f64,
+    pub cells: Mutex<CellState>,
+    pub freeze_frame: Mutex<Option<FreezeFrame>>,
+    pub fault: Mutex<FaultTracker>,
+}
+
+impl BMS_ECU {
+    pub fn new(can_id: CanId, poll_interval: Duration, rng: Box<dyn RngCore + Send>) -> Self {
+        Self {
+            can_id,
+            history: Mutex::new(Vec::new()),
+            poll_interval,
+            thermal: Mutex::new(ThermalConfig::default()),
+            detectors: Mutex::new(thermal_detectors(ThermalConfig::default())),
+            core: EcuCore::new(rng, BMS_FAULT_PROBABILITY, BMS_FIRMWARE, BMS_WARM_UP),
+            profile: VoltageProfile::default(),
+            profile_state: Mutex::new(ProfileState::default()),
+            noise: NoiseModel::default(),
+            cell_count: DEFAULT_BMS_CELLS,
+            max_cell_spread: DEFAULT_MAX_CELL_SPREAD,
+            cells: Mutex::new(CellState::default()),
+            freeze_frame: Mutex::new(None),
+            fault: Mutex::new(FaultTracker::default()),
+        }
+    }
+
+    pub fn with_thermal(mut self, thermal: ThermalConfig) -> Self {
+        self.thermal = Mutex::new(thermal);
+        self
+    }
+
+    pub fn with_detectors(mut self, detectors: Vec<Box<dyn FaultDetector>>) -> Self {
+        self.detectors = Mutex::new(detectors);
+        self
+    }
+
+    pub fn with_profile(mut self, profile: VoltageProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    pub fn with_noise(mut self, noise: NoiseModel) -> Self {
+        self.noise = noise;
+        self
+    }
+
+    pub fn with_cells(mut self, count: usize, max_spread: f64) -> Self {
+        self.cell_count = count;
+        self.max_cell_spread = max_spread;
+        self
+    }
+
+    // Fresh detectors pick up the new thresholds and are seeded from the kept history, as on a restart.
+    pub fn reseed_detectors(&self, thermal: ThermalConfig) {
+        let history = lock(&self.history).clone();
+        let recent: Vec<f64> = history[history.len().saturating_sub(thermal.window)..].iter().map(|&(_, v)| v).collect();
+        let mut detectors = thermal_detectors(thermal);
+        for detector in &mut detectors {
+            detector.restore(&recent);
+            if let Some(&last) = history.last() {
+                detector.resume(last);
+            }
+        }
+        *lock(&self.thermal) = thermal;
+        *lock(&self.detectors) = detectors;
+    }
+
+    // Stays None until the window is full, so a couple of cold-start samples don't look like a stable cell.
+    pub fn window_summary(&self) -> Option<WindowSummary> {
+        let history = self.history_snapshot();
+        (history.len() >= lock(&self.thermal).window).then(|| WindowSummary::of(&history))
+    }
+
+    pub fn detect_cell_spread(&self, cells: &[f64]) -> Option<(usize, f64)> {
+        let (low, spread) = cell_spread(cells)?;
+        if spread <= self.max_cell_spread { return None; }
+        *lock(&self.freeze_frame) = Some(FreezeFrame::capture(cells.to_vec()));
+        Some((low, spread))
+    }
+
+    // CAN frames only carry the pack voltage, so they pass no cells and skip the spread check.
+    // Every detector sees every sample, even after an earlier one has fired, so their state stays current.
+    pub fn run_detectors(&self, at: Instant, voltage: f64) -> Option<String> {
+        let mut detectors = lock(&self.detectors);
+        let mut raised = None;
+        for detector in detectors.iter_mut() {
+            if let Some(code) = detector.evaluate(voltage, at) && raised.is_none() {
+                *lock(&self.freeze_frame) = detector.freeze_frame();
+                let headline = match code {
+                    DtcCode::CellImbalance => "Cell Imbalance Detected!",
+                    DtcCode::RapidDischarge => "Rapid Cell Discharge",
+                    other => other.description(),
+                };
+                raised = Some(format!("DTC {}: {} ({})", code.as_str(), headline, detector.reading(voltage)));
+            }
+        }
+        raised
+    }
+
+    pub fn evaluate(&self, voltage: f64, cells: &[f64]) -> String {
+        let at = Instant::now();
+        {
+            let mut samples = lock(&self.history);
+            while !samples.is_empty() && samples.len() >= lock(&self.thermal).window { samples.remove(0); }
+            samples.push((at, voltage));
+        }
+        if let Some(dtc) = self.run_detectors(at, voltage) {
+            dtc
+        } else if let Some((low, spread)) = self.detect_cell_spread(cells) {
+            format!("DTC {}: Cell Voltage Spread {:.2}V (cell {} at {:.2}V)", DtcCode::CellSpread.as_str(), spread, low + 1, cells[low])
+        } else {
+            format!("Cell Voltage: {:.2}V (Optimal)", voltage)
+        }
+    }
+}
+
+impl EcuBuilder for BMS_ECU {
+    fn core_mut(&mut self) -> &mut EcuCore { &mut self.core }
+}
+
+impl SentinelComponent for BMS_ECU {
+    fn check_status(&self) -> String { self.read().status }
+    fn read(&self) -> Reading {
+        let fault_probability = match self.core.preamble() {
+            Ok(fault_probability) => fault_probability,
+            Err(reading) => return reading,
+        };
+        let (voltage, cells) = {
+            let mut rng = lock(&self.core.rng);
+            let voltage = self.profile.next(&mut lock(&self.profile_state), &mut **rng, fault_probability);
+            let voltage = self.noise.apply(voltage, &mut **rng);
+            let mut state = lock(&self.cells);
+            state.last = state.next(voltage, self.cell_count, &mut **rng, fault_probability);
+            (voltage, state.last.clone())
+        };
+        Reading::with(self.evaluate(voltage, &cells), Measurement::new("cell_voltage", voltage, "V"))
+    }
+    fn get_id(&self) -> CanId { self.can_id }
+    fn fault_tracker(&self) -> &Mutex<FaultTracker> { &self.fault }
+    fn firmware_version(&self) -> String { self.core.firmware_version.clone() }
+    fn retune(&self, tuning: Tuning) {
+        *lock(&self.core.fault_probability) = tuning.fault_probability;
+        self.reseed_detectors(tuning.thermal);
+    }
+    fn cycle_detector(&self) -> Option<ThermalDetector> {
+        let mut thermal = *lock(&self.thermal);
+        thermal.detector = thermal.detector.next();
+        self.reseed_detectors(thermal);
+        Some(thermal.detector)
+    }
+    fn thermal(&self) -> Option<ThermalConfig> { Some(*lock(&self.thermal)) }
+    fn adjust_thermal(&self, adjust: &dyn Fn(&mut ThermalConfig)) -> Option<ThermalConfig> {
+        let mut thermal = lock(&self.thermal);
+        adjust(&mut thermal);
+        lock(&self.detectors).iter_mut().for_each(|d| d.set_config(*thermal));
+        Some(*thermal)
+    }
+    fn poll_interval(&self) -> Duration { self.poll_interval }
+    fn take_freeze_frame(&self) -> Option<FreezeFrame> { lock(&self.freeze_frame).take() }
+    fn history_snapshot(&self) -> Vec<f64> { lock(&self.history).iter().map(|&(_, v)| v).collect() }
+    fn timed_history(&self) -> Vec<(Instant, f64)> { lock(&self.history).clone() }
+    fn trip_band(&self) -> Option<(f64, f64)> { lock(&self.detectors).iter().find_map(|d| d.trip_band()) }
+    fn name(&self) -> String { "Battery Management System".to_string() }
+    fn kind(&self) -> &'static str { "BMS" }
+
+    fn persistent_history(&self) -> Option<Vec<f64>> { Some(self.history_snapshot()) }
+
+    fn read_pid(&self, pid: u8) -> Option<PidValue> {
+        let &(_, voltage) = lock(&self.history).last()?;
+        (pid == PID_CELL_VOLTAGE).then(|| PidValue::new(pid, "Cell voltage", voltage, "V"))
+    }
+
+    fn restore_history(&self, history: &[f64]) {
+        let now = Instant::now();
+        let recent = &history[history.len().saturating_sub(lock(&self.thermal).window)..];
+        lock(&self.history).extend(recent.iter().map(|&voltage| (now, voltage)));
+        lock(&self.detectors).iter_mut().for_each(|d| d.restore(recent));
+    }
+
+    fn clear_faults(&self) {
+        lock(&self.history).clear();
+        lock(&self.cells).last.clear();
+        lock(&self.detectors).iter_mut().for_each(|d| d.reset());
+        lock(&self.freeze_frame).take();
+    }
+
+    fn describe(&self) -> Vec<String> {
+        let history = self.history_snapshot();
+        let Some(last) = history.last() else { return vec!["Cell voltage: no samples yet".to_string()] };
+        let [header, row] = WindowSummary::table(self.window_summary(), "V");
+        let thermal = *lock(&self.thermal);
+        let cells = lock(&self.cells).last.clone();
+        let spread = match cell_spread(&cells) {
+            Some((low, spread)) => format!(
+                "Cells   {}  spread {:.3}V (limit {:.2}V, lowest #{} {:.2}V)",
+                cell_bars(&cells, self.max_cell_spread), spread, self.max_cell_spread, low + 1, cells[low],
+            ),
+            None => format!("Cells   n/a  spread limit {:.2}V", self.max_cell_spread),
+        };
+        let mut lines = vec![
+            format!("Cell voltage: {:.2}V (last {}/{} samples, {:?} profile)", last, history.len(), thermal.window, self.profile),
+            header,
+            row,
+            format!(
+                "Detector: {} (d)  sigma {:.1} (+/-)  floor {:.2}V ([/])",
+                thermal.detector.label(), thermal.sigma, thermal.std_floor,
+            ),
+        ];
+        match self.trip_band() {
+            Some((low, high)) => {
+                let margin = (last - low).min(high - last);
+                lines.push(format!(
+                    "Latest {:.3}V is {:.3}V {} the {:.3}..{:.3}V trip band",
+                    last, margin.abs(), if margin >= 0.0 { "inside" } else { "outside" }, low, high,
+                ));
+            }
+            None if thermal.detector != ThermalDetector::Rate => {
+                lines.push(format!("No P0A80 possible: warming up or std-dev at or below the {:.2}V floor", thermal.std_floor));
+            }
+            None => {}
+        }
+        lines.extend(lock(&self.detectors).iter().map(|d| d.describe()));
+        lines.push(spread);
+        lines
+    }
+
+    #[cfg(feature = "socketcan")]
+    fn decode_frame(&self, data: &[u8]) -> Option<String> {
+        let millivolts = u16::from_be_bytes([*data.first()?, *data.get(1)?]);
+        Some(self.evaluate(millivolts as f64 / 1000.0, &[]))
+    }
+
+    #[cfg(feature = "can-dbc")]
+    fn apply_signals(&self, signals: &crate::dbc::Signals) -> Option<String> {
+        Some(self.evaluate(signals.get("CellVoltage")?, &[]))
+    }
+
+    #[cfg(feature = "metrics")]
+    fn gauges(&self) -> Vec<(&'static str, f64)> {
+        let spread = cell_spread(&lock(&self.cells).last).map(|(_, spread)| ("cell_spread_volts", spread));
+        lock(&self.history).last().map(|&(_, v)| ("cell_voltage_volts", v)).into_iter().chain(spread).collect()
+    }
+}
+
+#[allow(non_camel_case_types)]
+pub struct ADAS_Computer {
+    pub can_id: CanId,
+    pub module_name: String,
+    pub poll_interval: Duration,
+    pub core: EcuCore,
+    pub last_confidence: Mutex<Option<u32>>,
+    pub confidence_history: Mutex<VecDeque<u32>>,
+    // Simulated lens dirt or fog: percentage points currently lost, growing until it clears.
+    pub degradation: Mutex<u32>,
+    pub fault: Mutex<FaultTracker>,
+}
+
+impl ADAS_Computer {
+    pub fn new(can_id: CanId, module_name: &str, poll_interval: Duration, rng: Box<dyn RngCore + Send>) -> Self {
+        Self {
+            can_id,
+            module_name: module_name.to_string(),
+            poll_interval,
+            core: EcuCore::new(rng, ADAS_FAULT_PROBABILITY, ADAS_FIRMWARE, ADAS_WARM_UP),
+            last_confidence: Mutex::new(None),
+            confidence_history: Mutex::new(VecDeque::with_capacity(ADAS_TREND_WINDOW)),
+            degradation: Mutex::new(0),
+            fault: Mutex::new(FaultTracker::default()),
+        }
+    }
+
+    pub fn report(&self, blind: bool, confidence: u32) -> String {
+        *lock(&self.last_confidence) = if blind { None } else { Some(confidence) };
+        if blind {
+            return format!("DTC {}: Sensor Blind / Occluded", DtcCode::SensorBlind.as_str());
+        }
+        let mut history = lock(&self.confidence_history);
+        if history.len() == ADAS_TREND_WINDOW {
+            history.pop_front();
+        }
+        history.push_back(confidence);
+        match confidence_decline(history.make_contiguous()) {
+            Some(drop) => format!(
+                "DTC {}: Perception Degrading [{}] (confidence {}%, down {} points over {} polls)",
+                DtcCode::PerceptionDegraded.as_str(), self.module_name, confidence, drop, history.len(),
+            ),
+            None => format!("Tracking [{}]: Confidence {}%", self.module_name, confidence),
+        }
+    }
+}
+
+// A sustained slide rather than one bad frame: the last few polls all sit under the floor
+// and the newer half of the window averages clearly below the older half.
+pub fn confidence_decline(history: &[u32]) -> Option<u32> {
+    if history.len() < ADAS_TREND_WINDOW || !history[history.len() - ADAS_TREND_POLLS..].iter().all(|&c| c < ADAS_TREND_FLOOR) {
+        return None;
+    }
+    let (older, newer) = history.split_at(history.len() / 2);
+    let mean = |h: &[u32]| h.iter().sum::<u32>() / h.len() as u32;
+    let drop = mean(older).saturating_sub(mean(newer));
+    (drop >= ADAS_TREND_MIN_DROP).then_some(drop)
+}
+
+impl EcuBuilder for ADAS_Computer {
+    fn core_mut(&mut self) -> &mut EcuCore { &mut self.core }
+}
+
+impl SentinelComponent for ADAS_Computer {
+    fn check_status(&self) -> String { self.read().status }
+    fn read(&self) -> Reading {
+        let fault_probability = match self.core.preamble() {
+            Ok(fault_probability) => fault_probability,
+            Err(reading) => return reading,
+        };
+        let mut rng = lock(&self.core.rng);
+        let blind = rng.gen_bool(fault_probability);
+        let mut degradation = lock(&self.degradation);
+        *degradation = match *degradation {
+            0 if rng.gen_bool(fault_probability / 4.0) => rng.gen_range(2..5),
+            0 => 0,
+            _ if rng.gen_bool(0.1) => 0,
+            lost => (lost + rng.gen_range(1..4)).min(60),
+        };
+        let confidence = rng.gen_range(95..100) - *degradation;
+        drop(degradation);
+        let status = self.report(blind, confidence);
+        if blind {
+            Reading::text(status)
+        } else {
+            Reading::with(status, Measurement::new("tracking_confidence", confidence as f64, "%"))
+        }
+    }
+    fn get_id(&self) -> CanId { self.can_id }
+    fn fault_tracker(&self) -> &Mutex<FaultTracker> { &self.fault }
+    fn firmware_version(&self) -> String { self.core.firmware_version.clone() }
+    fn retune(&self, tuning: Tuning) { *lock(&self.core.fault_probability) = tuning.fault_probability; }
+    fn poll_interval(&self) -> Duration { self.poll_interval }
+    fn name(&self) -> String { self.module_name.clone() }
+    fn kind(&self) -> &'static str { "ADAS" }
+
+    fn read_pid(&self, pid: u8) -> Option<PidValue> {
+        let confidence = (*lock(&self.last_confidence))?;
+        (pid == PID_TRACKING_CONFIDENCE).then(|| PidValue::new(pid, "Tracking confidence", confidence as f64, "%"))
+    }
+
+    fn describe(&self) -> Vec<String> {
+        match *lock(&self.last_confidence) {
+            Some(confidence) => vec![format!("Tracking confidence: {}%", confidence)],
+            None => vec!["Tracking confidence: n/a (no valid track)".to_string()],
+        }
+    }
+
+    fn trend(&self) -> Option<(&'static str, Vec<u64>)> {
+        Some(("Confidence trend", lock(&self.confidence_history).iter().map(|&c| c as u64).collect()))
+    }
+
+    fn clear_faults(&self) {
+        lock(&self.confidence_history).clear();
+        *lock(&self.degradation) = 0;
+    }
+
+    #[cfg(feature = "socketcan")]
+    fn decode_frame(&self, data: &[u8]) -> Option<String> {
+        let confidence = *data.first()? as u32;
+        let blind = data.get(1).is_some_and(|flags| flags & 0x01 != 0);
+        Some(self.report(blind, confidence))
+    }
+
+    #[cfg(feature = "can-dbc")]
+    fn apply_signals(&self, signals: &crate::dbc::Signals) -> Option<String> {
+        let confidence = signals.get("Confidence")?.round().clamp(0.0, 100.0) as u32;
+        let blind = signals.get("SensorBlind").is_some_and(|flag| flag != 0.0);
+        Some(self.report(blind, confidence))
+    }
+
+    #[cfg(feature = "metrics")]
+    fn gauges(&self) -> Vec<(&'static str, f64)> {
+        lock(&self.last_confidence).map(|c| ("tracking_confidence_percent", c as f64)).into_iter().collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TpmsConfig {
+    pub window: usize,
+    pub nominal_kpa: f64,
+    pub low_kpa: f64,
+    pub leak_kpa: f64,
+    pub refill_below_kpa: f64,
+}
+
+impl Default for TpmsConfig {
+    fn default() -> Self {
+        Self { window: 10, nominal_kpa: 240.0, low_kpa: 200.0, leak_kpa: 10.0, refill_below_kpa: 170.0 }
+    }
+}
+
+pub struct TpmsState {
+    pub base_kpa: [f64; 4],
+    pub leak: Option<(usize, f64)>,
+    pub history: [Vec<f64>; 4],
+}
+
+#[allow(clippy::upper_case_acronyms)]
+pub struct TPMS {
+    pub can_id: CanId,
+    pub poll_interval: Duration,
+    pub config: TpmsConfig,
+    pub core: EcuCore,
+    pub state: Mutex<TpmsState>,
+    pub freeze_frame: Mutex<Option<FreezeFrame>>,
+    pub fault: Mutex<FaultTracker>,
+}
+
+impl TPMS {
+    pub fn new(can_id: CanId, poll_interval: Duration, rng: Box<dyn RngCore + Send>) -> Self {
+        let config = TpmsConfig::default();
+        Self {
+            can_id,
+            poll_interval,
+            config,
+            core: EcuCore::new(rng, TPMS_FAULT_PROBABILITY, TPMS_FIRMWARE, TPMS_WARM_UP),
+            state: Mutex::new(TpmsState {
+                base_kpa: [config.nominal_kpa; 4],
+                leak: None,
+                history: Default::default(),
+            }),
+            freeze_frame: Mutex::new(None),
+            fault: Mutex::new(FaultTracker::default()),
+        }
+    }
+
+    pub fn slow_leak(&self, history: &[Vec<f64>; 4]) -> Option<(usize, f64)> {
+        history.iter().enumerate()
+            .filter(|(_, h)| h.len() >= self.config.window / 2 && h.windows(2).all(|w| w[1] <= w[0] + 1.0))
+            .map(|(wheel, h)| (wheel, h[0] - h[h.len() - 1]))
+            .filter(|&(_, drop)| drop > self.config.leak_kpa)
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    pub fn evaluate(&self, pressures: [f64; 4]) -> String {
+        let mut state = lock(&self.state);
+        for (history, kpa) in state.history.iter_mut().zip(pressures) {
+            while !history.is_empty() && history.len() >= self.config.window { history.remove(0); }
+            history.push(kpa);
+        }
+
+        let (low, kpa) = pressures.iter().copied().enumerate()
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap();
+        if kpa < self.config.low_kpa {
+            *lock(&self.freeze_frame) = Some(FreezeFrame::capture(state.history[low].clone()));
+            return format!("DTC {}: Low Tire Pressure ({} {:.0}kPa)", DtcCode::LowTirePressure.as_str(), WHEELS[low], kpa);
+        }
+
+        let readings: Vec<String> = WHEELS.iter().zip(pressures)
+            .map(|(wheel, kpa)| format!("{} {:.0}", wheel, kpa))
+            .collect();
+        match self.slow_leak(&state.history) {
+            Some((wheel, _)) => format!("Tire Pressure: {} kPa (Slow Leak {})", readings.join(" "), WHEELS[wheel]),
+            None => format!("Tire Pressure: {} kPa (Optimal)", readings.join(" ")),
+        }
+    }
+}
+
+impl EcuBuilder for TPMS {
+    fn core_mut(&mut self) -> &mut EcuCore { &mut self.core }
+}
+
+impl SentinelComponent for TPMS {
+    fn check_status(&self) -> String { self.read().status }
+    fn read(&self) -> Reading {
+        let fault_probability = match self.core.preamble() {
+            Ok(fault_probability) => fault_probability,
+            Err(reading) => return reading,
+        };
+        let pressures = {
+            let mut rng = lock(&self.core.rng);
+            let mut state = lock(&self.state);
+            if state.leak.is_none() && rng.gen_bool(fault_probability) {
+                state.leak = Some((rng.gen_range(0..4), rng.gen_range(3.0..8.0)));
+            }
+            if let Some((wheel, rate)) = state.leak {
+                state.base_kpa[wheel] -= rate;
+                if state.base_kpa[wheel] < self.config.refill_below_kpa {
+                    state.base_kpa[wheel] = self.config.nominal_kpa;
+                    state.leak = None;
+                }
+            }
+            state.base_kpa.map(|kpa| kpa + rng.gen_range(-1.0..1.0))
+        };
+        let lowest = pressures.iter().copied().fold(f64::INFINITY, f64::min);
+        Reading::with(self.evaluate(pressures), Measurement::new("tire_pressure_min", lowest, "kPa"))
+    }
+    fn get_id(&self) -> CanId { self.can_id }
+    fn fault_tracker(&self) -> &Mutex<FaultTracker> { &self.fault }
+    fn firmware_version(&self) -> String { self.core.firmware_version.clone() }
+    fn retune(&self, tuning: Tuning) { *lock(&self.core.fault_probability) = tuning.fault_probability; }
+    fn poll_interval(&self) -> Duration { self.poll_interval }
+    fn take_freeze_frame(&self) -> Option<FreezeFrame> { lock(&self.freeze_frame).take() }
+    fn name(&self) -> String { "Tire Pressure Monitor".to_string() }
+    fn kind(&self) -> &'static str { "TPMS" }
+
+    fn read_pid(&self, pid: u8) -> Option<PidValue> {
+        if pid != PID_TIRE_PRESSURE { return None; }
+        let state = lock(&self.state);
+        let lowest = state.history.iter().filter_map(|h| h.last().copied()).reduce(f64::min)?;
+        Some(PidValue::new(pid, "Lowest tire pressure", lowest, "kPa"))
+    }
+
+    fn clear_faults(&self) {
+        lock(&self.state).history.iter_mut().for_each(Vec::clear);
+        lock(&self.freeze_frame).take();
+    }
+
+    fn history_snapshot(&self) -> Vec<f64> {
+        let state = lock(&self.state);
+        state.history.iter()
+            .filter(|h| !h.is_empty())
+            .min_by(|a, b| a[a.len() - 1].total_cmp(&b[b.len() - 1]))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn describe(&self) -> Vec<String> {
+        let state = lock(&self.state);
+        if state.history[0].is_empty() { return vec!["Tire pressure: no samples yet".to_string()] };
+        let readings: Vec<String> = WHEELS.iter().zip(&state.history)
+            .map(|(wheel, h)| format!("{} {:.0}kPa", wheel, h[h.len() - 1]))
+            .collect();
+        let leak = match self.slow_leak(&state.history) {
+            Some((wheel, drop)) => format!("Slow leak: {} (-{:.0}kPa over {} samples)", WHEELS[wheel], drop, state.history[wheel].len()),
+            None => "Slow leak: none".to_string(),
+        };
+        vec![
+            readings.join("  "),
+            format!("Low-pressure threshold {:.0}kPa", self.config.low_kpa),
+            leak,
+        ]
+    }
+
+    #[cfg(feature = "socketcan")]
+    fn decode_frame(&self, data: &[u8]) -> Option<String> {
+        let mut pressures = [0.0; 4];
+        for (wheel, kpa) in pressures.iter_mut().enumerate() {
+            *kpa = u16::from_be_bytes([*data.get(wheel * 2)?, *data.get(wheel * 2 + 1)?]) as f64;
+        }
+        Some(self.evaluate(pressures))
+    }
+
+    #[cfg(feature = "can-dbc")]
+    fn apply_signals(&self, signals: &crate::dbc::Signals) -> Option<String> {
+        let mut pressures = [0.0; 4];
+        for (kpa, wheel) in pressures.iter_mut().zip(WHEELS) {
+            *kpa = signals.get(&format!("TirePressure{}", wheel))?;
+        }
+        Some(self.evaluate(pressures))
+    }
+}
+
+pub const ABS_WHEELS: [&str; 4] = ["Left Front", "Right Front", "Left Rear", "Right Rear"];
+
+#[derive(Clone, Copy, Debug)]
+pub struct AbsConfig {
+    pub window: usize,
+    pub divergence_kph: f64,
+    pub divergence_ratio: f64,
+    pub sustained_samples: usize,
+    pub min_pump_bar: f64,
+}
+
+impl Default for AbsConfig {
+    fn default() -> Self {
+        Self { window: 10, divergence_kph: 15.0, divergence_ratio: 0.25, sustained_samples: 2, min_pump_bar: 30.0 }
+    }
+}
+
+pub struct AbsState {
+    pub vehicle_kph: f64,
+    pub braking: bool,
+    pub sensor_fault: Option<(usize, u32)>,
+    pub pump_fault: u32,
+    pub wheel_history: [Vec<f64>; 4],
+    pub pressure_history: Vec<f64>,
+}
+
+#[allow(non_camel_case_types)]
+pub struct ABS_ECU {
+    pub can_id: CanId,
+    pub poll_interval: Duration,
+    pub config: AbsConfig,
+    pub core: EcuCore,
+    pub state: Mutex<AbsState>,
+    pub freeze_frame: Mutex<Option<FreezeFrame>>,
+    pub fault: Mutex<FaultTracker>,
+}
+
+impl ABS_ECU {
+    pub fn new(can_id: CanId, poll_interval: Duration, rng: Box<dyn RngCore + Send>) -> Self {
+        Self {
+            can_id,
+            poll_interval,
+            config: AbsConfig::default(),
+            core: EcuCore::new(rng, ABS_FAULT_PROBABILITY, ABS_FIRMWARE, ABS_WARM_UP),
+            state: Mutex::new(AbsState {
+                vehicle_kph: 80.0,
+                braking: false,
+                sensor_fault: None,
+                pump_fault: 0,
+                wheel_history: Default::default(),
+                pressure_history: Vec::new(),
+            }),
+            freeze_frame: Mutex::new(None),
+            fault: Mutex::new(FaultTracker::default()),
+        }
+    }
+
+    pub fn diverging_wheel(&self, history: &[Vec<f64>; 4]) -> Option<usize> {
+        let cfg = &self.config;
+        let sustained = history.iter().map(Vec::len).min()?;
+        if sustained < cfg.sustained_samples { return None; }
+        (0..4).find(|&wheel| {
+            (1..=cfg.sustained_samples).all(|back| {
+                let speed = |w: usize| history[w][history[w].len() - back];
+                let mut others: Vec<f64> = (0..4).filter(|&w| w != wheel).map(speed).collect();
+                others.sort_by(f64::total_cmp);
+                let gap = (speed(wheel) - others[1]).abs();
+                gap > cfg.divergence_kph && gap > others[1] * cfg.divergence_ratio
+            })
+        })
+    }
+
+    pub fn evaluate(&self, braking: bool, line_bar: f64, speeds: [f64; 4]) -> String {
+        let cfg = &self.config;
+        let mut state = lock(&self.state);
+        for (history, kph) in state.wheel_history.iter_mut().zip(speeds) {
+            while !history.is_empty() && history.len() >= cfg.window { history.remove(0); }
+            history.push(kph);
+        }
+        while !state.pressure_history.is_empty() && state.pressure_history.len() >= cfg.window { state.pressure_history.remove(0); }
+        state.pressure_history.push(line_bar);
+
+        if braking && line_bar < cfg.min_pump_bar {
+            *lock(&self.freeze_frame) = Some(FreezeFrame::capture(state.pressure_history.clone()));
+            return format!("DTC {}: ABS Pump Motor Fault ({:.0} bar while braking)", DtcCode::AbsPumpMotor.as_str(), line_bar);
+        }
+        if braking && let Some(wheel) = self.diverging_wheel(&state.wheel_history) {
+            *lock(&self.freeze_frame) = Some(FreezeFrame::capture(state.wheel_history[wheel].clone()));
+            return format!("DTC {}: {} Wheel Speed Circuit ({:.0} km/h)", DtcCode::WheelSpeedCircuit.as_str(), ABS_WHEELS[wheel], speeds[wheel]);
+        }
+
+        let mean_kph = speeds.iter().sum::<f64>() / 4.0;
+        let phase = if braking { "Braking" } else { "Cruising" };
+        format!("{}: {:.0} km/h, Line {:.0} bar (Optimal)", phase, mean_kph, line_bar)
+    }
+}
+
+impl EcuBuilder for ABS_ECU {
+    fn core_mut(&mut self) -> &mut EcuCore { &mut self.core }
+}
+
+impl SentinelComponent for ABS_ECU {
+    fn check_status(&self) -> String { self.read().status }
+    fn read(&self) -> Reading {
+        let fault_probability = match self.core.preamble() {
+            Ok(fault_probability) => fault_probability,
+            Err(reading) => return reading,
+        };
+        let (braking, line_bar, speeds) = {
+            let mut rng = lock(&self.core.rng);
+            let mut state = lock(&self.state);
+            if state.braking {
+                state.vehicle_kph -= rng.gen_range(5.0..12.0);
+                if state.vehicle_kph < 10.0 {
+                    state.braking = false;
+                    state.vehicle_kph = rng.gen_range(60.0..110.0);
+                }
+            } else if rng.gen_bool(0.2) {
+                state.braking = true;
+                if rng.gen_bool(fault_probability) {
+                    match rng.gen_range(0..4) {
+                        0 => state.pump_fault = 2,
+                        _ => state.sensor_fault = Some((rng.gen_range(0..4), 3)),
+                    }
+                }
+            }
+
+            let line_bar = match (state.braking, state.pump_fault) {
+                (false, _) => rng.gen_range(0.0..2.0),
+                (true, 0) => rng.gen_range(60.0..90.0),
+                (true, _) => {
+                    state.pump_fault -= 1;
+                    rng.gen_range(5.0..15.0)
+                }
+            };
+            let fault = state.sensor_fault;
+            state.sensor_fault = match fault {
+                Some((wheel, polls)) if polls > 1 => Some((wheel, polls - 1)),
+                _ => None,
+            };
+            let mut speeds = [0.0; 4];
+            for (wheel, kph) in speeds.iter_mut().enumerate() {
+                *kph = state.vehicle_kph + rng.gen_range(-1.0..1.0);
+                if fault.is_some_and(|(w, _)| w == wheel) { *kph *= 0.2; }
+            }
+            (state.braking, line_bar, speeds)
+        };
+        let status = self.evaluate(braking, line_bar, speeds);
+        Reading::with(status, Measurement::new("vehicle_speed", lock(&self.state).vehicle_kph, "km/h"))
+    }
+    fn get_id(&self) -> CanId { self.can_id }
+    fn fault_tracker(&self) -> &Mutex<FaultTracker> { &self.fault }
+    fn firmware_version(&self) -> String { self.core.firmware_version.clone() }
+    fn retune(&self, tuning: Tuning) { *lock(&self.core.fault_probability) = tuning.fault_probability; }
+    fn poll_interval(&self) -> Duration { self.poll_interval }
+    fn take_freeze_frame(&self) -> Option<FreezeFrame> { lock(&self.freeze_frame).take() }
+    fn history_snapshot(&self) -> Vec<f64> { lock(&self.state).pressure_history.clone() }
+    fn name(&self) -> String { "Anti-lock Braking System".to_string() }
+    fn kind(&self) -> &'static str { "ABS" }
+
+    fn read_pid(&self, pid: u8) -> Option<PidValue> {
+        if pid != PID_VEHICLE_SPEED { return None; }
+        let state = lock(&self.state);
+        let speeds: Vec<f64> = state.wheel_history.iter().filter_map(|h| h.last().copied()).collect();
+        if speeds.is_empty() { return None; }
+        Some(PidValue::new(pid, "Vehicle speed", speeds.iter().sum::<f64>() / speeds.len() as f64, "km/h"))
+    }
+
+    fn clear_faults(&self) {
+        let mut state = lock(&self.state);
+        state.wheel_history.iter_mut().for_each(Vec::clear);
+        state.pressure_history.clear();
+        lock(&self.freeze_frame).take();
+    }
+
+    fn describe(&self) -> Vec<String> {
+        let state = lock(&self.state);
+        if state.pressure_history.is_empty() { return vec!["Wheel speed: no samples yet".to_string()] };
+        let speeds: Vec<String> = state.wheel_history.iter()
+            .map(|h| format!("{:.0}", h[h.len() - 1]))
+            .collect();
+        vec![
+            format!("Wheel speed FL/FR/RL/RR: {} km/h", speeds.join("/")),
+            format!("Line pressure {:.0} bar (pump fault below {:.0} bar)", state.pressure_history[state.pressure_history.len() - 1], self.config.min_pump_bar),
+            format!("Divergence limit {:.0} km/h and {:.0}%", self.config.divergence_kph, self.config.divergence_ratio * 100.0),
+        ]
+    }
+
+    #[cfg(feature = "socketcan")]
+    fn decode_frame(&self, data: &[u8]) -> Option<String> {
+        let mut speeds = [0.0; 4];
+        for (wheel, kph) in speeds.iter_mut().enumerate() {
+            *kph = *data.get(wheel)? as f64;
+        }
+        let line_bar = *data.get(4)? as f64;
+        let braking = data.get(5).is_some_and(|flags| flags & 0x01 != 0);
+        Some(self.evaluate(braking, line_bar, speeds))
+    }
+
+    #[cfg(feature = "can-dbc")]
+    fn apply_signals(&self, signals: &crate::dbc::Signals) -> Option<String> {
+        let mut speeds = [0.0; 4];
+        for (kph, wheel) in speeds.iter_mut().zip(WHEELS) {
+            *kph = signals.get(&format!("WheelSpeed{}", wheel))?;
+        }
+        let line_bar = signals.get("LinePressure")?;
+        let braking = signals.get("BrakePedal").is_some_and(|flag| flag != 0.0);
+        Some(self.evaluate(braking, line_bar, speeds))
+    }
+}
+
+pub const GPS_MIN_SATELLITES: u8 = 4;
+pub const GPS_MAX_SATELLITES: u8 = 12;
+pub const METERS_PER_DEGREE: f64 = 111_320.0;
+pub const DEFAULT_GPS_ORIGIN: Position = Position { lat: 48.1372, lon: 11.5756 };
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Position {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl Position {
+    pub fn moved(self, meters: f64, heading_deg: f64) -> Self {
+        let heading = heading_deg.to_radians();
+        let lat = self.lat + meters * heading.cos() / METERS_PER_DEGREE;
+        let lon = self.lon + meters * heading.sin() / (METERS_PER_DEGREE * self.lat.to_radians().cos());
+        Self { lat: lat.clamp(-90.0, 90.0), lon: (lon + 180.0).rem_euclid(360.0) - 180.0 }
+    }
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.5}, {:.5}", self.lat, self.lon)
+    }
+}
+
+pub struct GpsState {
+    pub position: Position,
+    pub heading_deg: f64,
+    pub speed_mps: f64,
+    pub satellites: u8,
+    pub outage: u8,
+    pub fix: Option<Position>,
+    pub last_poll: Option<Instant>,
+}
+
+#[allow(non_camel_case_types)]
+pub struct GPS_Module {
+    pub can_id: CanId,
+    pub poll_interval: Duration,
+    pub core: EcuCore,
+    pub state: Mutex<GpsState>,
+    pub fault: Mutex<FaultTracker>,
+}
+
+impl GPS_Module {
+    pub fn new(can_id: CanId, origin: Position, poll_interval: Duration, rng: Box<dyn RngCore + Send>) -> Self {
+        Self {
+            can_id,
+            poll_interval,
+            core: EcuCore::new(rng, GPS_FAULT_PROBABILITY, GPS_FIRMWARE, GPS_WARM_UP),
+            state: Mutex::new(GpsState {
+                position: origin,
+                heading_deg: 0.0,
+                speed_mps: 0.0,
+                satellites: GPS_MAX_SATELLITES,
+                outage: 0,
+                fix: None,
+                last_poll: None,
+            }),
+            fault: Mutex::new(FaultTracker::default()),
+        }
+    }
+
+    pub fn evaluate(&self, fix: Option<Position>, satellites: u8) -> String {
+        match fix {
+            Some(position) => format!("Position: {} ({} satellites)", position, satellites),
+            None => format!(
+                "DTC {}: Invalid Data from Nav Unit (no satellite lock, {} satellites)",
+                DtcCode::NavInvalidData.as_str(),
+                satellites,
+            ),
+        }
+    }
+}
+
+impl EcuBuilder for GPS_Module {
+    fn core_mut(&mut self) -> &mut EcuCore { &mut self.core }
+}
+
+impl SentinelComponent for GPS_Module {
+    fn check_status(&self) -> String { self.read().status }
+    fn read(&self) -> Reading {
+        let fault_probability = match self.core.preamble() {
+            Ok(fault_probability) => fault_probability,
+            Err(reading) => return reading,
+        };
+        let (fix, satellites) = {
+            let mut rng = lock(&self.core.rng);
+            let mut state = lock(&self.state);
+            let now = Instant::now();
+            let elapsed = state.last_poll.map_or(self.poll_interval, |t| now.duration_since(t)).as_secs_f64();
+            state.last_poll = Some(now);
+
+            // The vehicle keeps moving whether or not the receiver can see it: heading and speed wander a little
+            // each poll so the track curves and accelerates instead of jumping between random points.
+            state.heading_deg = (state.heading_deg + rng.gen_range(-10.0..10.0)).rem_euclid(360.0);
+            state.speed_mps = (state.speed_mps + rng.gen_range(-1.5..2.0)).clamp(0.0, 36.0);
+            state.position = state.position.moved(state.speed_mps * elapsed, state.heading_deg);
+
+            if state.outage == 0 && rng.gen_bool(fault_probability) {
+                state.outage = rng.gen_range(3..8);
+            }
+            if state.outage > 0 {
+                state.outage -= 1;
+                state.satellites = rng.gen_range(0..GPS_MIN_SATELLITES);
+                state.fix = None;
+            } else {
+                let drift: i8 = rng.gen_range(-1..=1);
+                state.satellites = state.satellites.saturating_add_signed(drift).clamp(GPS_MIN_SATELLITES, GPS_MAX_SATELLITES);
+                state.fix = Some(state.position);
+            }
+            (state.fix, state.satellites)
+        };
+        Reading::with(self.evaluate(fix, satellites), Measurement::new("gps_satellites", satellites as f64, "satellites"))
+    }
+    fn get_id(&self) -> CanId { self.can_id }
+    fn fault_tracker(&self) -> &Mutex<FaultTracker> { &self.fault }
+    fn firmware_version(&self) -> String { self.core.firmware_version.clone() }
+    fn retune(&self, tuning: Tuning) { *lock(&self.core.fault_probability) = tuning.fault_probability; }
+    fn poll_interval(&self) -> Duration { self.poll_interval }
+    fn position(&self) -> Option<Position> { lock(&self.state).fix }
+    fn name(&self) -> String { "GPS Module".to_string() }
+    fn kind(&self) -> &'static str { "GPS" }
+
+    fn describe(&self) -> Vec<String> {
+        let state = lock(&self.state);
+        let Some(fix) = state.fix else {
+            return vec![format!("Position: no satellite lock ({} satellites)", state.satellites)];
+        };
+        vec![
+            format!("Position: {}", fix),
+            format!("Heading {:.0} deg  Speed {:.0} km/h", state.heading_deg, state.speed_mps * 3.6),
+            format!("Satellites: {} (lock needs {})", state.satellites, GPS_MIN_SATELLITES),
+        ]
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FaultInjection {
+    pub can_id: CanId,
+    // One entry per poll, true for a failing cycle; the sequence repeats.
+    pub pattern: Vec<bool>,
+}
+
+pub fn parse_injection(s: &str) -> Result<FaultInjection, String> {
+    let (id, pattern) = s.split_once(':').ok_or_else(|| format!("expected <can_id>:<pattern>, got {}", s))?;
+    let can_id = id.trim().parse()?;
+    let mut cycles = Vec::new();
+    for step in pattern.split(',').map(str::trim) {
+        let (count, bad) = match (step.strip_suffix("good"), step.strip_suffix("bad")) {
+            (Some(count), _) => (count, false),
+            (_, Some(count)) => (count, true),
+            _ => return Err(format!("pattern step '{}' must end in good or bad, e.g. 3good,1bad", step)),
+        };
+        let count = match count {
+            "" => 1,
+            n => n.parse::<usize>().map_err(|_| format!("invalid repeat count in pattern step '{}'", step))?,
+        };
+        cycles.extend(std::iter::repeat_n(bad, count));
+    }
+    if !cycles.contains(&true) {
+        return Err(format!("pattern {} has no bad cycles", pattern));
+    }
+    Ok(FaultInjection { can_id, pattern: cycles })
+}
+
+pub fn injected_code(kind: &str) -> DtcCode {
+    match kind {
+        "ADAS" => DtcCode::SensorBlind,
+        "TPMS" => DtcCode::LowTirePressure,
+        "ABS" => DtcCode::WheelSpeedCircuit,
+        "GPS" => DtcCode::NavInvalidData,
+        _ => DtcCode::CellImbalance,
+    }
+}
+
+// Wraps a component and replaces its random faults with a fixed good/bad sequence, so the
+// pending/confirmed/healing transitions can be demonstrated cycle by cycle.
+pub struct InjectedComponent {
+    pub inner: Box<dyn SentinelComponent>,
+    pub pattern: Vec<bool>,
+    pub step: Mutex<usize>,
+}
+
+impl InjectedComponent {
+    pub fn new(inner: Box<dyn SentinelComponent>, pattern: Vec<bool>) -> Self {
+        Self { inner, pattern, step: Mutex::new(0) }
+    }
+}
+
+impl SentinelComponent for InjectedComponent {
+    fn check_status(&self) -> String { self.read().status }
+    fn read(&self) -> Reading {
+        let step = {
+            let mut step = lock(&self.step);
+            let current = *step;
+            *step = (current + 1) % self.pattern.len();
+            current
+        };
+        let reading = self.inner.read();
+        let position = format!("step {}/{}", step + 1, self.pattern.len());
+        if self.pattern[step] {
+            self.inner.take_freeze_frame();
+            Reading::text(format!("DTC {}: Injected Intermittent Fault ({})", injected_code(self.inner.kind()).as_str(), position))
+        } else if reading.status.contains("DTC") {
+            self.inner.take_freeze_frame();
+            Reading { status: format!("Injected pass ({})", position), ..reading }
+        } else {
+            reading
+        }
+    }
+    fn get_id(&self) -> CanId { self.inner.get_id() }
+    fn poll_interval(&self) -> Duration { self.inner.poll_interval() }
+    fn position(&self) -> Option<Position> { self.inner.position() }
+    fn history_snapshot(&self) -> Vec<f64> { self.inner.history_snapshot() }
+    fn timed_history(&self) -> Vec<(Instant, f64)> { self.inner.timed_history() }
+    fn trip_band(&self) -> Option<(f64, f64)> { self.inner.trip_band() }
+    fn persistent_history(&self) -> Option<Vec<f64>> { self.inner.persistent_history() }
+    fn restore_history(&self, history: &[f64]) { self.inner.restore_history(history) }
+    fn name(&self) -> String { format!("{} (fault injection)", self.inner.name()) }
+    fn kind(&self) -> &'static str { self.inner.kind() }
+    fn describe(&self) -> Vec<String> {
+        let pattern: String = self.pattern.iter().map(|&bad| if bad { 'x' } else { '.' }).collect();
+        let mut lines = self.inner.describe();
+        lines.push(format!("Injected pattern [{}], next step {}", pattern, *lock(&self.step) + 1));
+        lines
+    }
+    fn trend(&self) -> Option<(&'static str, Vec<u64>)> { self.inner.trend() }
+    fn read_pid(&self, pid: u8) -> Option<PidValue> { self.inner.read_pid(pid) }
+    fn clear_faults(&self) {
+        self.inner.clear_faults();
+        *lock(&self.step) = 0;
+    }
+    fn fault_tracker(&self) -> &Mutex<FaultTracker> { self.inner.fault_tracker() }
+    fn firmware_version(&self) -> String { self.inner.firmware_version() }
+    fn retune(&self, tuning: Tuning) { self.inner.retune(tuning) }
+    fn cycle_detector(&self) -> Option<ThermalDetector> { self.inner.cycle_detector() }
+    fn thermal(&self) -> Option<ThermalConfig> { self.inner.thermal() }
+    fn adjust_thermal(&self, adjust: &dyn Fn(&mut ThermalConfig)) -> Option<ThermalConfig> { self.inner.adjust_thermal(adjust) }
+
+    #[cfg(feature = "socketcan")]
+    fn decode_frame(&self, data: &[u8]) -> Option<String> { self.inner.decode_frame(data) }
+    #[cfg(feature = "can-dbc")]
+    fn apply_signals(&self, signals: &crate::dbc::Signals) -> Option<String> { self.inner.apply_signals(signals) }
+    #[cfg(feature = "metrics")]
+    fn gauges(&self) -> Vec<(&'static str, f64)> { self.inner.gauges() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adas(seed: u64) -> ADAS_Computer {
+        ADAS_Computer::new(CanId::new(0x291).unwrap(), "Front_Radar", DEFAULT_POLL_INTERVAL, seeded_rng(Some(seed)))
+            .with_warm_up(Duration::ZERO)
+    }
+
+    #[test]
+    fn seeded_adas_reports_sensor_blind() {
+        // With the default 10% fault probability, seed 3 draws a blind frame on the first poll.
+        assert_eq!(adas(3).check_status(), "DTC C1A67: Sensor Blind / Occluded");
+        let (a, b) = (adas(3), adas(3));
+        assert!((0..20).all(|_| a.check_status() == b.check_status()));
+    }
+
+    #[test]
+    fn full_db_queue_counts_drops_instead_of_blocking() {
+        let (ui, _ui_rx) = mpsc::channel();
+        let (db, db_rx) = mpsc::sync_channel(2);
+        let outbox = Outbox {
+            ui,
+            db,
+            db_dropped: Arc::new(AtomicU64::new(0)),
+            enabled: Arc::new(HashMap::new()),
+            buses: Arc::new(HashMap::new()),
+            severities: Arc::new(SeverityMap::default()),
+            #[cfg(feature = "metrics")]
+            metrics: Arc::new(crate::metrics::Registry::default()),
+            #[cfg(feature = "mqtt")]
+            mqtt: None,
+            #[cfg(feature = "syslog")]
+            syslog: None,
+            #[cfg(unix)]
+            ipc: None,
+            on_dtc: None,
+            #[cfg(feature = "websocket")]
+            websocket: None,
+        };
+        let dropped = Arc::clone(&outbox.db_dropped);
+        let (done, finished) = mpsc::channel();
+        std::thread::spawn(move || {
+            let sensor = adas(3);
+            for _ in 0..5 {
+                record_status(&outbox, &sensor, sensor.read());
+            }
+            done.send(()).unwrap();
+        });
+        finished.recv_timeout(Duration::from_secs(5)).expect("record_status blocked on the full DB queue");
+        assert_eq!(dropped.load(Ordering::Relaxed), 3);
+        assert_eq!(db_rx.try_iter().count(), 2);
+    }
+}
@@ -0,0 +1,1208 @@
+//! The SQLite blackbox: schema, batched writer, replay and exports.
+use std::io::{self, Write};
+use std::fs::File;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::AtomicBool;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use rusqlite::{params, Connection};
+use crate::{lock, sleep_unless_shutdown, Error, NO_RESPONSE, SensorUpdate};
+use crate::app_state::LogRecord;
+use crate::dtc::{CanId, DtcCode, FaultState, FaultTracker, FreezeFrame, PID_CELL_VOLTAGE, PID_TIRE_PRESSURE, PID_VEHICLE_SPEED, PidValue};
+use crate::sensors::{Measurement, SentinelComponent};
+
+pub const OBD_EXPORT_SCHEMA: &str = "rustyadv/obd-freeze-frame/1";
+pub const DB_BATCH_ROWS: usize = 100;
+pub const DB_BATCH_INTERVAL: Duration = Duration::from_millis(500);
+pub const DB_RETRY_MIN: Duration = Duration::from_millis(500);
+pub const DB_RETRY_MAX: Duration = Duration::from_secs(30);
+pub const DB_MAX_PENDING_ROWS: usize = 10_000;
+pub const DB_QUEUE_CAPACITY: usize = 4096;
+pub const RETENTION_INTERVAL: Duration = Duration::from_secs(60);
+pub const LATENCY_BUCKETS: usize = 10_000;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Session {
+    pub id: String,
+    pub started_at: String,
+    pub app_version: &'static str,
+    pub git_rev: Option<&'static str>,
+    pub vehicle_id: Option<String>,
+    // CAN ID -> firmware build, filled in once the sensors are built.
+    pub firmware: BTreeMap<String, String>,
+}
+
+impl Session {
+    pub fn new(vehicle_id: Option<String>) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            started_at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            app_version: env!("CARGO_PKG_VERSION"),
+            git_rev: option_env!("RUSTYADV_GIT_REV"),
+            vehicle_id,
+            firmware: BTreeMap::new(),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "{} (vehicle {}, v{} {})",
+            self.id,
+            self.vehicle_id.as_deref().unwrap_or("unset"),
+            self.app_version,
+            self.git_rev.unwrap_or("unknown rev"),
+        )
+    }
+}
+
+pub const DTC_RANGE_QUERY: &str = "SELECT sensor_id, message, timestamp_ms FROM sensor_logs \
+     WHERE message LIKE '%DTC%' AND timestamp_ms BETWEEN ?1 AND ?2 ORDER BY timestamp_ms, id";
+
+pub fn query_dtcs(conn: &Connection, from: DateTime<Utc>, to: DateTime<Utc>) -> rusqlite::Result<Vec<LogRecord>> {
+    let mut stmt = conn.prepare(DTC_RANGE_QUERY)?;
+    let rows = stmt.query_map(params![from.timestamp_millis(), to.timestamp_millis()], |row| {
+        let sensor_id: CanId = row.get(0)?;
+        let message: String = row.get(1)?;
+        let at = epoch_millis(row.get(2)?);
+        Ok(LogRecord::new(sensor_id, &message, true, at))
+    })?;
+    rows.collect()
+}
+
+pub const CONSOLE_HELP: &[&str] = &[
+    "count [dtc|all]      rows in sensor_logs (default: DTCs only)",
+    "top-sensor [n]       sensors with the most DTCs (default 1)",
+    "top-code [n]         most frequent DTC codes (default 5)",
+    "last [n]             newest DTCs (default 10)",
+    "sensor <can_id> [n]  newest rows from one sensor (default 10)",
+    "help                 this list",
+];
+pub const CONSOLE_MAX_ROWS: usize = 200;
+
+pub fn console_count(arg: Option<&str>, default: usize) -> Result<usize, Error> {
+    match arg {
+        None => Ok(default),
+        Some(n) => match n.parse::<usize>() {
+            Ok(n) if (1..=CONSOLE_MAX_ROWS).contains(&n) => Ok(n),
+            _ => Err(Error::Console(format!("expected a row count from 1 to {}, got '{}'", CONSOLE_MAX_ROWS, n))),
+        },
+    }
+}
+
+pub fn console_records(stmt: &mut rusqlite::Statement, params: impl rusqlite::Params) -> rusqlite::Result<Vec<String>> {
+    let rows = stmt.query_map(params, |row| {
+        let sensor_id: CanId = row.get(0)?;
+        let message: String = row.get(1)?;
+        let at = epoch_millis(row.get(2)?).with_timezone(&chrono::Local);
+        Ok(format!("{} [CAN ID {}] {}", at.format("%Y-%m-%d %H:%M:%S%.3f"), sensor_id, message))
+    })?;
+    rows.collect()
+}
+
+fn console_lines(conn: &Connection, words: &[&str]) -> Result<Vec<String>, Error> {
+    match words {
+        [] | ["help"] => Ok(CONSOLE_HELP.iter().map(|line| line.to_string()).collect()),
+        ["count"] | ["count", "dtc"] => {
+            let n: i64 = conn.query_row("SELECT COUNT(*) FROM sensor_logs WHERE message LIKE '%DTC%'", [], |row| row.get(0))?;
+            Ok(vec![format!("{} DTCs recorded", n)])
+        }
+        ["count", "all"] => {
+            let n: i64 = conn.query_row("SELECT COUNT(*) FROM sensor_logs", [], |row| row.get(0))?;
+            Ok(vec![format!("{} rows recorded", n)])
+        }
+        ["top-sensor", rest @ ..] if rest.len() <= 1 => {
+            let n = console_count(rest.first().copied(), 1)?;
+            let mut stmt = conn.prepare(
+                "SELECT sensor_id, COUNT(*) FROM sensor_logs WHERE message LIKE '%DTC%' GROUP BY sensor_id ORDER BY 2 DESC LIMIT ?1",
+            )?;
+            let rows = stmt.query_map(params![n as i64], |row| Ok(format!("CAN ID {}: {} DTCs", row.get::<_, CanId>(0)?, row.get::<_, i64>(1)?)))?;
+            Ok(rows.collect::<rusqlite::Result<_>>()?)
+        }
+        ["top-code", rest @ ..] if rest.len() <= 1 => {
+            let n = console_count(rest.first().copied(), 5)?;
+            let mut stmt = conn.prepare(
+                "SELECT substr(message, instr(message, 'DTC ') + 4, 5) AS code, COUNT(*) FROM sensor_logs \
+                 WHERE message LIKE '%DTC %' GROUP BY code ORDER BY 2 DESC, code LIMIT ?1",
+            )?;
+            let rows = stmt.query_map(params![n as i64], |row| {
+                let code: String = row.get(0)?;
+                let description = DtcCode::from_code(&code).map_or("", |c| c.description());
+                Ok(format!("{}: {} ({})", code, row.get::<_, i64>(1)?, description))
+            })?;
+            Ok(rows.collect::<rusqlite::Result<_>>()?)
+        }
+        ["last", rest @ ..] if rest.len() <= 1 => {
+            let n = console_count(rest.first().copied(), 10)?;
+            let mut stmt = conn.prepare(
+                "SELECT sensor_id, message, timestamp_ms FROM \
+                 (SELECT id, sensor_id, message, timestamp_ms FROM sensor_logs WHERE message LIKE '%DTC%' ORDER BY timestamp_ms DESC, id DESC LIMIT ?1) \
+                 ORDER BY timestamp_ms, id",
+            )?;
+            Ok(console_records(&mut stmt, params![n as i64])?)
+        }
+        ["sensor", id, rest @ ..] if rest.len() <= 1 => {
+            let id = id.parse::<CanId>().map_err(Error::Console)?;
+            let n = console_count(rest.first().copied(), 10)?;
+            let mut stmt = conn.prepare(
+                "SELECT sensor_id, message, timestamp_ms FROM \
+                 (SELECT id, sensor_id, message, timestamp_ms FROM sensor_logs WHERE sensor_id = ?1 ORDER BY timestamp_ms DESC, id DESC LIMIT ?2) \
+                 ORDER BY timestamp_ms, id",
+            )?;
+            Ok(console_records(&mut stmt, params![id, n as i64])?)
+        }
+        [command, ..] => Err(Error::Console(format!("unknown command or arguments: '{}' (type help for the list)", command))),
+    }
+}
+
+// A small fixed vocabulary over prepared statements; anything else gets a hint instead of reaching SQLite.
+pub fn run_console_command(conn: &Connection, input: &str) -> Vec<String> {
+    let words: Vec<&str> = input.split_whitespace().collect();
+    match console_lines(conn, &words) {
+        Ok(lines) if lines.is_empty() => vec!["No matching rows in the blackbox.".to_string()],
+        Ok(lines) => lines,
+        Err(e) => vec![format!("Error: {}", e)],
+    }
+}
+
+pub fn epoch_millis(ms: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_millis(ms).unwrap_or_default()
+}
+
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// Returns the crate Error instead of rusqlite::Result, since creating and writing the file fail with io::Error.
+pub fn export_csv(conn: &Connection, path: &Path, bus: Option<u8>) -> Result<(), Error> {
+    let mut out = io::BufWriter::new(File::create(path)?);
+    write_csv(conn, &mut out, bus)?;
+    out.flush()?;
+    Ok(())
+}
+
+// InfluxDB line protocol, one point per row that carries a value, with nanosecond timestamps.
+pub fn export_influx(conn: &Connection, path: &Path) -> Result<usize, Error> {
+    let mut out = io::BufWriter::new(File::create(path)?);
+    let points = write_influx(conn, &mut out)?;
+    out.flush()?;
+    Ok(points)
+}
+
+pub fn write_influx(conn: &Connection, out: &mut impl Write) -> Result<usize, Error> {
+    let mut stmt = conn.prepare(
+        "SELECT measurement, sensor_id, value, timestamp_ms, unit, bus FROM sensor_logs
+         WHERE measurement IS NOT NULL AND value IS NOT NULL ORDER BY timestamp_ms, id",
+    )?;
+    let mut rows = stmt.query([])?;
+    let mut points = 0;
+    while let Some(row) = rows.next()? {
+        let measurement: String = row.get(0)?;
+        let sensor_id: CanId = row.get(1)?;
+        let value: f64 = row.get(2)?;
+        let timestamp_ms: i64 = row.get(3)?;
+        let unit: Option<String> = row.get(4)?;
+        let unit = unit.map_or(String::new(), |unit| format!(",unit={}", unit));
+        let bus: u8 = row.get(5)?;
+        writeln!(out, "{},sensor_id={},bus={}{} value={} {}", measurement, sensor_id, bus, unit, value, timestamp_ms * 1_000_000)?;
+        points += 1;
+    }
+    Ok(points)
+}
+
+// With a bus, only that bus's rows are written; markers belong to no bus and are always kept.
+pub fn write_csv(conn: &Connection, out: &mut impl Write, bus: Option<u8>) -> Result<(), Error> {
+    let mut sessions = conn.prepare("SELECT id, started_at, app_version, git_rev, vehicle_id, firmware FROM sessions ORDER BY started_at")?;
+    let mut rows = sessions.query([])?;
+    while let Some(row) = rows.next()? {
+        let id: String = row.get(0)?;
+        let started_at: String = row.get(1)?;
+        let app_version: String = row.get(2)?;
+        let git_rev: Option<String> = row.get(3)?;
+        let vehicle_id: Option<String> = row.get(4)?;
+        let firmware: Option<String> = row.get(5)?;
+        writeln!(
+            out,
+            "# session {} started {} app {} rev {} vehicle {} firmware {}",
+            id,
+            started_at,
+            app_version,
+            git_rev.as_deref().unwrap_or("-"),
+            vehicle_id.as_deref().unwrap_or("-"),
+            firmware.as_deref().unwrap_or("-"),
+        )?;
+    }
+    writeln!(out, "id,sensor_id,message,timestamp,session_id,acknowledged,kind,value,unit,bus")?;
+
+    // Markers are interleaved by time with an empty sensor_id so the analyst sees them in context.
+    let mut stmt = conn.prepare(
+        "SELECT id, sensor_id, message, timestamp, session_id, acknowledged, timestamp_ms, 'log' AS kind, value, unit, bus FROM sensor_logs_readable
+         WHERE ?1 IS NULL OR bus = ?1
+         UNION ALL
+         SELECT id, NULL, note, strftime('%Y-%m-%d %H:%M:%f', timestamp_ms / 1000.0, 'unixepoch'), session_id, 0, timestamp_ms, 'marker', NULL, NULL, NULL FROM markers
+         ORDER BY timestamp_ms, kind, id",
+    )?;
+    let mut rows = stmt.query(params![bus])?;
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let sensor_id: Option<u32> = row.get(1)?;
+        let message: Option<String> = row.get(2)?;
+        let timestamp: String = row.get(3)?;
+        let session_id: Option<String> = row.get(4)?;
+        let acknowledged: bool = row.get(5)?;
+        let kind: String = row.get(7)?;
+        let value: Option<f64> = row.get(8)?;
+        let unit: Option<String> = row.get(9)?;
+        let bus: Option<u8> = row.get(10)?;
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{},{},{},{}",
+            id,
+            sensor_id.map_or(String::new(), |id| id.to_string()),
+            csv_field(message.as_deref().unwrap_or_default()),
+            csv_field(&timestamp),
+            session_id.unwrap_or_default(),
+            acknowledged as u8,
+            kind,
+            value.map_or(String::new(), |v| v.to_string()),
+            csv_field(unit.as_deref().unwrap_or_default()),
+            bus.map_or(String::new(), |bus| bus.to_string()),
+        )?;
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+pub struct ObdExport {
+    pub schema: &'static str,
+    pub generated_at: String,
+    pub dtcs: Vec<ObdDtc>,
+}
+
+#[derive(Serialize)]
+pub struct ObdDtc {
+    pub dtc: &'static str,
+    pub description: &'static str,
+    pub ecu: String,
+    pub status: ObdStatus,
+    pub acknowledged: bool,
+    pub timestamp: String,
+    pub session_id: Option<String>,
+    pub message: String,
+    pub freeze_frame: Option<ObdFreezeFrame>,
+}
+
+#[derive(Serialize)]
+pub struct ObdStatus {
+    pub byte: String,
+    pub test_failed: bool,
+    pub pending_dtc: bool,
+    pub confirmed_dtc: bool,
+}
+
+impl ObdStatus {
+    // DTC status bits as in ISO 14229: 0x01 test failed, 0x04 pending, 0x08 confirmed.
+    pub fn of(fault: FaultState) -> Self {
+        let byte: u8 = match fault {
+            FaultState::Ok => 0x00,
+            FaultState::Pending(_) => 0x05,
+            FaultState::Confirmed => 0x0D,
+            FaultState::Healing(_) => 0x08,
+        };
+        Self { byte: format!("{:#04X}", byte), test_failed: byte & 0x01 != 0, pending_dtc: byte & 0x04 != 0, confirmed_dtc: byte & 0x08 != 0 }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ObdFreezeFrame {
+    pub pids: Vec<ObdPid>,
+    pub samples: Vec<f64>,
+    pub mean: f64,
+    pub std_dev: f64,
+}
+
+#[derive(Serialize)]
+pub struct ObdPid {
+    pub pid: String,
+    pub name: &'static str,
+    pub raw: String,
+    pub value: f64,
+    pub unit: &'static str,
+}
+
+impl From<&PidValue> for ObdPid {
+    fn from(value: &PidValue) -> Self {
+        Self {
+            pid: format!("{:#04X}", value.pid),
+            name: value.name,
+            raw: value.raw().iter().map(|b| format!("{:02X}", b)).collect(),
+            value: value.scaled(),
+            unit: value.unit,
+        }
+    }
+}
+
+// The PID a DTC's freeze frame samples belong to; codes whose samples have no PID only keep the raw samples.
+pub fn freeze_frame_pid(code: DtcCode, sample: f64) -> Option<PidValue> {
+    match code {
+        DtcCode::CellImbalance | DtcCode::RapidDischarge | DtcCode::CellSpread => Some(PidValue::new(PID_CELL_VOLTAGE, "Cell voltage", sample, "V")),
+        DtcCode::LowTirePressure => Some(PidValue::new(PID_TIRE_PRESSURE, "Lowest tire pressure", sample, "kPa")),
+        DtcCode::WheelSpeedCircuit => Some(PidValue::new(PID_VEHICLE_SPEED, "Vehicle speed", sample, "km/h")),
+        _ => None,
+    }
+}
+
+pub fn export_obd_json(conn: &Connection, path: &Path) -> Result<usize, Error> {
+    let export = build_obd_export(conn)?;
+    let mut out = io::BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut out, &export)?;
+    writeln!(out)?;
+    out.flush()?;
+    Ok(export.dtcs.len())
+}
+
+// Status bytes aren't stored, so each sensor's rows are run back through a FaultTracker like a replay does.
+pub fn build_obd_export(conn: &Connection) -> Result<ObdExport, Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, sensor_id, message, timestamp_ms, session_id, acknowledged FROM sensor_logs ORDER BY timestamp_ms, id",
+    )?;
+    let mut rows = stmt.query([])?;
+    let mut trackers: HashMap<CanId, FaultTracker> = HashMap::new();
+    let mut dtcs = Vec::new();
+    while let Some(row) = rows.next()? {
+        let log_id: i64 = row.get(0)?;
+        let id: CanId = row.get(1)?;
+        let message: String = row.get(2)?;
+        let is_dtc = message.contains("DTC");
+        let fault = trackers.entry(id).or_default().observe(is_dtc);
+        if !is_dtc { continue; }
+
+        let code = DtcCode::parse(&message)
+            .ok_or_else(|| Error::Export(format!("sensor_logs row {}: no known DTC code in {:?}", log_id, message)))?;
+        let at = row.get::<_, Option<i64>>(3)?.map(epoch_millis)
+            .ok_or_else(|| Error::Export(format!("sensor_logs row {}: missing timestamp", log_id)))?;
+        let freeze_frame = match get_freeze_frame(conn, log_id)? {
+            Some(frame) => {
+                let &last = frame.history.last().ok_or_else(|| Error::Export(format!("freeze frame for row {}: no samples", log_id)))?;
+                Some(ObdFreezeFrame {
+                    pids: freeze_frame_pid(code, last).iter().map(ObdPid::from).collect(),
+                    samples: frame.history,
+                    mean: frame.mean,
+                    std_dev: frame.std_dev,
+                })
+            }
+            None => None,
+        };
+        dtcs.push(ObdDtc {
+            dtc: code.as_str(),
+            description: code.description(),
+            ecu: format!("{}", id),
+            status: ObdStatus::of(fault),
+            acknowledged: row.get(5)?,
+            timestamp: at.to_rfc3339_opts(SecondsFormat::Millis, true),
+            session_id: row.get(4)?,
+            message,
+            freeze_frame,
+        });
+    }
+    Ok(ObdExport { schema: OBD_EXPORT_SCHEMA, generated_at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true), dtcs })
+}
+
+pub fn replay_sensor_ids(conn: &Connection) -> rusqlite::Result<Vec<CanId>> {
+    let mut stmt = conn.prepare("SELECT sensor_id FROM sensor_logs GROUP BY sensor_id ORDER BY MIN(id)")?;
+    let ids = stmt.query_map([], |row| row.get(0))?;
+    ids.collect()
+}
+
+pub fn recorded_buses(conn: &Connection) -> rusqlite::Result<HashMap<CanId, u8>> {
+    let mut stmt = conn.prepare("SELECT sensor_id, MAX(bus) FROM sensor_logs GROUP BY sensor_id")?;
+    let buses = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    buses.collect()
+}
+
+pub fn spawn_replay(path: &Path, speed: f64, ui: mpsc::Sender<SensorUpdate>, shutdown: Arc<AtomicBool>) -> rusqlite::Result<thread::JoinHandle<()>> {
+    let conn = Connection::open(path)?;
+    Ok(thread::spawn(move || {
+        let replay = || -> rusqlite::Result<()> {
+            let mut stmt = conn.prepare(
+                "SELECT id, sensor_id, message, timestamp_ms, acknowledged FROM sensor_logs ORDER BY timestamp_ms, id",
+            )?;
+            let mut rows = stmt.query([])?;
+            let mut previous: Option<DateTime<Utc>> = None;
+            let mut trackers: HashMap<CanId, FaultTracker> = HashMap::new();
+            while let Some(row) = rows.next()? {
+                let log_id: i64 = row.get(0)?;
+                let id: CanId = row.get(1)?;
+                let status: String = row.get(2)?;
+                let Some(recorded) = row.get::<_, Option<i64>>(3)?.map(epoch_millis) else { continue };
+                let acknowledged: bool = row.get(4)?;
+
+                if let Some(prev) = previous {
+                    let gap = (recorded - prev).to_std().unwrap_or_default();
+                    if !sleep_unless_shutdown(&shutdown, gap.div_f64(speed)) { return Ok(()); }
+                }
+                previous = Some(recorded);
+
+                let is_dtc = status.contains("DTC");
+                let freeze = if is_dtc { get_freeze_frame(&conn, log_id).ok().flatten() } else { None };
+                let fault = trackers.entry(id).or_default().observe(is_dtc);
+                let _ = ui.send(SensorUpdate::Status { id, status, is_dtc, fault, at: recorded, freeze, acknowledged, position: None });
+            }
+            Ok(())
+        };
+        if replay().is_ok() {
+            let _ = ui.send(SensorUpdate::ReplayComplete);
+        }
+    }))
+}
+
+pub type Migration = (&'static str, fn(&Connection) -> rusqlite::Result<bool>);
+
+// Schema changes in the order they shipped; PRAGMA user_version counts how many a blackbox has had.
+// Each step reports whether it changed anything and is a no-op when it has nothing to do, because
+// files written before the counter existed may already have some of them.
+pub const MIGRATIONS: &[Migration] = &[
+    ("add sensor_logs.cleared_at", |conn| ensure_column(conn, "sensor_logs", "cleared_at", "TEXT")),
+    ("add sensor_logs.session_id", |conn| ensure_column(conn, "sensor_logs", "session_id", "TEXT REFERENCES sessions(id)")),
+    ("add sessions.firmware", |conn| ensure_column(conn, "sessions", "firmware", "TEXT")),
+    ("add sensor_logs.acknowledged", |conn| ensure_column(conn, "sensor_logs", "acknowledged", "INTEGER NOT NULL DEFAULT 0")),
+    ("store timestamps as epoch milliseconds", migrate_text_timestamps),
+    ("add sensor_logs.measurement", |conn| ensure_column(conn, "sensor_logs", "measurement", "TEXT")),
+    ("add sensor_logs.value", |conn| ensure_column(conn, "sensor_logs", "value", "REAL")),
+    ("add sensor_logs.unit", |conn| ensure_column(conn, "sensor_logs", "unit", "TEXT")),
+    ("add sensor_logs.bus", |conn| ensure_column(conn, "sensor_logs", "bus", "INTEGER NOT NULL DEFAULT 0")),
+];
+
+pub const SCHEMA_VERSION: usize = MIGRATIONS.len();
+
+pub fn init_db(conn: &Connection) -> rusqlite::Result<Vec<&'static str>> {
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
+    upgrade_schema(conn)
+}
+
+// Also run on recordings opened for replay, which may predate these columns. Returns the
+// migrations that changed a file which already held a blackbox, for the startup log.
+pub fn upgrade_schema(conn: &Connection) -> rusqlite::Result<Vec<&'static str>> {
+    let existing = has_table(conn, "sensor_logs")?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS sensor_logs (id INTEGER PRIMARY KEY, sensor_id INTEGER, message TEXT, timestamp_ms INTEGER NOT NULL, cleared_at TEXT);
+         CREATE TABLE IF NOT EXISTS freeze_frames (id INTEGER PRIMARY KEY, log_id INTEGER REFERENCES sensor_logs(id), history TEXT, mean REAL, std_dev REAL);
+         CREATE INDEX IF NOT EXISTS idx_sensor_id ON sensor_logs(sensor_id);
+         CREATE INDEX IF NOT EXISTS idx_freeze_log_id ON freeze_frames(log_id);
+         CREATE TABLE IF NOT EXISTS component_state (can_id INTEGER PRIMARY KEY, history TEXT, saved_at TEXT DEFAULT CURRENT_TIMESTAMP);
+         CREATE TABLE IF NOT EXISTS sessions (id TEXT PRIMARY KEY, started_at TEXT, app_version TEXT, git_rev TEXT, vehicle_id TEXT);
+         CREATE TABLE IF NOT EXISTS markers (id INTEGER PRIMARY KEY, timestamp_ms INTEGER NOT NULL, note TEXT, session_id TEXT);",
+    )?;
+    let applied = migrate(conn)?;
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_session_id ON sensor_logs(session_id);
+         CREATE INDEX IF NOT EXISTS idx_timestamp_ms ON sensor_logs(timestamp_ms);
+         DROP VIEW IF EXISTS sensor_logs_readable;
+         CREATE VIEW sensor_logs_readable AS
+             SELECT id, sensor_id, message, timestamp_ms,
+                    strftime('%Y-%m-%d %H:%M:%f', timestamp_ms / 1000.0, 'unixepoch') AS timestamp,
+                    cleared_at, session_id, acknowledged, measurement, value, unit, bus
+             FROM sensor_logs;",
+    )?;
+    Ok(if existing { applied } else { Vec::new() })
+}
+
+// Each step commits together with its version bump, so an interrupted upgrade resumes where it stopped.
+pub fn migrate(conn: &Connection) -> rusqlite::Result<Vec<&'static str>> {
+    let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    let mut applied = Vec::new();
+    for (index, (name, step)) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.unchecked_transaction()?;
+        if step(&tx)? {
+            applied.push(*name);
+        }
+        tx.pragma_update(None, "user_version", index + 1)?;
+        tx.commit()?;
+    }
+    Ok(applied)
+}
+
+// Blackboxes written before timestamp_ms stored CURRENT_TIMESTAMP text (UTC, second resolution) in `timestamp`.
+// SQLite cannot add a NOT NULL column without a default, so the table is rebuilt with the columns it has
+// at this point. A timestamp that does not parse is stored as 0, the Unix epoch, so the row is kept but
+// sorts first and no range query for a real drive picks it up.
+pub fn migrate_text_timestamps(conn: &Connection) -> rusqlite::Result<bool> {
+    if !has_column(conn, "sensor_logs", "timestamp")? { return Ok(false); }
+    conn.execute_batch(
+        "DROP VIEW IF EXISTS sensor_logs_readable;
+         CREATE TABLE sensor_logs_rebuilt (id INTEGER PRIMARY KEY, sensor_id INTEGER, message TEXT, timestamp_ms INTEGER NOT NULL, cleared_at TEXT,
+             session_id TEXT REFERENCES sessions(id), acknowledged INTEGER NOT NULL DEFAULT 0);
+         INSERT INTO sensor_logs_rebuilt (id, sensor_id, message, timestamp_ms, cleared_at, session_id, acknowledged)
+             SELECT id, sensor_id, message, coalesce(CAST(round((julianday(timestamp) - 2440587.5) * 86400000.0) AS INTEGER), 0),
+                    cleared_at, session_id, acknowledged
+             FROM sensor_logs;
+         DROP TABLE sensor_logs;
+         ALTER TABLE sensor_logs_rebuilt RENAME TO sensor_logs;
+         CREATE INDEX IF NOT EXISTS idx_sensor_id ON sensor_logs(sensor_id);",
+    )?;
+    Ok(true)
+}
+
+// The clip is an ordinary blackbox holding only the window's rows, their freeze frames, the
+// markers in it and the sessions that wrote them, so --replay reads it like any other.
+pub fn export_clip(source: &Connection, path: &Path, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<usize, Error> {
+    if from > to {
+        return Err(Error::Clip("--clip-from must not be after --clip-to".to_string()));
+    }
+    if path.exists() {
+        return Err(Error::Clip(format!("{} already exists", path.display())));
+    }
+    let source_path = source.path().ok_or_else(|| Error::Clip("the blackbox has no file to clip from".to_string()))?;
+    let clip = Connection::open(path)?;
+    let copied = copy_clip(&clip, source_path, from, to);
+    drop(clip);
+    if copied.is_err() {
+        let _ = std::fs::remove_file(path);
+    }
+    Ok(copied?)
+}
+
+pub fn copy_clip(clip: &Connection, source_path: &str, from: DateTime<Utc>, to: DateTime<Utc>) -> rusqlite::Result<usize> {
+    init_db(clip)?;
+    clip.execute("ATTACH DATABASE ?1 AS source", params![source_path])?;
+    let window = params![from.timestamp_millis(), to.timestamp_millis()];
+    let tx = clip.unchecked_transaction()?;
+    tx.execute(
+        "INSERT INTO sessions (id, started_at, app_version, git_rev, vehicle_id, firmware)
+         SELECT id, started_at, app_version, git_rev, vehicle_id, firmware FROM source.sessions
+         WHERE id IN (SELECT session_id FROM source.sensor_logs WHERE timestamp_ms BETWEEN ?1 AND ?2)",
+        window,
+    )?;
+    let rows = tx.execute(
+        "INSERT INTO sensor_logs (id, sensor_id, message, timestamp_ms, cleared_at, session_id, acknowledged, measurement, value, unit, bus)
+         SELECT id, sensor_id, message, timestamp_ms, cleared_at, session_id, acknowledged, measurement, value, unit, bus FROM source.sensor_logs
+         WHERE timestamp_ms BETWEEN ?1 AND ?2",
+        window,
+    )?;
+    tx.execute_batch(
+        "INSERT INTO freeze_frames (id, log_id, history, mean, std_dev)
+         SELECT id, log_id, history, mean, std_dev FROM source.freeze_frames WHERE log_id IN (SELECT id FROM sensor_logs);",
+    )?;
+    tx.execute(
+        "INSERT INTO markers (id, timestamp_ms, note, session_id)
+         SELECT id, timestamp_ms, note, session_id FROM source.markers WHERE timestamp_ms BETWEEN ?1 AND ?2",
+        window,
+    )?;
+    tx.commit()?;
+    clip.execute("DETACH DATABASE source", [])?;
+    Ok(rows)
+}
+
+pub fn open_blackbox(conn: &Connection, vehicle_id: Option<String>) -> rusqlite::Result<Session> {
+    let session = Session::new(vehicle_id);
+    start_session(conn, &session)?;
+    Ok(session)
+}
+
+pub fn start_session(conn: &Connection, session: &Session) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO sessions (id, started_at, app_version, git_rev, vehicle_id) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![session.id, session.started_at, session.app_version, session.git_rev, session.vehicle_id],
+    )?;
+    Ok(())
+}
+
+pub fn record_firmware(conn: &Connection, session: &mut Session, sensors: &[Box<dyn SentinelComponent>]) -> rusqlite::Result<()> {
+    session.firmware = sensors.iter().map(|s| (s.get_id().to_string(), s.firmware_version())).collect();
+    let firmware = serde_json::to_string(&session.firmware).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    conn.execute("UPDATE sessions SET firmware = ?1 WHERE id = ?2", params![firmware, session.id])?;
+    Ok(())
+}
+
+pub fn load_state(conn: &Connection, can_id: CanId) -> rusqlite::Result<Vec<f64>> {
+    let mut stmt = conn.prepare("SELECT history FROM component_state WHERE can_id = ?1")?;
+    let mut rows = stmt.query(params![can_id])?;
+    let Some(row) = rows.next()? else { return Ok(Vec::new()) };
+    let history: String = row.get(0)?;
+    serde_json::from_str(&history)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e)))
+}
+
+pub fn save_state(conn: &Connection, can_id: CanId, history: &[f64]) -> rusqlite::Result<()> {
+    let history = serde_json::to_string(history).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    conn.execute(
+        "INSERT OR REPLACE INTO component_state (can_id, history, saved_at) VALUES (?1, ?2, CURRENT_TIMESTAMP)",
+        params![can_id, history],
+    )?;
+    Ok(())
+}
+
+pub fn has_column(conn: &Connection, table: &str, column: &str) -> rusqlite::Result<bool> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt.query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<Vec<_>>>()?
+        .iter()
+        .any(|name| name == column);
+    Ok(exists)
+}
+
+pub fn has_table(conn: &Connection, table: &str) -> rusqlite::Result<bool> {
+    conn.query_row("SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)", params![table], |row| row.get(0))
+}
+
+pub fn ensure_column(conn: &Connection, table: &str, column: &str, decl: &str) -> rusqlite::Result<bool> {
+    if has_column(conn, table, column)? { return Ok(false); }
+    conn.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, decl))?;
+    Ok(true)
+}
+
+pub fn acknowledge_dtcs(conn: &mut Connection, keys: &mut Vec<AckKey>) -> rusqlite::Result<()> {
+    if keys.is_empty() { return Ok(()); }
+    let tx = conn.transaction()?;
+    {
+        let mut update = tx.prepare_cached(
+            "UPDATE sensor_logs SET acknowledged = 1 WHERE sensor_id = ?1 AND message = ?2 AND timestamp_ms = ?3",
+        )?;
+        for key in keys.iter() {
+            update.execute(params![key.sensor_id, key.message, key.at.timestamp_millis()])?;
+        }
+    }
+    tx.commit()?;
+    keys.clear();
+    Ok(())
+}
+
+pub fn insert_markers(conn: &mut Connection, markers: &mut Vec<Marker>, session_id: Option<&str>) -> rusqlite::Result<()> {
+    if markers.is_empty() { return Ok(()); }
+    let tx = conn.transaction()?;
+    {
+        let mut insert = tx.prepare_cached("INSERT INTO markers (timestamp_ms, note, session_id) VALUES (?1, ?2, ?3)")?;
+        for marker in markers.iter() {
+            insert.execute(params![marker.at.timestamp_millis(), marker.note, session_id])?;
+        }
+    }
+    tx.commit()?;
+    markers.clear();
+    Ok(())
+}
+
+pub fn clear_dtcs(conn: &Connection) -> rusqlite::Result<usize> {
+    conn.execute(
+        "UPDATE sensor_logs SET cleared_at = CURRENT_TIMESTAMP WHERE cleared_at IS NULL AND message LIKE '%DTC%'",
+        [],
+    )
+}
+
+pub struct DbRecord {
+    pub sensor_id: CanId,
+    pub message: String,
+    pub at: DateTime<Utc>,
+    pub freeze: Option<FreezeFrame>,
+    pub detected: Instant,
+    pub measurement: Option<Measurement>,
+    pub bus: u8,
+}
+
+// Detection-to-commit latency of DTC rows in 1 ms buckets; anything slower lands in the last one.
+pub struct LatencyHistogram {
+    pub buckets: Vec<u64>,
+    pub count: u64,
+    pub max: Duration,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self { buckets: vec![0; LATENCY_BUCKETS], count: 0, max: Duration::ZERO }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency: Duration) {
+        let bucket = (latency.as_millis() as usize).min(LATENCY_BUCKETS - 1);
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.max = self.max.max(latency);
+    }
+
+    // Whole milliseconds of the bucket holding the q-th sample.
+    pub fn percentile(&self, q: f64) -> Option<u64> {
+        if self.count == 0 { return None; }
+        let rank = ((q * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        self.buckets.iter().position(|&n| { seen += n; seen >= rank }).map(|bucket| bucket as u64)
+    }
+
+    pub fn summary(&self) -> String {
+        match (self.percentile(0.5), self.percentile(0.95), self.percentile(0.99)) {
+            (Some(p50), Some(p95), Some(p99)) => format!(
+                "p50 {} ms, p95 {} ms, p99 {} ms, max {} ms ({} DTCs)",
+                p50, p95, p99, self.max.as_millis(), self.count,
+            ),
+            _ => "no DTCs committed".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AckKey {
+    pub sensor_id: CanId,
+    pub message: String,
+    pub at: DateTime<Utc>,
+}
+
+pub enum DbCommand {
+    Insert(DbRecord),
+    ClearDtcs,
+    Acknowledge(Vec<AckKey>),
+    Marker(Marker),
+}
+
+pub struct Marker {
+    pub at: DateTime<Utc>,
+    pub note: String,
+}
+
+pub fn flush_batch(
+    conn: &mut Connection,
+    batch: &mut VecDeque<DbRecord>,
+    session_id: Option<&str>,
+    latency: &Mutex<LatencyHistogram>,
+) -> rusqlite::Result<()> {
+    if batch.is_empty() { return Ok(()); }
+    let tx = conn.transaction()?;
+    {
+        let mut insert = tx.prepare_cached(
+            "INSERT INTO sensor_logs (sensor_id, message, timestamp_ms, session_id, measurement, value, unit, bus) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        )?;
+        for record in batch.iter() {
+            let m = record.measurement.as_ref();
+            insert.execute(params![
+                record.sensor_id, record.message, record.at.timestamp_millis(), session_id,
+                m.map(|m| m.name), m.map(|m| m.value), m.map(|m| m.unit), record.bus,
+            ])?;
+            if let Some(frame) = &record.freeze {
+                save_freeze_frame(&tx, tx.last_insert_rowid(), frame)?;
+            }
+        }
+    }
+    tx.commit()?;
+    let committed = Instant::now();
+    let mut latency = lock(latency);
+    for record in batch.iter().filter(|r| r.message.contains("DTC")) {
+        latency.record(committed.duration_since(record.detected));
+    }
+    batch.clear();
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Retention {
+    pub dtc_days: Option<u32>,
+    pub normal_days: Option<u32>,
+    pub vacuum_every: Option<Duration>,
+    pub downsample: Downsample,
+}
+
+// How many normal readings per sensor reach the blackbox. DTCs and NO RESPONSE rows are always
+// written, and so is the first normal reading after one, so every recovery is on record.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Downsample {
+    #[default]
+    Off,
+    Every(u32),
+    Interval(Duration),
+}
+
+pub fn parse_downsample(s: &str) -> Result<Downsample, String> {
+    let invalid = || format!("expected a number of readings (e.g. 10) or seconds (e.g. 30s), got {}", s);
+    match s.strip_suffix('s') {
+        Some(secs) => match secs.parse::<u64>() {
+            Ok(secs) if secs > 0 => Ok(Downsample::Interval(Duration::from_secs(secs))),
+            _ => Err(invalid()),
+        },
+        None => match s.parse::<u32>() {
+            Ok(1) => Ok(Downsample::Off),
+            Ok(n) if n > 1 => Ok(Downsample::Every(n)),
+            _ => Err(invalid()),
+        },
+    }
+}
+
+// Per sensor: normal readings skipped since the last one written, and when that was.
+#[derive(Default)]
+pub struct NormalRows {
+    pub kept: HashMap<CanId, (u32, DateTime<Utc>)>,
+}
+
+impl NormalRows {
+    pub fn keep(&mut self, downsample: Downsample, record: &DbRecord) -> bool {
+        if downsample == Downsample::Off { return true; }
+        if record.message.contains("DTC") || record.message == NO_RESPONSE {
+            self.kept.remove(&record.sensor_id);
+            return true;
+        }
+        let keep = self.kept.get(&record.sensor_id).is_none_or(|&(skipped, at)| match downsample {
+            Downsample::Off => true,
+            Downsample::Every(n) => skipped + 1 >= n,
+            Downsample::Interval(every) => (record.at - at).to_std().is_ok_and(|elapsed| elapsed >= every),
+        });
+        let kept = self.kept.entry(record.sensor_id).or_insert((0, record.at));
+        *kept = if keep { (0, record.at) } else { (kept.0 + 1, kept.1) };
+        keep
+    }
+}
+
+pub fn prune_logs(conn: &mut Connection, now: DateTime<Utc>, retention: &Retention) -> rusqlite::Result<usize> {
+    let cutoff = |days: u32| (now - chrono::Duration::days(days as i64)).timestamp_millis();
+    let rules = [
+        (retention.dtc_days, "message LIKE '%DTC%'"),
+        (retention.normal_days, "message NOT LIKE '%DTC%'"),
+    ];
+    let tx = conn.transaction()?;
+    let mut deleted = 0;
+    for (days, matching) in rules {
+        let Some(days) = days else { continue };
+        let cutoff = cutoff(days);
+        tx.execute(
+            &format!("DELETE FROM freeze_frames WHERE log_id IN (SELECT id FROM sensor_logs WHERE timestamp_ms < ?1 AND {})", matching),
+            params![cutoff],
+        )?;
+        deleted += tx.execute(&format!("DELETE FROM sensor_logs WHERE timestamp_ms < ?1 AND {}", matching), params![cutoff])?;
+    }
+    tx.commit()?;
+    Ok(deleted)
+}
+
+pub fn spawn_db_writer(
+    db: Arc<Mutex<Connection>>,
+    commands: mpsc::Receiver<DbCommand>,
+    retention: Retention,
+    session_id: Option<String>,
+    ui: mpsc::Sender<SensorUpdate>,
+    latency: Arc<Mutex<LatencyHistogram>>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let notice = |msg: String| { let _ = ui.send(SensorUpdate::Notice(msg)); };
+        let mut batch = VecDeque::with_capacity(DB_BATCH_ROWS);
+        let mut clear_pending = false;
+        let mut acks = Vec::new();
+        let mut markers = Vec::new();
+        let mut deadline = Instant::now() + DB_BATCH_INTERVAL;
+        let mut next_prune = Instant::now();
+        let mut next_vacuum = retention.vacuum_every.map(|every| Instant::now() + every);
+        let mut retry_at = Instant::now();
+        let mut backoff: Option<Duration> = None;
+        let mut dropped = 0;
+        let mut normal_rows = NormalRows::default();
+        loop {
+            let disconnected = match commands.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(DbCommand::Insert(record)) => {
+                    if normal_rows.keep(retention.downsample, &record) {
+                        batch.push_back(record);
+                    }
+                    if batch.len() > DB_MAX_PENDING_ROWS {
+                        if dropped == 0 {
+                            notice(format!("[DB] {} rows pending, dropping the oldest until writes succeed", DB_MAX_PENDING_ROWS));
+                        }
+                        batch.pop_front();
+                        dropped += 1;
+                    }
+                    if batch.len() < DB_BATCH_ROWS && Instant::now() < deadline { continue; }
+                    false
+                }
+                Ok(DbCommand::ClearDtcs) => {
+                    clear_pending = true;
+                    false
+                }
+                Ok(DbCommand::Acknowledge(keys)) => {
+                    acks.extend(keys);
+                    false
+                }
+                Ok(DbCommand::Marker(marker)) => {
+                    markers.push(marker);
+                    false
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => false,
+                Err(mpsc::RecvTimeoutError::Disconnected) => true,
+            };
+            let now = Instant::now();
+            deadline = now + DB_BATCH_INTERVAL;
+            if now < retry_at && !disconnected { continue; }
+            let mut conn = lock(&db);
+            let result = flush_batch(&mut conn, &mut batch, session_id.as_deref(), &latency)
+                .map_err(|e| ("write", e))
+                .and_then(|()| acknowledge_dtcs(&mut conn, &mut acks).map_err(|e| ("acknowledge", e)))
+                .and_then(|()| insert_markers(&mut conn, &mut markers, session_id.as_deref()).map_err(|e| ("marker", e)))
+                .and_then(|()| {
+                    if clear_pending {
+                        clear_dtcs(&conn).map_err(|e| ("clear", e))?;
+                        clear_pending = false;
+                    }
+                    if (retention.dtc_days.is_some() || retention.normal_days.is_some()) && now >= next_prune {
+                        prune_logs(&mut conn, Utc::now(), &retention).map_err(|e| ("retention", e))?;
+                        next_prune = now + RETENTION_INTERVAL;
+                    }
+                    if let (Some(due), Some(every)) = (next_vacuum, retention.vacuum_every) && now >= due {
+                        conn.execute_batch("VACUUM").map_err(|e| ("vacuum", e))?;
+                        next_vacuum = Some(now + every);
+                    }
+                    Ok(())
+                });
+            drop(conn);
+            match result {
+                Ok(()) => {
+                    if backoff.take().is_some() {
+                        match dropped {
+                            0 => notice("[DB] writes recovered".to_string()),
+                            n => notice(format!("[DB] writes recovered, {} rows were dropped", n)),
+                        }
+                        dropped = 0;
+                    }
+                }
+                Err((what, e)) if disconnected => {
+                    eprintln!("[DB] {} failed during shutdown, {} rows lost: {}", what, batch.len(), e);
+                }
+                Err((what, e)) => {
+                    let delay = backoff.map_or(DB_RETRY_MIN, |d| (d * 2).min(DB_RETRY_MAX));
+                    notice(format!("[DB] {} failed: {}; retrying in {} ms", what, e, delay.as_millis()));
+                    backoff = Some(delay);
+                    retry_at = now + delay;
+                }
+            }
+            if disconnected { break; }
+        }
+    })
+}
+
+pub fn save_freeze_frame(conn: &Connection, log_id: i64, frame: &FreezeFrame) -> rusqlite::Result<()> {
+    let history = serde_json::to_string(&frame.history).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    conn.execute(
+        "INSERT INTO freeze_frames (log_id, history, mean, std_dev) VALUES (?1, ?2, ?3, ?4)",
+        params![log_id, history, frame.mean, frame.std_dev],
+    )?;
+    Ok(())
+}
+
+pub fn get_freeze_frame(conn: &Connection, log_id: i64) -> rusqlite::Result<Option<FreezeFrame>> {
+    let mut stmt = conn.prepare("SELECT history, mean, std_dev FROM freeze_frames WHERE log_id = ?1")?;
+    let mut rows = stmt.query(params![log_id])?;
+    let Some(row) = rows.next()? else { return Ok(None) };
+    let history: String = row.get(0)?;
+    let history = serde_json::from_str(&history)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e)))?;
+    Ok(Some(FreezeFrame { history, mean: row.get(1)?, std_dev: row.get(2)? }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blackbox() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        conn
+    }
+
+    fn at(ms: i64) -> DateTime<Utc> { epoch_millis(ms) }
+
+    fn record(id: u32, message: &str, ms: i64) -> DbRecord {
+        DbRecord {
+            sensor_id: CanId::new(id).unwrap(),
+            message: message.to_string(),
+            at: at(ms),
+            freeze: None,
+            detected: Instant::now(),
+            measurement: None,
+            bus: 0,
+        }
+    }
+
+    fn write(conn: &mut Connection, records: Vec<DbRecord>) {
+        let mut batch = VecDeque::from(records);
+        flush_batch(conn, &mut batch, None, &Mutex::new(LatencyHistogram::default())).unwrap();
+        assert!(batch.is_empty());
+    }
+
+    fn user_version(conn: &Connection) -> usize {
+        conn.pragma_query_value(None, "user_version", |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn fresh_blackbox_gets_the_current_schema() {
+        let conn = Connection::open_in_memory().unwrap();
+        assert_eq!(init_db(&conn).unwrap(), Vec::<&str>::new());
+        assert_eq!(user_version(&conn), SCHEMA_VERSION);
+        for column in ["timestamp_ms", "cleared_at", "session_id", "acknowledged", "measurement", "value", "unit", "bus"] {
+            assert!(has_column(&conn, "sensor_logs", column).unwrap(), "missing sensor_logs.{}", column);
+        }
+        assert!(!has_column(&conn, "sensor_logs", "timestamp").unwrap());
+        assert!(has_column(&conn, "sessions", "firmware").unwrap());
+        assert!(has_table(&conn, "markers").unwrap());
+    }
+
+    #[test]
+    fn baseline_blackbox_is_upgraded_in_place() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE sensor_logs (id INTEGER PRIMARY KEY, sensor_id INTEGER, message TEXT, timestamp TEXT DEFAULT CURRENT_TIMESTAMP);
+             INSERT INTO sensor_logs (sensor_id, message, timestamp) VALUES (390, 'DTC P0A80: Thermal Runaway Risk', '2026-01-01 10:00:00');",
+        ).unwrap();
+        let applied = init_db(&conn).unwrap();
+        assert_eq!(applied, MIGRATIONS.iter().map(|(name, _)| *name).collect::<Vec<_>>());
+        assert_eq!(user_version(&conn), SCHEMA_VERSION);
+        assert!(!has_column(&conn, "sensor_logs", "timestamp").unwrap());
+        let (ms, acknowledged, bus): (i64, bool, u8) = conn
+            .query_row("SELECT timestamp_ms, acknowledged, bus FROM sensor_logs", [], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap();
+        assert_eq!(ms, 1_767_261_600_000);
+        assert!(!acknowledged);
+        assert_eq!(bus, 0);
+        assert_eq!(init_db(&conn).unwrap(), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn unparseable_baseline_timestamps_fall_back_to_the_epoch_and_the_column_is_required() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE sensor_logs (id INTEGER PRIMARY KEY, sensor_id INTEGER, message TEXT, timestamp TEXT DEFAULT CURRENT_TIMESTAMP);
+             CREATE INDEX idx_timestamp ON sensor_logs(timestamp);
+             INSERT INTO sensor_logs (sensor_id, message, timestamp) VALUES (390, 'Cell Voltage: 3.91V (Optimal)', NULL);
+             INSERT INTO sensor_logs (sensor_id, message, timestamp) VALUES (390, 'DTC P0A80: Thermal Runaway Risk', 'yesterday-ish');
+             INSERT INTO sensor_logs (sensor_id, message, timestamp) VALUES (390, 'Cell Voltage: 3.92V (Optimal)', '2026-01-01 10:00:00');",
+        ).unwrap();
+        init_db(&conn).unwrap();
+        let mut stmt = conn.prepare("SELECT timestamp_ms FROM sensor_logs ORDER BY id").unwrap();
+        let stamps: Vec<i64> = stmt.query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+        assert_eq!(stamps, [0, 0, 1_767_261_600_000]);
+        let not_null: bool = conn
+            .query_row("SELECT \"notnull\" FROM pragma_table_info('sensor_logs') WHERE name = 'timestamp_ms'", [], |row| row.get(0))
+            .unwrap();
+        assert!(not_null);
+        assert!(conn.execute("INSERT INTO sensor_logs (sensor_id, message) VALUES (390, 'no time')", []).is_err());
+        assert_eq!(query_dtcs(&conn, at(1), at(i64::MAX / 2)).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn flushed_dtcs_come_back_from_query_dtcs() {
+        let mut conn = blackbox();
+        write(&mut conn, vec![
+            record(0x186, "Cell Voltage: 3.91V (Optimal)", 1_000),
+            record(0x186, "DTC P0A80: Thermal Runaway Risk, 3.21V", 2_000),
+            record(0x18FE_F100, "DTC U0100: Lost Communication", 3_000),
+        ]);
+        let dtcs = query_dtcs(&conn, at(0), at(10_000)).unwrap();
+        let rows: Vec<(&str, &str)> = dtcs.iter().map(|r| (r.can_id.as_str(), r.message.as_str())).collect();
+        assert_eq!(rows, [
+            ("0x186", "DTC P0A80: Thermal Runaway Risk, 3.21V"),
+            ("0x18FEF100", "DTC U0100: Lost Communication"),
+        ]);
+        assert!(dtcs.iter().all(|r| r.is_dtc));
+        assert_eq!(dtcs[0].timestamp, "1970-01-01T00:00:02.000Z");
+    }
+
+    #[test]
+    fn query_dtcs_keeps_only_rows_inside_the_range() {
+        let mut conn = blackbox();
+        // 10:00:00 UTC on 2026-01-01, then rows every minute until 10:06.
+        let ten = 1_767_261_600_000;
+        let minute = 60_000;
+        write(&mut conn, (0..=6).map(|i| record(0x186, &format!("DTC P0A80: minute {}", i), ten + i * minute)).collect());
+        write(&mut conn, vec![record(0x186, "Cell Voltage: 3.91V (Optimal)", ten + 2 * minute)]);
+        let messages = |from, to| -> Vec<String> {
+            query_dtcs(&conn, at(from), at(to)).unwrap().into_iter().map(|r| r.message).collect()
+        };
+        assert_eq!(messages(ten + minute, ten + 5 * minute), (1..=5).map(|i| format!("DTC P0A80: minute {}", i)).collect::<Vec<_>>());
+        assert_eq!(messages(ten + minute + 1, ten + 2 * minute - 1), Vec::<String>::new());
+        assert_eq!(messages(ten - minute, ten - 1), Vec::<String>::new());
+    }
+
+    #[test]
+    fn dtc_range_query_searches_the_timestamp_index() {
+        let conn = blackbox();
+        let mut stmt = conn.prepare(&format!("EXPLAIN QUERY PLAN {}", DTC_RANGE_QUERY)).unwrap();
+        let plan: Vec<String> = stmt.query_map(params![0, 1], |row| row.get(3)).unwrap().collect::<rusqlite::Result<_>>().unwrap();
+        assert!(plan.iter().any(|step| step.starts_with("SEARCH sensor_logs USING INDEX idx_timestamp_ms")), "{:?}", plan);
+        assert!(!plan.iter().any(|step| step.starts_with("SCAN sensor_logs")), "{:?}", plan);
+    }
+
+    #[test]
+    fn prune_logs_deletes_only_expired_normal_rows() {
+        let mut conn = blackbox();
+        let now = at(1_767_261_600_000);
+        let days_ago = |days: i64| (now - chrono::Duration::days(days)).timestamp_millis();
+        write(&mut conn, vec![
+            record(0x186, "Cell Voltage: 3.91V (Optimal)", days_ago(10)),
+            record(0x186, "DTC P0A80: Thermal Runaway Risk", days_ago(10)),
+            record(0x186, "Cell Voltage: 3.92V (Optimal)", days_ago(1)),
+            record(0x186, "DTC P0A80: Thermal Runaway Risk", days_ago(1)),
+        ]);
+        let retention = Retention { dtc_days: None, normal_days: Some(7), ..Retention::default() };
+        assert_eq!(prune_logs(&mut conn, now, &retention).unwrap(), 1);
+        let mut stmt = conn.prepare("SELECT message, timestamp_ms FROM sensor_logs ORDER BY id").unwrap();
+        let rows: Vec<(String, i64)> = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap().collect::<rusqlite::Result<_>>().unwrap();
+        assert_eq!(rows, [
+            ("DTC P0A80: Thermal Runaway Risk".to_string(), days_ago(10)),
+            ("Cell Voltage: 3.92V (Optimal)".to_string(), days_ago(1)),
+            ("DTC P0A80: Thermal Runaway Risk".to_string(), days_ago(1)),
+        ]);
+    }
+
+    // Run with `cargo test --release --lib -- --ignored --nocapture write_throughput`; the README quotes its output.
+    #[test]
+    #[ignore]
+    fn write_throughput() {
+        const ROWS: i64 = 5_000;
+        let message = "Cell Voltage: 3.91V (Optimal)";
+        let open = |name: &str| {
+            let path = std::env::temp_dir().join(format!("rustyadv-{}-{}.db", name, std::process::id()));
+            (Connection::open(&path).unwrap(), path)
+        };
+        let one_per_transaction = |conn: &Connection| {
+            let started = Instant::now();
+            for i in 0..ROWS {
+                conn.execute(
+                    "INSERT INTO sensor_logs (sensor_id, message, timestamp_ms) VALUES (?1, ?2, ?3)",
+                    params![CanId::new(0x186).unwrap(), message, i],
+                ).unwrap();
+            }
+            started.elapsed()
+        };
+        // SQLite's defaults (rollback journal, synchronous=FULL), as the blackbox was opened before batching.
+        let (rollback, rollback_path) = open("rollback");
+        upgrade_schema(&rollback).unwrap();
+        let unbatched = one_per_transaction(&rollback);
+        drop(rollback);
+        let (mut conn, path) = open("wal");
+        init_db(&conn).unwrap();
+        let wal = one_per_transaction(&conn);
+        let started = Instant::now();
+        let mut pending: Vec<DbRecord> = (0..ROWS).map(|i| record(0x186, message, i)).collect();
+        while !pending.is_empty() {
+            let rest = pending.split_off(pending.len().min(DB_BATCH_ROWS));
+            write(&mut conn, std::mem::replace(&mut pending, rest));
+        }
+        let batched = started.elapsed();
+        drop(conn);
+        for (path, suffix) in [&rollback_path, &path].into_iter().flat_map(|p| ["", "-journal", "-wal", "-shm"].map(|s| (p, s))) {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+        let report = |what: &str, elapsed: Duration| println!("{:<40} {:>8.1} ms  {:>8.0} rows/s", what, elapsed.as_secs_f64() * 1000.0, ROWS as f64 / elapsed.as_secs_f64());
+        report("one row per transaction, rollback journal", unbatched);
+        report("one row per transaction, WAL", wal);
+        report(&format!("batches of {}, WAL", DB_BATCH_ROWS), batched);
+        assert!(batched < wal && batched < unbatched);
+    }
+
+    #[test]
+    fn console_usage_errors_come_back_as_a_line() {
+        let conn = blackbox();
+        assert_eq!(run_console_command(&conn, "last 0"), ["Error: expected a row count from 1 to 200, got '0'"]);
+        assert_eq!(run_console_command(&conn, "sensor 0xZZ"), ["Error: '0xZZ' is not a hex CAN ID"]);
+        assert_eq!(run_console_command(&conn, "drop table"), ["Error: unknown command or arguments: 'drop' (type help for the list)"]);
+        assert_eq!(run_console_command(&conn, "count all"), ["0 rows recorded"]);
+    }
+
+}
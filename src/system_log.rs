@@ -0,0 +1,40 @@
+//! Forwarding DTCs to the local syslog daemon.
+use std::sync::{Arc, Mutex};
+use syslog::{Facility, Formatter3164, LoggerBackend};
+use crate::lock;
+use crate::dtc::{CanId, FaultState, Severity};
+
+#[derive(Clone)]
+pub struct Logger {
+    inner: Arc<Mutex<syslog::Logger<LoggerBackend, Formatter3164>>>,
+}
+
+impl Logger {
+    pub fn connect(facility: Facility) -> Result<Self, String> {
+        let formatter = Formatter3164 {
+            facility,
+            hostname: None,
+            process: env!("CARGO_PKG_NAME").to_string(),
+            pid: std::process::id(),
+        };
+        let logger = syslog::unix(formatter).map_err(|e| format!("cannot open syslog: {}", e))?;
+        Ok(Self { inner: Arc::new(Mutex::new(logger)) })
+    }
+
+    pub fn log(&self, id: CanId, name: &str, status: &str, severity: Option<Severity>, previous: FaultState, fault: FaultState) {
+        let message = format!("[{}] {}: {} ({})", id, name, status, fault.label());
+        let mut logger = lock(&self.inner);
+        let _ = match (fault, severity) {
+            (FaultState::Confirmed, Some(Severity::Critical)) => logger.crit(message),
+            (FaultState::Confirmed | FaultState::Pending(_), Some(Severity::Info)) => logger.notice(message),
+            (FaultState::Confirmed, _) => logger.err(message),
+            (FaultState::Pending(_), _) => logger.warning(message),
+            (FaultState::Ok, _) if previous != FaultState::Ok => logger.info(message),
+            _ => Ok(()),
+        };
+    }
+}
+
+pub fn parse_facility(s: &str) -> Result<Facility, String> {
+    s.parse().map_err(|_| format!("unknown syslog facility '{}'", s))
+}
@@ -0,0 +1,798 @@
+//! Drawing the dashboard with ratatui and owning the terminal while it is shown.
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+use std::sync::atomic::Ordering;
+use ratatui::{symbols, Frame, Terminal};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+use ratatui::widgets::{Axis, Bar, BarChart, BarGroup, Block, Borders, Chart, Clear, Dataset, Gauge, GraphType, List, ListItem, ListState, Paragraph, Sparkline, Wrap};
+use crossterm::execute;
+use crossterm::event::{DisableMouseCapture, EnableMouseCapture, MouseButton, MouseEvent, MouseEventKind};
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use crate::app_state::{confidence_of, scale_to_sparkline, AppState, BUS_LOAD_WINDOW, LOW_CONFIDENCE, ListArea, LogEntry, LogRecord, PanelLayout, SessionSummary, ThemeName, Tone};
+use crate::dtc::{CanId, DtcCode, FaultState, Severity, SeverityMap};
+use crate::sensors::SentinelComponent;
+use crate::storage::CONSOLE_HELP;
+
+pub const HISTORY_WINDOW_MINUTES: i64 = 10;
+pub const SPARKLINE_HEIGHT: u16 = 7;
+pub const DETAIL_HEIGHT: u16 = 13;
+pub const MIN_SPLIT_WIDTH: u16 = 60;
+pub const BUS_LOAD_HEIGHT: u16 = 5;
+pub const MIN_TERMINAL_WIDTH: u16 = 40;
+pub const MIN_TERMINAL_HEIGHT: u16 = 10;
+pub const IDLE_REDRAW: Duration = Duration::from_secs(1);
+pub const ORANGE: Color = Color::Indexed(208);
+pub const SKY_BLUE: Color = Color::Indexed(39);
+pub const MOUSE_SCROLL_LINES: usize = 3;
+pub const KEYBINDINGS: &[(&str, &str)] = &[
+    ("q", "Quit"),
+    ("Space", "Pause / resume live updates"),
+    ("Up / Down", "Select ECU"),
+    ("Click", "Select the clicked ECU"),
+    ("Wheel", "Scroll the DTC log"),
+    ("PgUp / PgDn", "Scroll the DTC log"),
+    ("Home / End", "Jump to oldest / follow newest"),
+    ("i", "Toggle DTC descriptions and freeze frames"),
+    ("l", "Cycle the panel layout (horizontal / vertical / grid)"),
+    ("s", "Sort the DTC log by severity / arrival time"),
+    ("h", "Toggle DTC history from the blackbox"),
+    ("m", "Bookmark this moment with an optional note"),
+    ("d", "Cycle the selected BMS's detector (sigma / EWMA / dV/dt)"),
+    ("+ / -", "Raise / lower the selected BMS's sigma threshold by 0.1"),
+    ("[ / ]", "Lower / raise the selected BMS's std-dev floor by 0.01 V"),
+    ("r", "Reload thresholds and fault rates from --config"),
+    ("g", "Toggle a histogram of this session's DTCs by code"),
+    ("v", "Toggle a chart of the selected BMS's voltage against time"),
+    ("e", "Export sensor_logs to CSV"),
+    ("o", "Export stored DTCs as OBD-II freeze frames (JSON)"),
+    ("F2", "Save a text snapshot of the dashboard"),
+    ("W", "Save the layout, theme and tuning as defaults in ~/.config/rusty-adas/config.toml"),
+    ("c", "Clear stored DTCs"),
+    ("a", "Acknowledge the selected DTC in the log"),
+    ("p", "Request OBD-II PIDs from the selected ECU"),
+    ("f", "Filter the DTC log by CAN ID (hex, Enter to apply)"),
+    ("b", "Cycle the CAN bus shown (all / each bus)"),
+    ("/", "Search the DTC log (Enter for next match, Esc to close)"),
+    ("Enter", "Disable or re-enable polling of the selected sensor"),
+    (":", "Query the blackbox: count dtc, top-sensor, top-code, last 10, sensor <id>, help"),
+    ("?", "Toggle this help"),
+    ("Esc", "Close this help / clear the log filter"),
+];
+
+pub struct SensorView {
+    pub id: CanId,
+    pub name: String,
+    pub status: String,
+    pub details: Vec<String>,
+    pub firmware: Option<String>,
+    pub trend: Option<(&'static str, Vec<u64>)>,
+}
+
+// Seconds since the dashboard started against volts, for the chart 'v' opens.
+pub struct VoltageChart {
+    pub id: CanId,
+    pub samples: Vec<(f64, f64)>,
+    pub band: Option<(f64, f64)>,
+}
+
+impl VoltageChart {
+    pub fn of(sensor: &dyn SentinelComponent, start: Instant) -> Self {
+        Self {
+            id: sensor.get_id(),
+            samples: sensor.timed_history().iter().map(|&(at, v)| (at.saturating_duration_since(start).as_secs_f64(), v)).collect(),
+            band: sensor.trip_band(),
+        }
+    }
+}
+
+pub struct DashboardAreas {
+    pub status: Rect,
+    pub health: Option<Rect>,
+    pub spark: Option<Rect>,
+    pub bus: Option<Rect>,
+    pub log: Rect,
+    pub detail: Option<Rect>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Theme {
+    pub dtc: Style,
+    pub pending: Style,
+    pub warning: Style,
+    pub ok: Style,
+    pub acknowledged: Style,
+    pub search_match: Style,
+    pub health: [Color; 3],
+    pub sparkline: Color,
+    pub bus_load: Color,
+    pub pid: Color,
+}
+
+impl Theme {
+    pub fn new(name: ThemeName) -> Self {
+        let fg = |color| Style::default().fg(color);
+        match name {
+            ThemeName::Dark => Self {
+                dtc: fg(Color::Red).add_modifier(Modifier::BOLD),
+                pending: fg(Color::Yellow).add_modifier(Modifier::BOLD),
+                warning: fg(Color::Yellow).add_modifier(Modifier::UNDERLINED),
+                ok: fg(Color::Green),
+                acknowledged: fg(Color::DarkGray),
+                search_match: fg(Color::Black).bg(Color::Yellow),
+                health: [Color::Red, Color::Yellow, Color::Green],
+                sparkline: Color::Cyan,
+                bus_load: Color::Magenta,
+                pid: Color::Cyan,
+            },
+            // Yellow and cyan wash out on a white background.
+            ThemeName::Light => Self {
+                dtc: fg(Color::Red).add_modifier(Modifier::BOLD),
+                pending: fg(Color::Magenta).add_modifier(Modifier::BOLD),
+                warning: fg(Color::Magenta).add_modifier(Modifier::UNDERLINED),
+                ok: fg(Color::Blue),
+                acknowledged: fg(Color::Gray),
+                search_match: fg(Color::White).bg(Color::Blue),
+                health: [Color::Red, Color::Magenta, Color::Blue],
+                sparkline: Color::Blue,
+                bus_load: Color::Magenta,
+                pid: Color::Blue,
+            },
+            ThemeName::HighContrast => Self {
+                dtc: fg(Color::White).bg(Color::Red).add_modifier(Modifier::BOLD),
+                pending: fg(Color::Black).bg(Color::Yellow).add_modifier(Modifier::BOLD),
+                warning: fg(Color::LightYellow).add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+                ok: fg(Color::White),
+                acknowledged: fg(Color::Gray).add_modifier(Modifier::DIM),
+                search_match: fg(Color::Black).bg(Color::White),
+                health: [Color::LightRed, Color::LightYellow, Color::White],
+                sparkline: Color::White,
+                bus_load: Color::White,
+                pid: Color::White,
+            },
+            // Blue/orange instead of red/green, and a different modifier per level so hue isn't the only cue.
+            ThemeName::Colorblind => Self {
+                dtc: fg(ORANGE).add_modifier(Modifier::BOLD | Modifier::REVERSED),
+                pending: fg(ORANGE).add_modifier(Modifier::BOLD),
+                warning: fg(ORANGE).add_modifier(Modifier::UNDERLINED),
+                ok: fg(SKY_BLUE),
+                acknowledged: fg(Color::DarkGray).add_modifier(Modifier::ITALIC),
+                search_match: fg(Color::Black).bg(SKY_BLUE),
+                health: [ORANGE, Color::White, SKY_BLUE],
+                sparkline: SKY_BLUE,
+                bus_load: SKY_BLUE,
+                pid: SKY_BLUE,
+            },
+        }
+    }
+
+    // Critical DTCs get the DTC style, warnings the pending one and info the warning one.
+    pub fn severity(&self, msg: &str, severities: &SeverityMap) -> Style {
+        if let Some(severity) = severities.classify(msg) {
+            match severity {
+                Severity::Critical => self.dtc,
+                Severity::Warning => self.pending,
+                Severity::Info => self.warning,
+            }
+        } else if msg.contains("Initializing") || confidence_of(msg).is_some_and(|c| c < LOW_CONFIDENCE) {
+            self.warning
+        } else if msg.contains("Optimal") || msg.contains("Tracking") {
+            self.ok
+        } else {
+            Style::default()
+        }
+    }
+
+    pub fn fault(&self, msg: &str, fault: FaultState, severities: &SeverityMap) -> Style {
+        match fault {
+            FaultState::Pending(_) if msg.contains("DTC") => self.pending,
+            _ => self.severity(msg, severities),
+        }
+    }
+
+    pub fn health(&self, score: u8) -> Color {
+        match score {
+            0..50 => self.health[0],
+            50..80 => self.health[1],
+            _ => self.health[2],
+        }
+    }
+
+    pub fn tone(&self, tone: Tone) -> Style {
+        match tone {
+            Tone::Ok => self.ok,
+            Tone::Warning => self.warning,
+            Tone::Dtc => self.dtc,
+        }
+    }
+}
+
+pub fn split(area: Rect, direction: Direction) -> (Rect, Rect) {
+    let halves = Layout::default()
+        .direction(direction)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+        .split(area);
+    (halves[0], halves[1])
+}
+
+// Stacks `main` above the fixed-height panels, leaving out trailing panels that would squeeze `main` below 3 rows.
+pub fn column(area: Rect, panels: &[u16]) -> (Rect, Vec<Option<Rect>>) {
+    let mut budget = area.height.saturating_sub(3);
+    let fitting = panels.iter().take_while(|&&h| {
+        let fits = h <= budget;
+        budget = budget.saturating_sub(h);
+        fits
+    }).count();
+    let constraints: Vec<Constraint> = std::iter::once(Constraint::Min(3))
+        .chain(panels[..fitting].iter().map(|&h| Constraint::Length(h)))
+        .collect();
+    let rects = Layout::default().direction(Direction::Vertical).constraints(constraints).split(area);
+    let extras = (0..panels.len()).map(|i| rects.get(i + 1).copied()).collect();
+    (rects[0], extras)
+}
+
+pub fn dashboard_areas(layout: PanelLayout, area: Rect, health_rows: u16) -> DashboardAreas {
+    let layout = if area.width < MIN_SPLIT_WIDTH { PanelLayout::Vertical } else { layout };
+    let (health_height, spark_height) = (health_rows + 2, SPARKLINE_HEIGHT);
+    match layout {
+        PanelLayout::Horizontal | PanelLayout::Vertical => {
+            let direction = if layout == PanelLayout::Horizontal { Direction::Horizontal } else { Direction::Vertical };
+            let (first, second) = split(area, direction);
+            let (status, left) = column(first, &[health_height, spark_height, BUS_LOAD_HEIGHT]);
+            let (log, right) = column(second, &[DETAIL_HEIGHT]);
+            DashboardAreas { status, health: left[0], spark: left[1], bus: left[2], log, detail: right[0] }
+        }
+        PanelLayout::Grid => {
+            let (top, bottom) = split(area, Direction::Vertical);
+            let (status, log) = split(top, Direction::Horizontal);
+            let (gauges, detail) = split(bottom, Direction::Horizontal);
+            let (health, charts) = column(gauges, &[spark_height, BUS_LOAD_HEIGHT]);
+            DashboardAreas { status, health: Some(health), spark: charts[0], bus: charts[1], log, detail: Some(detail) }
+        }
+    }
+}
+
+pub fn draw_dashboard(
+    f: &mut Frame,
+    app: &mut AppState,
+    selected: Option<&SensorView>,
+    spark: Option<(CanId, &[f64])>,
+    chart: Option<&VoltageChart>,
+    health: &[(CanId, u8)],
+) {
+    let size = f.size();
+    if size.width < MIN_TERMINAL_WIDTH || size.height < MIN_TERMINAL_HEIGHT {
+        app.status_area = ListArea::default();
+        let message = format!(
+            "Terminal too small ({}x{}, need {}x{})",
+            size.width, size.height, MIN_TERMINAL_WIDTH, MIN_TERMINAL_HEIGHT,
+        );
+        f.render_widget(Paragraph::new(message).wrap(Wrap { trim: true }), size);
+        return;
+    }
+    let theme = Theme::new(app.theme);
+    let areas = dashboard_areas(app.layout, size, health.len() as u16);
+    draw_status_list(f, app, areas.status);
+    if let Some(area) = areas.health {
+        draw_health_panel(f, &theme, health, area);
+    }
+    if let Some(area) = areas.spark {
+        draw_sparkline(f, &theme, spark, area);
+    }
+    if let Some(area) = areas.bus {
+        draw_bus_load(f, app, area);
+    }
+    if app.marker_input.is_some() {
+        draw_log_panel(f, app, areas.log);
+    } else if app.command_input.is_some() || app.console.is_some() {
+        draw_console(f, app, areas.log);
+    } else if app.show_chart {
+        draw_voltage_chart(f, &theme, chart, areas.log);
+    } else if app.show_histogram {
+        draw_histogram(f, &theme, &app.summary, areas.log);
+    } else {
+        match &app.history {
+            Some(records) => draw_history_panel(f, &theme, &app.severities, records, areas.log),
+            None => draw_log_panel(f, app, areas.log),
+        }
+    }
+    if let Some(area) = areas.detail {
+        draw_detail_pane(f, app, selected, area);
+    }
+    if app.show_help {
+        draw_help_overlay(f);
+    }
+}
+
+pub fn centered_rect(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(area.x + (area.width - width) / 2, area.y + (area.height - height) / 2, width, height)
+}
+
+pub fn draw_help_overlay(f: &mut Frame) {
+    let area = f.size();
+    f.buffer_mut().set_style(area, Style::default().add_modifier(Modifier::DIM));
+
+    let key_width = KEYBINDINGS.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+    let lines: Vec<Line> = KEYBINDINGS.iter()
+        .map(|(key, action)| Line::from(format!(" {:<width$}  {}", key, action, width = key_width)))
+        .collect();
+    let width = lines.iter().map(|l| l.width()).max().unwrap_or(0) as u16 + 4;
+    let popup = centered_rect(width, lines.len() as u16 + 2, area);
+
+    f.render_widget(Clear, popup);
+    let help = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title("Keybindings (? or Esc to close)"));
+    f.render_widget(help, popup);
+}
+
+pub fn draw_status_list(f: &mut Frame, app: &mut AppState, area: Rect) {
+    let theme = Theme::new(app.theme);
+    let area = match app.fusion_line() {
+        Some((line, tone)) if area.height > 3 => {
+            let rows = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(1), Constraint::Min(0)])
+                .split(area);
+            f.render_widget(Paragraph::new(line).style(theme.tone(tone)), rows[0]);
+            rows[1]
+        }
+        _ => area,
+    };
+    let status_items: Vec<ListItem> = app.sensor_status.iter()
+        .map(|(id, msg)| {
+            let fault = app.fault_states.get(id).copied().unwrap_or(FaultState::Ok);
+            let style = match app.log_filter {
+                Some(filter) if filter == *id => theme.fault(msg, fault, &app.severities).add_modifier(Modifier::UNDERLINED),
+                Some(_) => theme.fault(msg, fault, &app.severities).add_modifier(Modifier::DIM),
+                None => theme.fault(msg, fault, &app.severities),
+            };
+            let style = if app.on_filtered_bus(*id) { style } else { style.add_modifier(Modifier::DIM) };
+            let prefix = format!("{}{}", app.bus_prefix(*id), app.kind_prefix(*id));
+            match app.is_enabled(*id) {
+                true => ListItem::new(format!("{}CAN ID {}: {}", prefix, app.label(*id), msg)).style(style),
+                false => ListItem::new(format!("{}CAN ID {}: [DISABLED] {}", prefix, app.label(*id), msg)).style(style.add_modifier(Modifier::DIM)),
+            }
+        })
+        .collect();
+
+    let mut status_title = format!("ECU Network Status — up {} — {} DTC/min", app.uptime(), app.dtcs_last_minute());
+    if let Some(bus) = app.bus_filter {
+        status_title.push_str(&format!(" — bus {}", bus));
+    }
+    if let Some(position) = app.position {
+        status_title.push_str(&format!(" — {}", position));
+    }
+    if app.paused {
+        status_title.push_str(" [PAUSED]");
+    }
+    if app.replay_complete {
+        status_title.push_str(" [REPLAY COMPLETE]");
+    }
+    let dropped = app.db_dropped.load(Ordering::Relaxed);
+    if dropped > 0 {
+        status_title.push_str(&format!(" [{} DB WRITES DROPPED]", dropped));
+    }
+    let status_list = List::new(status_items)
+        .block(Block::default().borders(Borders::ALL).title(status_title))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol("> ");
+    let mut state = ListState::default().with_selected(Some(app.selected));
+    f.render_stateful_widget(status_list, area, &mut state);
+    app.status_area = ListArea { x: area.x, y: area.y, width: area.width, height: area.height };
+    app.status_offset = state.offset();
+}
+
+pub fn draw_health_panel(f: &mut Frame, theme: &Theme, health: &[(CanId, u8)], area: Rect) {
+    let block = Block::default().borders(Borders::ALL).title("ECU Health (0-100)");
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(vec![Constraint::Length(1); health.len()])
+        .split(inner);
+    for (&(id, score), row) in health.iter().zip(rows.iter()) {
+        let gauge = Gauge::default()
+            .gauge_style(Style::default().fg(theme.health(score)))
+            .percent(score as u16)
+            .label(format!("{}: {}", id, score));
+        f.render_widget(gauge, *row);
+    }
+}
+
+pub fn draw_sparkline(f: &mut Frame, theme: &Theme, spark: Option<(CanId, &[f64])>, area: Rect) {
+    let history = spark.map_or(&[][..], |(_, h)| h);
+    let title = match (spark, history.last()) {
+        (Some((id, _)), Some(last)) => {
+            let min = history.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = history.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            format!("Cell Voltage {} [{:.2}V, min {:.2} max {:.2}]", id, last, min, max)
+        }
+        (Some((id, _)), None) => format!("Cell Voltage {} (no samples)", id),
+        (None, _) => "Cell Voltage".to_string(),
+    };
+    let data = scale_to_sparkline(history);
+    let sparkline = Sparkline::default()
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .data(&data)
+        .max(100)
+        .style(Style::default().fg(theme.sparkline));
+    f.render_widget(sparkline, area);
+}
+
+// With a bus filter the chart is that bus alone; otherwise it is every bus together, with the
+// per-bus rates in the title when there is more than one.
+pub fn draw_bus_load(f: &mut Frame, app: &mut AppState, area: Rect) {
+    let theme = Theme::new(app.theme);
+    let second = app.start.elapsed().as_secs();
+    app.bus_load.advance(second);
+    for load in app.bus_loads.values_mut() {
+        load.advance(second);
+    }
+    let (load, label) = match app.bus_filter {
+        Some(bus) => (app.bus_loads.get(&bus), format!("Bus {} load", bus)),
+        None => (Some(&app.bus_load), "Bus load".to_string()),
+    };
+    let history = load.map_or_else(Vec::new, |load| load.completed());
+    let current = load.map_or(0, |load| load.frames_per_second());
+    let visible = area.width.saturating_sub(2) as usize;
+    let data = &history[history.len().saturating_sub(visible)..];
+    let peak = history.iter().copied().max().unwrap_or(0);
+    let mut title = format!("{}: {} frames/s (peak {} over {} s)", label, current, peak, BUS_LOAD_WINDOW);
+    if app.bus_filter.is_none() && app.multi_bus() {
+        let per_bus: Vec<String> = app.bus_loads.iter()
+            .map(|(bus, load)| format!("B{} {}", bus, load.frames_per_second()))
+            .collect();
+        title.push_str(&format!(" — {}", per_bus.join(" / ")));
+    }
+    let sparkline = Sparkline::default()
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .data(data)
+        .max(peak.max(1))
+        .style(Style::default().fg(theme.bus_load));
+    f.render_widget(sparkline, area);
+}
+
+pub fn highlight_matches(text: &str, query: &str, style: Style) -> Line<'static> {
+    if query.is_empty() { return Line::from(text.to_string()); }
+    let lower = text.to_ascii_lowercase();
+    let query = query.to_ascii_lowercase();
+    let mut spans = Vec::new();
+    let mut rest = 0;
+    for (start, _) in lower.match_indices(&query) {
+        if start < rest { continue; }
+        spans.push(Span::raw(text[rest..start].to_string()));
+        spans.push(Span::styled(text[start..start + query.len()].to_string(), style));
+        rest = start + query.len();
+    }
+    spans.push(Span::raw(text[rest..].to_string()));
+    Line::from(spans)
+}
+
+pub fn draw_log_panel(f: &mut Frame, app: &mut AppState, area: Rect) {
+    let theme = Theme::new(app.theme);
+    let lines_per_entry = if app.show_details { 3 } else { 1 };
+    app.log_page = (area.height.saturating_sub(2) / lines_per_entry).max(1) as usize;
+    app.log_scroll = app.log_scroll.min(app.max_log_scroll());
+    let visible: Vec<&LogEntry> = app.visible_logs().collect();
+    let offset = visible.len().saturating_sub(app.log_scroll + app.log_page);
+
+    let log_items: Vec<ListItem> = visible.iter().enumerate()
+        .map(|(i, entry)| {
+            let mut text = Text::from(highlight_matches(&entry.display_text(), &app.search_query, theme.search_match));
+            if app.show_details {
+                let detail = DtcCode::parse(&entry.text).map_or("", |code| code.description());
+                text.lines.push(Line::from(format!("    {}", detail)));
+                let frame = entry.freeze.as_ref().map_or(String::new(), |f| f.summary());
+                text.lines.push(Line::from(format!("    {}", frame)));
+            }
+            let style = match entry.acknowledged {
+                true => theme.acknowledged,
+                false => theme.fault(&entry.text, entry.fault, &app.severities),
+            };
+            let style = if app.search_match == Some(i) { style.add_modifier(Modifier::REVERSED) } else { style };
+            ListItem::new(text).style(style)
+        })
+        .collect();
+
+    let mut log_title = "OBD-II Diagnostic Trouble Codes (DTC)".to_string();
+    let unacknowledged = app.unacknowledged_dtcs();
+    if unacknowledged > 0 {
+        log_title.push_str(&format!(" [{} UNACKNOWLEDGED | a to ack]", unacknowledged));
+    }
+    if app.sort_by_severity {
+        log_title.push_str(" [BY SEVERITY | s for time]");
+    }
+    if let Some(id) = app.log_filter {
+        log_title.push_str(&format!(" [CAN ID {} | Esc to clear]", app.label(id)));
+    }
+    if app.log_scroll > 0 {
+        log_title.push_str(&format!(" [-{} | End to follow]", app.log_scroll));
+    }
+    if let Some((_, note)) = &app.marker_input {
+        log_title = format!("Marker note: {}_ (Enter to save, Esc to cancel)", note);
+    } else if let Some(input) = &app.filter_input {
+        log_title = format!("Filter by CAN ID: {}_ (Enter to apply, Esc to cancel)", input);
+    } else if app.searching {
+        let matches = app.search_matches().len();
+        log_title = format!("Search: {}_ ({} matches, Enter for next, Esc to close)", app.search_query, matches);
+    }
+    let log_list = List::new(log_items)
+        .block(Block::default().borders(Borders::ALL).title(log_title));
+    let mut log_state = ListState::default().with_offset(offset);
+    f.render_stateful_widget(log_list, area, &mut log_state);
+}
+
+pub fn draw_histogram(f: &mut Frame, theme: &Theme, summary: &SessionSummary, area: Rect) {
+    let mut counts: Vec<(DtcCode, usize)> = summary.per_code.iter().map(|(&code, &n)| (code, n)).collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.as_str().cmp(b.0.as_str())));
+    let block = Block::default().borders(Borders::ALL).title(format!(
+        "DTCs by code this session ({} total, g to close)",
+        counts.iter().map(|(_, n)| n).sum::<usize>(),
+    ));
+    if counts.is_empty() {
+        f.render_widget(Paragraph::new("No DTCs this session").block(block), area);
+        return;
+    }
+    let bars: Vec<Bar> = counts.iter()
+        .map(|(code, n)| Bar::default()
+            .label(Line::from(code.as_str()))
+            .value(*n as u64)
+            .style(theme.dtc))
+        .collect();
+    let chart = BarChart::default()
+        .block(block)
+        .direction(Direction::Horizontal)
+        .bar_width(1)
+        .bar_gap(0)
+        .value_style(theme.dtc.add_modifier(Modifier::REVERSED))
+        .data(BarGroup::default().bars(&bars));
+    f.render_widget(chart, area);
+}
+
+pub fn draw_voltage_chart(f: &mut Frame, theme: &Theme, chart: Option<&VoltageChart>, area: Rect) {
+    let Some(chart) = chart.filter(|c| !c.samples.is_empty()) else {
+        let block = Block::default().borders(Borders::ALL).title("Cell Voltage vs Time (v to close)");
+        f.render_widget(Paragraph::new("No BMS voltage samples yet").block(block), area);
+        return;
+    };
+    let x_min = chart.samples[0].0;
+    let x_max = chart.samples[chart.samples.len() - 1].0.max(x_min + 1.0);
+    let volts = chart.samples.iter().map(|&(_, v)| v).chain(chart.band.into_iter().flat_map(|(low, high)| [low, high]));
+    let (y_min, y_max) = volts.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)));
+    let pad = ((y_max - y_min) * 0.1).max(0.05);
+    let (y_min, y_max) = (y_min - pad, y_max + pad);
+    let edges: Vec<[(f64, f64); 2]> = chart.band.into_iter()
+        .flat_map(|(low, high)| [[(x_min, low), (x_max, low)], [(x_min, high), (x_max, high)]])
+        .collect();
+
+    let mut datasets = vec![
+        Dataset::default()
+            .name("cell voltage")
+            .marker(symbols::Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(theme.sparkline))
+            .data(&chart.samples),
+    ];
+    for (edge, name) in edges.iter().zip(["DTC below", "DTC above"]) {
+        datasets.push(Dataset::default()
+            .name(format!("{} {:.2}V", name, edge[0].1))
+            .marker(symbols::Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(theme.dtc)
+            .data(edge));
+    }
+    let labels = |lo: f64, hi: f64, unit: &str| -> Vec<Span> {
+        [lo, (lo + hi) / 2.0, hi].iter().map(|v| Span::raw(format!("{:.2}{}", v, unit))).collect()
+    };
+    let widget = Chart::new(datasets)
+        .block(Block::default().borders(Borders::ALL).title(format!("Cell Voltage {} vs Time (v to close)", chart.id)))
+        .x_axis(Axis::default().title("elapsed").bounds([x_min, x_max]).labels(labels(x_min, x_max, "s")))
+        .y_axis(Axis::default().title("volts").bounds([y_min, y_max]).labels(labels(y_min, y_max, "V")));
+    f.render_widget(widget, area);
+}
+
+pub fn draw_console(f: &mut Frame, app: &AppState, area: Rect) {
+    let (title, lines) = match (&app.command_input, &app.console) {
+        (Some(input), _) => (format!("Query: {}_ (Enter to run, Esc to cancel)", input), CONSOLE_HELP.iter().map(|l| l.to_string()).collect()),
+        (None, Some((command, output))) => (format!("Query: {} ({} lines, : for another, Esc to close)", command, output.len()), output.clone()),
+        (None, None) => return,
+    };
+    let visible = area.height.saturating_sub(2) as usize;
+    let lines: Vec<Line> = lines.iter().skip(lines.len().saturating_sub(visible)).map(|l| Line::from(l.clone())).collect();
+    f.render_widget(Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)), area);
+}
+
+pub fn draw_history_panel(f: &mut Frame, theme: &Theme, severities: &SeverityMap, records: &[LogRecord], area: Rect) {
+    let visible = area.height.saturating_sub(2) as usize;
+    let items: Vec<ListItem> = records.iter()
+        .skip(records.len().saturating_sub(visible))
+        .map(|r| ListItem::new(format!("{} [CAN ID {}] {}", r.timestamp, r.can_id, r.message)).style(theme.severity(&r.message, severities)))
+        .collect();
+    let title = format!("DTC History: last {} min from blackbox ({} faults, h to close)", HISTORY_WINDOW_MINUTES, records.len());
+    f.render_widget(List::new(items).block(Block::default().borders(Borders::ALL).title(title)), area);
+}
+
+pub fn draw_detail_pane(f: &mut Frame, app: &AppState, selected: Option<&SensorView>, area: Rect) {
+    let theme = Theme::new(app.theme);
+    let mut lines = Vec::new();
+    if let Some(view) = selected {
+        let stats = app.sensor_stats.get(&view.id);
+        let fault = app.fault_states.get(&view.id).copied().unwrap_or(FaultState::Ok);
+        lines.push(Line::from(format!("CAN ID: {}   Module: {}", view.id, view.name)));
+        if let Some(firmware) = &view.firmware {
+            lines.push(Line::from(format!("Firmware: {}", firmware)));
+        }
+        lines.push(Line::styled(format!("Status: {}", view.status), theme.fault(&view.status, fault, &app.severities)));
+        lines.push(Line::from(format!("DTCs this session: {}   Fault state: {}", stats.map_or(0, |s| s.dtc_count), fault.label())));
+        lines.push(Line::from(format!(
+            "Arbitration losses: {}   Last delay: {:?}",
+            app.arbitration_losses.get(&view.id).copied().unwrap_or(0),
+            app.arbitration_delay.get(&view.id).copied().unwrap_or_default(),
+        )));
+        if let Some((id, values)) = &app.pid_response && *id == view.id {
+            if values.is_empty() {
+                lines.push(Line::from("PID scan: no supported PIDs answered"));
+            }
+            lines.extend(values.iter().map(|v| Line::styled(v.label(), Style::default().fg(theme.pid))));
+        }
+        if let Some(code) = stats.and_then(|s| s.last_dtc) {
+            lines.push(Line::from(format!("Last DTC: {} - {}", code.as_str(), code.description())));
+        }
+        lines.extend(view.details.iter().map(|d| Line::from(d.clone())));
+    }
+    let block = Block::default().borders(Borders::ALL).title("ECU Detail (Up/Down to select)");
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+    let trend = selected.and_then(|view| view.trend.as_ref()).filter(|_| inner.height > 4);
+    let [text_area, trend_area] = match trend {
+        Some(_) => Layout::vertical([Constraint::Min(0), Constraint::Length(3)]).areas(inner),
+        None => [inner, Rect::default()],
+    };
+    f.render_widget(Paragraph::new(lines), text_area);
+    if let Some((label, data)) = trend {
+        let min = data.iter().min().map_or(String::new(), |m| format!(", min {}%", m));
+        let [label_area, spark_area] = Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(trend_area);
+        f.render_widget(Paragraph::new(format!("{} (last {} polls{})", label, data.len(), min)), label_area);
+        f.render_widget(Sparkline::default().data(data).max(100).style(Style::default().fg(theme.sparkline)), spark_area);
+    }
+}
+
+pub type Tui = Terminal<CrosstermBackend<io::Stdout>>;
+
+pub fn handle_mouse(app: &mut AppState, mouse: MouseEvent) {
+    match mouse.kind {
+        MouseEventKind::Down(MouseButton::Left) => app.click(mouse.column, mouse.row),
+        MouseEventKind::ScrollUp => app.scroll_logs_up(MOUSE_SCROLL_LINES),
+        MouseEventKind::ScrollDown => app.scroll_logs_down(MOUSE_SCROLL_LINES),
+        _ => {}
+    }
+}
+
+pub fn restore_terminal() -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture, crossterm::cursor::Show)
+}
+
+// Restores the terminal when dropped, so an early `?` return or a panic in the UI thread
+// doesn't leave the shell in raw mode on the alternate screen.
+pub struct TerminalGuard;
+
+impl TerminalGuard {
+    pub fn enter() -> io::Result<Self> {
+        static PANIC_HOOK: std::sync::Once = std::sync::Once::new();
+        PANIC_HOOK.call_once(|| {
+            let previous = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                // Restore before the message is printed, otherwise it lands on the alternate screen.
+                // Sensor threads leave the dashboard alone; their panics don't end the UI.
+                if thread::current().name() == Some("main") && crossterm::terminal::is_raw_mode_enabled().unwrap_or(false) {
+                    let _ = restore_terminal();
+                }
+                previous(info);
+            }));
+        });
+        enable_raw_mode()?;
+        let guard = TerminalGuard;
+        execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture)?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = restore_terminal();
+    }
+}
+
+pub fn enter_tui() -> io::Result<(TerminalGuard, Tui)> {
+    let guard = TerminalGuard::enter()?;
+    Ok((guard, Terminal::new(CrosstermBackend::new(io::stdout()))?))
+}
+
+// Sensor updates are drawn at most once per frame interval, and an idle dashboard only every
+// IDLE_REDRAW so the uptime clock and bus load keep moving. Input is drawn straight away.
+pub struct FramePacer {
+    pub interval: Duration,
+    pub last: Option<Instant>,
+}
+
+impl FramePacer {
+    pub fn new(interval: Duration) -> Self {
+        Self { interval, last: None }
+    }
+
+    pub fn should_draw(&mut self, dirty: bool, now: Instant) -> bool {
+        let due = self.last.is_none_or(|last| {
+            let since = now.duration_since(last);
+            (dirty && since >= self.interval) || since >= IDLE_REDRAW
+        });
+        if due {
+            self.last = Some(now);
+        }
+        due
+    }
+
+    pub fn redraw_now(&mut self) {
+        self.last = None;
+    }
+}
+
+pub fn draw_frame(terminal: &mut Tui, app: &mut AppState, sensors: &[Box<dyn SentinelComponent>]) -> io::Result<()> {
+    let selected = app.sensor_status.get(app.selected).map(|(id, status)| {
+        let sensor = sensors.iter().find(|s| s.get_id() == *id);
+        SensorView {
+            id: *id,
+            name: sensor.map_or_else(|| "(recorded)".to_string(), |s| s.name()),
+            status: status.clone(),
+            details: sensor.map(|s| s.describe()).unwrap_or_default(),
+            firmware: sensor.map(|s| s.firmware_version()),
+            trend: sensor.and_then(|s| s.trend()),
+        }
+    });
+    if let Some(pos) = sensors.iter().position(|s| Some(s.get_id()) == app.selected_id())
+        && !sensors[pos].history_snapshot().is_empty()
+    {
+        app.spark_sensor = pos;
+    }
+    let spark = sensors.get(app.spark_sensor).map(|s| (s.get_id(), s.history_snapshot()));
+    // The selected ECU if it is a BMS, otherwise the first BMS.
+    let chart = app.show_chart.then(|| {
+        let selected = sensors.iter().find(|s| Some(s.get_id()) == app.selected_id() && s.kind() == "BMS");
+        selected.or_else(|| sensors.iter().find(|s| s.kind() == "BMS")).map(|s| VoltageChart::of(s.as_ref(), app.start))
+    }).flatten();
+    let health: Vec<(CanId, u8)> = sensors.iter().map(|s| (s.get_id(), s.health_score())).collect();
+
+    terminal.draw(|f| {
+        draw_dashboard(f, app, selected.as_ref(), spark.as_ref().map(|(id, h)| (*id, h.as_slice())), chart.as_ref(), &health);
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NO_RESPONSE;
+
+    #[test]
+    fn healthy_tracking_lines_are_ok_and_only_a_degraded_one_warns() {
+        let severities = SeverityMap::default();
+        for name in [ThemeName::Dark, ThemeName::Light, ThemeName::HighContrast, ThemeName::Colorblind] {
+            let theme = Theme::new(name);
+            for confidence in 95..100 {
+                let line = format!("Tracking [Front_Radar]: Confidence {}%", confidence);
+                assert_eq!(theme.severity(&line, &severities), theme.ok, "{:?} {}", name, line);
+            }
+            assert_eq!(theme.severity("Tracking [Front_Radar]: Confidence 89%", &severities), theme.warning);
+            assert_eq!(theme.severity("Initializing... (3s)", &severities), theme.warning);
+            assert_eq!(theme.severity("DTC P0A80: Thermal Runaway Risk", &severities), theme.dtc);
+            assert_eq!(theme.severity(NO_RESPONSE, &severities), theme.dtc);
+            assert_eq!(theme.severity("Cell Voltage: 3.91V (Optimal)", &severities), theme.ok);
+            assert_eq!(theme.severity("Position 52.52N 13.40E", &severities), Style::default());
+        }
+    }
+}
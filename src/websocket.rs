@@ -0,0 +1,94 @@
+//! Broadcasting live sensor status to WebSocket clients. Only sensor status goes out: the dashboard's
+//! own notices and the end-of-replay marker are not sensor state, and replays are not broadcast.
+use std::io;
+use std::collections::BTreeMap;
+use std::thread;
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::net::{SocketAddr, TcpListener};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use tungstenite::Message;
+use crate::{lock, SHUTDOWN_POLL};
+use crate::dtc::{CanId, FaultState};
+use crate::sensors::Position;
+
+const CLIENT_QUEUE: usize = 256;
+
+#[derive(Serialize)]
+struct StatusRecord<'a> {
+    can_id: String,
+    status: &'a str,
+    is_dtc: bool,
+    fault: String,
+    timestamp: String,
+    #[serde(flatten)]
+    position: Option<Position>,
+}
+
+#[derive(Default)]
+struct Clients {
+    latest: BTreeMap<CanId, serde_json::Value>,
+    queues: Vec<mpsc::SyncSender<Arc<str>>>,
+}
+
+#[derive(Clone)]
+pub struct Publisher {
+    clients: Arc<Mutex<Clients>>,
+}
+
+impl Publisher {
+    pub fn publish(&self, id: CanId, status: &str, is_dtc: bool, fault: FaultState, at: DateTime<Utc>, position: Option<Position>) {
+        let record = StatusRecord {
+            can_id: format!("{}", id),
+            status,
+            is_dtc,
+            fault: fault.label(),
+            timestamp: at.to_rfc3339_opts(SecondsFormat::Millis, true),
+            position,
+        };
+        let Ok(record) = serde_json::to_value(&record) else { return };
+        let message: Arc<str> = serde_json::json!({ "type": "status", "sensor": record }).to_string().into();
+        let mut clients = lock(&self.clients);
+        clients.latest.insert(id, record);
+        clients.queues.retain(|client| !matches!(client.try_send(Arc::clone(&message)), Err(mpsc::TrySendError::Disconnected(_))));
+    }
+
+    // Registered under the same lock as publish, so the snapshot and the live stream neither overlap nor leave a gap.
+    fn subscribe(&self) -> mpsc::Receiver<Arc<str>> {
+        let (tx, rx) = mpsc::sync_channel(CLIENT_QUEUE);
+        let mut clients = lock(&self.clients);
+        let sensors: Vec<&serde_json::Value> = clients.latest.values().collect();
+        let snapshot = serde_json::json!({ "type": "snapshot", "sensors": sensors }).to_string();
+        let _ = tx.try_send(snapshot.into());
+        clients.queues.push(tx);
+        rx
+    }
+}
+
+pub fn serve(addr: SocketAddr, shutdown: Arc<AtomicBool>) -> io::Result<(Publisher, thread::JoinHandle<()>)> {
+    let listener = TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    let publisher = Publisher { clients: Arc::default() };
+    let acceptor = publisher.clone();
+    let handle = thread::spawn(move || {
+        while !shutdown.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    if stream.set_nonblocking(false).is_err() { continue; }
+                    let messages = acceptor.subscribe();
+                    thread::spawn(move || {
+                        let Ok(mut socket) = tungstenite::accept(stream) else { return };
+                        for message in messages {
+                            if socket.send(Message::text(message.as_ref())).is_err() { break; }
+                        }
+                        let _ = socket.close(None);
+                    });
+                }
+                Err(_) => thread::sleep(SHUTDOWN_POLL),
+            }
+        }
+        lock(&acceptor.clients).queues.clear();
+    });
+    Ok((publisher, handle))
+}